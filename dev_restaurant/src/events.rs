@@ -0,0 +1,190 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant}
+};
+use serde::Serialize;
+use tungstenite::{protocol::Role, Message, WebSocket};
+
+use crate::{Restaurant, Sale};
+use crate::i18n::tr;
+use crate::order::Order;
+
+// What happened, the kitchen display shows orders as tickets and refunds and stock-outs as notices
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind{
+    Order,
+    Refund,
+    StockOut
+}
+
+// An item and how many of it, the same values buy is given
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EventLine{
+    pub item_id: i64,
+    pub name: String,
+    pub quantity: i64
+}
+
+// One entry of the kitchen feed. Refunds carry the order they take back from, stock-outs no order at all
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Event{
+    pub kind: EventKind,
+    pub timestamp: String,
+    pub order_id: Option<i64>,
+    pub order_number: Option<i64>,
+    pub lines: Vec<EventLine>
+}
+
+impl Restaurant{
+    // The ticket of a new order, followed by a stock-out for every item the order used up
+    pub fn order_events(&self, order: &Order, sales: &[Sale]) -> Vec<Event>{
+        let mut events = vec![Event{
+            kind: EventKind::Order,
+            timestamp: order.timestamp.clone(),
+            order_id: Some(order.id),
+            order_number: Some(order.number),
+            lines: sales.iter().map(|sale| EventLine{ item_id: sale.item_id, name: sale.item_name.clone(), quantity: sale.quantity }).collect()
+        }];
+
+        // Items cooked from a recipe run out when their ingredients no longer make a portion
+        let portions = self.recipes.portions(&self.ingredients);
+        for sale in sales{
+            let Some(item) = self.items.list.get(&sale.item_id) else { continue };
+            if portions.get(&item.id).copied().unwrap_or(item.quantity) <= 0{
+                events.push(Event{
+                    kind: EventKind::StockOut,
+                    timestamp: order.timestamp.clone(),
+                    order_id: None,
+                    order_number: None,
+                    lines: vec![EventLine{ item_id: item.id, name: item.name.clone(), quantity: 0 }]
+                });
+            }
+        }
+        events
+    }
+
+    // One refund per order the refund lines take back from, with the quantities given back
+    pub fn refund_events(&self, refunds: &[Sale]) -> Vec<Event>{
+        let mut events: Vec<Event> = Vec::new();
+        for refund in refunds{
+            let line = EventLine{ item_id: refund.item_id, name: refund.item_name.clone(), quantity: -refund.quantity };
+            match events.iter_mut().find(|event| event.order_id == Some(refund.order_id)){
+                Some(event) => event.lines.push(line),
+                None => events.push(Event{
+                    kind: EventKind::Refund,
+                    timestamp: refund.timestamp.clone(),
+                    order_id: Some(refund.order_id),
+                    order_number: self.orders.find(refund.order_id).map(|order| order.number),
+                    lines: vec![line]
+                }),
+            }
+        }
+        events
+    }
+}
+
+// Appends events to the feed file, one JSON object per line. Every process appends to the same file,
+// so sales made from the command line reach the kitchen as well as the ones made through the server
+pub fn publish(path: &Path, events: &[Event]) -> io::Result<()>{
+    if events.is_empty(){
+        return Ok(());
+    }
+    let mut lines = String::new();
+    for event in events{
+        lines.push_str(&serde_json::to_string(event)?);
+        lines.push('\n');
+    }
+    OpenOptions::new().create(true).append(true).open(path)?.write_all(lines.as_bytes())
+}
+
+// For changes that are saved already: a feed that cannot be written is only reported, it must not turn the change into a failure
+pub fn publish_saved(path: &Path, events: &[Event]) -> Result<(), String>{
+    publish(path, events).map_err(|e| tr!("Gagal menulis feed dapur {}: {}", "Could not write the kitchen feed {}: {}", path.display(), e))
+}
+
+const POLL: Duration = Duration::from_millis(250);
+// A ping now and then notices displays that went away without closing
+const PING: Duration = Duration::from_secs(15);
+
+// Sends every event appended to the feed file after the display connected, until the display goes away
+pub fn feed<S: Read + Write>(stream: S, path: PathBuf){
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    let mut offset = fs::metadata(&path).map_or(0, |metadata| metadata.len());
+    let mut pinged = Instant::now();
+    loop{
+        thread::sleep(POLL);
+        let lines = match read_new_lines(&path, &mut offset){
+            Ok(lines) => lines,
+            Err(_) => continue,
+        };
+        for line in lines{
+            if socket.send(Message::Text(line)).is_err(){
+                return;
+            }
+        }
+        if pinged.elapsed() >= PING{
+            if socket.send(Message::Ping(Vec::new())).is_err(){
+                return;
+            }
+            pinged = Instant::now();
+        }
+    }
+}
+
+// Whole lines written since `offset`, a line still being written is left for the next poll
+fn read_new_lines(path: &Path, offset: &mut u64) -> io::Result<Vec<String>>{
+    let mut file = File::open(path)?;
+    // Start over when the file was emptied or replaced
+    if file.metadata()?.len() < *offset{
+        *offset = 0;
+    }
+    file.seek(SeekFrom::Start(*offset))?;
+    let mut buffer = String::new();
+    file.read_to_string(&mut buffer)?;
+
+    let complete = buffer.rfind('\n').map_or(0, |end| end + 1);
+    *offset += complete as u64;
+    Ok(buffer[..complete].lines().filter(|line| !line.trim().is_empty()).map(str::to_string).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Restaurant;
+    use crate::money::rp;
+    use crate::order::OrderLine;
+    use super::{read_new_lines, EventKind};
+
+    #[test]
+    fn events_test(){
+        let mut data = Restaurant::new();
+        data.items.add("Risoles", 2, rp(5000), 0, false);
+        data.items.add("Es Teh", 10, rp(3000), 0, false);
+
+        let lines = [OrderLine{ name: "risoles".to_string(), quantity: 2 }, OrderLine{ name: "es teh".to_string(), quantity: 1 }];
+        let (order, sales) = data.order(&lines, None, "2022-11-22 12:00:00").unwrap();
+        let events = data.order_events(&order, &sales);
+        let kinds: Vec<_> = events.iter().map(|event| event.kind).collect();
+        assert_eq!(kinds, vec![EventKind::Order, EventKind::StockOut]);
+        assert_eq!((events[0].order_number, events[0].lines.len()), (Some(1), 2));
+        assert_eq!(events[1].lines[0].name, "risoles");
+
+        let refunds = data.refund_today("risoles", 1, "", "2022-11-22 12:30:00").unwrap();
+        let events = data.refund_events(&refunds);
+        assert_eq!((events[0].kind, events[0].order_id, events[0].lines[0].quantity), (EventKind::Refund, Some(order.id), 1));
+    }
+
+    #[test]
+    fn read_new_lines_test(){
+        let path = std::env::temp_dir().join(format!("dev_restaurant_events_{}.ndjson", std::process::id()));
+        std::fs::write(&path, "{\"a\":1}\n{\"b\"").unwrap();
+        let mut offset = 0;
+        assert_eq!(read_new_lines(&path, &mut offset).unwrap(), vec!["{\"a\":1}"]);
+        std::fs::write(&path, "{\"a\":1}\n{\"b\":2}\n").unwrap();
+        assert_eq!(read_new_lines(&path, &mut offset).unwrap(), vec!["{\"b\":2}"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
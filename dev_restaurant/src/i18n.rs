@@ -0,0 +1,84 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+// Language of the messages printed for staff, amounts follow the locale instead
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Lang{
+    #[default]
+    Indonesian,
+    English
+}
+
+// The message catalogue: every text shown to staff is written as tr!("indonesian", "english", args..),
+// so both versions sit next to each other and their arguments are checked by format!
+macro_rules! tr{
+    ($id:literal, $en:literal $(, $arg:expr)* $(,)?) => {
+        match $crate::i18n::lang(){
+            $crate::i18n::Lang::Indonesian => format!($id $(, $arg)*),
+            $crate::i18n::Lang::English => format!($en $(, $arg)*),
+        }
+    };
+}
+pub(crate) use tr;
+
+impl FromStr for Lang{
+    type Err = String;
+
+    // Accepts id, en and locale style tags like id-ID or en_US.UTF-8
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        let tag = s.split(['.', '-', '_']).next().unwrap_or(s).to_lowercase();
+        match tag.as_str(){
+            "id" => Ok(Lang::Indonesian),
+            "en" => Ok(Lang::English),
+            _ => Err(tr!("bahasa \"{}\" tidak dikenal, seharusnya id atau en", "unknown language \"{}\", expected id or en", s)),
+        }
+    }
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+// Chosen once at startup, until then messages are in Indonesian
+pub fn set_lang(lang: Lang){
+    let _ = LANG.set(lang);
+}
+
+pub fn lang() -> Lang{
+    LANG.get().copied().unwrap_or_default()
+}
+
+// The language asked for by --lang or DEV_RESTAURANT_LANG, read before the other arguments
+// so the errors parsing them are already in that language
+pub fn requested_lang(args: impl IntoIterator<Item = String>) -> Lang{
+    let mut args = args.into_iter();
+    let mut value = None;
+    while let Some(arg) = args.next(){
+        if arg == "--lang"{
+            value = args.next();
+        } else if let Some(tag) = arg.strip_prefix("--lang="){
+            value = Some(tag.to_string());
+        }
+    }
+    value.or_else(|| std::env::var("DEV_RESTAURANT_LANG").ok())
+        .and_then(|tag| tag.parse().ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ItemError;
+    use super::{requested_lang, Lang};
+
+    #[test]
+    fn lang_test(){
+        assert_eq!("en_US.UTF-8".parse::<Lang>(), Ok(Lang::English));
+        assert_eq!("id".parse::<Lang>(), Ok(Lang::Indonesian));
+        assert!("fr".parse::<Lang>().is_err());
+        let args = |line: &str| line.split(' ').map(String::from).collect::<Vec<_>>();
+        assert_eq!(requested_lang(args("dev_restaurant --lang en list")), Lang::English);
+        assert_eq!(requested_lang(args("dev_restaurant --lang=en-US report --period hari")), Lang::English);
+
+        // Tests never set a language, so messages stay in Indonesian
+        assert_eq!(ItemError::NotEnoughStock(2, 5).to_string(), "stok tidak mencukupi, tersedia 2 dari 5");
+        assert_eq!(tr!("{} porsi", "{} portions", 3), "3 porsi");
+    }
+}
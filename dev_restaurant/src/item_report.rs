@@ -0,0 +1,158 @@
+use std::str::FromStr;
+use chrono::NaiveDate;
+
+use crate::Ledger;
+use crate::i18n::tr;
+use crate::locale::Locale;
+use crate::money::{Money, MoneyError};
+use crate::period::parse_date;
+
+// Column the per-item report is ranked by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortBy{
+    Quantity,
+    Revenue
+}
+
+impl FromStr for SortBy{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        match s.to_lowercase().as_str(){
+            "quantity" | "qty" => Ok(SortBy::Quantity),
+            "revenue" | "income" => Ok(SortBy::Revenue),
+            _ => Err(tr!("kolom urutan \"{}\" tidak dikenal, seharusnya quantity atau revenue", "unknown sort column \"{}\", expected quantity or revenue", s)),
+        }
+    }
+}
+
+// Sales of one item over a period, revenue is net of discounts, service charge and tax
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemSales{
+    pub rank: usize,
+    pub item_id: i64,
+    pub name: String,
+    pub quantity: i64,
+    pub revenue: Money,
+    pub cost: Money,
+    // Hundredths of a percent of the revenue of all items in the period
+    pub share: i64
+}
+
+impl Ledger{
+    // Units sold and revenue per item between `from` and `to` (both included), from the sales history
+    // so deleted items still show up. Refunds count against the day of the original sale.
+    // Items selling the same amount of the sorted column share a rank
+    pub fn item_sales(&self, from: Option<NaiveDate>, to: Option<NaiveDate>, sort_by: SortBy) -> Result<Vec<ItemSales>, MoneyError>{
+        let mut rows: Vec<ItemSales> = Vec::new();
+        for sale in &self.list{
+            let date = parse_date(&self.report_date(sale));
+            if from.is_some_and(|from| date.is_none_or(|date| date < from)) || to.is_some_and(|to| date.is_none_or(|date| date > to)){
+                continue;
+            }
+
+            match rows.iter_mut().find(|row| row.item_id == sale.item_id){
+                Some(row) => {
+                    row.quantity += sale.quantity;
+                    row.revenue = row.revenue.checked_add(sale.net()?)?;
+                    row.cost = row.cost.checked_add(sale.cost)?;
+                    // Keep the name the item was last sold under
+                    row.name = sale.item_name.clone();
+                },
+                None => rows.push(ItemSales{
+                    rank: 0,
+                    item_id: sale.item_id,
+                    name: sale.item_name.clone(),
+                    quantity: sale.quantity,
+                    revenue: sale.net()?,
+                    cost: sale.cost,
+                    share: 0
+                }),
+            }
+        }
+
+        // Items whose every sale was refunded are left out
+        rows.retain(|row| row.quantity != 0 || !row.revenue.is_zero());
+
+        let total = Money::sum(rows.iter().map(|row| row.revenue))?;
+        let key = |row: &ItemSales| match sort_by{
+            SortBy::Quantity => (row.quantity, row.revenue.sen()),
+            SortBy::Revenue => (row.revenue.sen(), row.quantity),
+        };
+        rows.sort_by(|a, b| key(b).cmp(&key(a)).then_with(|| a.name.cmp(&b.name)));
+
+        for index in 0..rows.len(){
+            rows[index].rank = if index > 0 && key(&rows[index]).0 == key(&rows[index - 1]).0{
+                rows[index - 1].rank
+            } else {
+                index + 1
+            };
+            rows[index].share = rows[index].revenue.ratio(total).unwrap_or_default();
+        }
+        Ok(rows)
+    }
+}
+
+pub fn print_item_sales(rows: &[ItemSales], locale: Locale){
+    if rows.is_empty(){
+        println!("{}", tr!("Tidak ada penjualan pada periode tersebut", "No sales in that period"));
+        return;
+    }
+    println!("{}", tr!(
+        "No.  | Nama            | Jumlah       | Pendapatan    | Bagian",
+        "Rank | Name            | Quantity     | Revenue       | Share"
+    ));
+    for row in rows{
+        println!(
+            "{:<5}| {:<16}| {:<13}| {:<14}| {}",
+            row.rank, row.name, locale.number(row.quantity), locale.money(row.revenue), locale.percent(row.share)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Items, Ledger};
+    use crate::money::{rp, Money};
+    use crate::period::parse_date;
+    use crate::promo::LineDiscount;
+    use crate::tax::Charges;
+    use super::SortBy;
+
+    #[test]
+    fn item_sales_test(){
+        let mut items = Items::new();
+        items.add("Risoles", 100, rp(5000), 0, false);
+        items.add("Nasi Goreng", 100, rp(25000), 0, false);
+        items.add("Es Teh", 100, rp(3000), 0, false);
+        let risoles = items.find_item("risoles").unwrap();
+        let nasi = items.find_item("nasi goreng").unwrap();
+        let es_teh = items.find_item("es teh").unwrap();
+
+        let mut ledger = Ledger::new();
+        let none = LineDiscount::default();
+        ledger.record(&risoles, 6, &none, Charges::untaxed(rp(30000)), Money::ZERO, 0, "2022-11-22 10:00:00");
+        ledger.record(&nasi, 2, &none, Charges::untaxed(rp(50000)), Money::ZERO, 0, "2022-11-22 10:00:00");
+        let sale = ledger.record(&es_teh, 10, &none, Charges::untaxed(rp(30000)), Money::ZERO, 1, "2022-11-23 10:00:00");
+        ledger.record(&nasi, 1, &none, Charges::untaxed(rp(25000)), Money::ZERO, 2, "2022-11-30 10:00:00");
+        // Refunded on the 24th, still counted against the 23rd
+        ledger.refund(&sale, 4, "", "2022-11-24 10:00:00").unwrap();
+
+        let rows = ledger.item_sales(None, None, SortBy::Revenue).unwrap();
+        let rows: Vec<_> = rows.iter().map(|row| (row.rank, row.name.as_str(), row.quantity, row.revenue, row.share)).collect();
+        assert_eq!(rows, vec![
+            (1, "nasi goreng", 3, rp(75000), 6098),
+            (2, "risoles", 6, rp(30000), 2439),
+            (3, "es teh", 6, rp(18000), 1463),
+        ]);
+
+        // Same quantity, ranked together and listed by revenue
+        let rows = ledger.item_sales(None, None, SortBy::Quantity).unwrap();
+        assert_eq!(rows.iter().map(|row| row.rank).collect::<Vec<_>>(), vec![1, 1, 3]);
+        assert_eq!(rows[0].name, "risoles");
+
+        let rows = ledger.item_sales(parse_date("2022-11-23"), parse_date("2022-11-23"), SortBy::Revenue).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].quantity, rows[0].share), (6, 10000));
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{Item, Reports};
+use crate::money::{Money, MoneyError};
+use crate::promo::LineDiscount;
+use crate::tax::Charges;
+
+// One sale line, appended by Buy and never modified afterwards
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sale{
+    pub id: i64,
+    // Lines sold together share an order id, refund lines keep the one of the sale they reverse
+    pub order_id: i64,
+    // Local time in YYYY-MM-DD HH:MM:SS
+    pub timestamp: String,
+    pub item_id: i64,
+    pub item_name: String,
+    pub quantity: i64,
+    pub unit_price: Money,
+    // What the customer paid for the line, service and tax included
+    pub total: Money,
+    pub service: Money,
+    pub tax: Money,
+    // Taken off the menu price by promotions, before service and tax
+    pub discount: Money,
+    pub promotion: String,
+    // What the units cost the restaurant when they were sold, 0 when the cost was unknown
+    pub cost: Money,
+    // Set on refund lines: the sale being reversed. Refund lines carry negative quantity and total
+    pub refund_of: Option<i64>,
+    pub reason: String
+}
+
+impl Sale{
+    // YYYY-MM-DD part of the timestamp, the key used by the daily reports
+    pub fn date(&self) -> &str{
+        self.timestamp.split(' ').next().unwrap_or(&self.timestamp)
+    }
+
+    // Revenue without service charge and tax
+    pub fn net(&self) -> Result<Money, MoneyError>{
+        self.total.checked_sub(self.service)?.checked_sub(self.tax)
+    }
+
+    // Menu price of the units before promotions
+    pub fn list_price(&self) -> Result<Money, MoneyError>{
+        self.unit_price.checked_mul(self.quantity)
+    }
+
+    pub fn is_refund(&self) -> bool{
+        self.refund_of.is_some()
+    }
+}
+
+// Amounts of a sale not refunded yet
+struct Remaining{
+    quantity: i64,
+    total: Money,
+    service: Money,
+    tax: Money,
+    discount: Money,
+    cost: Money
+}
+
+// Append-only list of every sale, ordered by id
+pub struct Ledger{
+    pub list: Vec<Sale>
+}
+
+impl Ledger{
+    pub fn new() -> Self{
+        Self{
+            list: Vec::new()
+        }
+    }
+
+    fn next_id(&self) -> i64{
+        match self.list.last(){
+            Some(sale) => sale.id + 1,
+            None => 0,
+        }
+    }
+
+    pub fn next_order_id(&self) -> i64{
+        match self.list.iter().map(|sale| sale.order_id).max(){
+            Some(id) => id + 1,
+            None => 0,
+        }
+    }
+
+    // Used when reading the ledger back from storage
+    pub fn push(&mut self, sale: Sale){
+        self.list.push(sale);
+    }
+
+    // Appends a sale of `quantity` units of `item` at its current price less `discount`, split up as in `charges`.
+    // `cost` is what the units cost the restaurant
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(&mut self, item: &Item, quantity: i64, discount: &LineDiscount, charges: Charges, cost: Money, order_id: i64, timestamp: &str) -> Sale{
+        let sale = Sale{
+            id: self.next_id(),
+            order_id,
+            timestamp: timestamp.to_string(),
+            item_id: item.id,
+            item_name: item.name.clone(),
+            quantity,
+            unit_price: item.price,
+            total: charges.gross,
+            service: charges.service,
+            tax: charges.tax,
+            discount: discount.amount,
+            promotion: discount.promotion.clone(),
+            cost,
+            refund_of: None,
+            reason: String::new(),
+        };
+        self.list.push(sale.clone());
+        sale
+    }
+
+    pub fn find_sale(&self, id: i64) -> Option<&Sale>{
+        self.list.iter().find(|sale| sale.id == id)
+    }
+
+    fn remaining(&self, sale: &Sale) -> Result<Remaining, MoneyError>{
+        let start = Remaining{
+            quantity: sale.quantity,
+            total: sale.total,
+            service: sale.service,
+            tax: sale.tax,
+            discount: sale.discount,
+            cost: sale.cost
+        };
+        self.list.iter()
+            .filter(|line| line.refund_of == Some(sale.id))
+            .try_fold(start, |left, line| Ok(Remaining{
+                quantity: left.quantity + line.quantity,
+                total: left.total.checked_add(line.total)?,
+                service: left.service.checked_add(line.service)?,
+                tax: left.tax.checked_add(line.tax)?,
+                discount: left.discount.checked_add(line.discount)?,
+                cost: left.cost.checked_add(line.cost)?
+            }))
+    }
+
+    // Quantity of a sale that has not been refunded yet
+    pub fn refundable(&self, sale: &Sale) -> i64{
+        self.list.iter()
+            .filter(|line| line.refund_of == Some(sale.id))
+            .fold(sale.quantity, |left, line| left + line.quantity)
+    }
+
+    // Sales of an item on a date that can still be refunded, newest first
+    pub fn refundable_on(&self, item_name: &str, date: &str) -> Vec<(Sale, i64)>{
+        let name = item_name.to_lowercase();
+        self.list.iter().rev()
+            .filter(|sale| !sale.is_refund() && sale.date() == date && sale.item_name.to_lowercase() == name)
+            .map(|sale| (sale.clone(), self.refundable(sale)))
+            .filter(|(_, quantity)| *quantity > 0)
+            .collect()
+    }
+
+    // Appends a line reversing `quantity` units of `sale`. Amounts are refunded pro rata,
+    // the last refund takes whatever is left so rounding never leaves a few rupiah behind
+    pub fn refund(&mut self, sale: &Sale, quantity: i64, reason: &str, timestamp: &str) -> Result<Sale, MoneyError>{
+        let left = self.remaining(sale)?;
+        let refunded = if quantity >= left.quantity{
+            left
+        } else {
+            let part = |amount: Money| amount.share(quantity, sale.quantity).map(Money::trunc_rupiah);
+            Remaining{
+                quantity,
+                total: part(sale.total)?,
+                service: part(sale.service)?,
+                tax: part(sale.tax)?,
+                discount: part(sale.discount)?,
+                // Not paid back to anyone, so kept to the sen
+                cost: sale.cost.share(quantity, sale.quantity)?
+            }
+        };
+
+        let refund = Sale{
+            id: self.next_id(),
+            order_id: sale.order_id,
+            timestamp: timestamp.to_string(),
+            item_id: sale.item_id,
+            item_name: sale.item_name.clone(),
+            quantity: -quantity,
+            unit_price: sale.unit_price,
+            total: refunded.total.checked_neg()?,
+            service: refunded.service.checked_neg()?,
+            tax: refunded.tax.checked_neg()?,
+            discount: refunded.discount.checked_neg()?,
+            promotion: sale.promotion.clone(),
+            cost: refunded.cost.checked_neg()?,
+            refund_of: Some(sale.id),
+            reason: reason.to_string(),
+        };
+        self.list.push(refund.clone());
+        Ok(refund)
+    }
+
+    // Date of the report a line belongs to: refunds count against the day of the original sale
+    pub fn report_date(&self, sale: &Sale) -> String{
+        match sale.refund_of.and_then(|id| self.find_sale(id)){
+            Some(original) => original.date().to_string(),
+            None => sale.date().to_string(),
+        }
+    }
+
+    // Rebuilds the daily report view from every recorded sale
+    pub fn daily_reports(&self) -> Result<Reports, MoneyError>{
+        let mut reports = Reports::new();
+        for sale in &self.list{
+            reports.record_sale(&self.report_date(sale), sale)?;
+        }
+        Ok(reports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Items;
+    use crate::money::{rp, Money};
+    use crate::promo::LineDiscount;
+    use crate::tax::{Charges, TaxRules};
+    use super::Ledger;
+
+    #[test]
+    fn daily_reports_from_ledger_test(){
+        let mut items = Items::new();
+        items.add("Risoles", 164, rp(25000), 0, false);
+        items.add("Ikan Asin", 512, rp(25182), 0, false);
+
+        let mut ledger = Ledger::new();
+        let risoles = items.find_item("risoles").unwrap();
+        let ikan = items.find_item("ikan asin").unwrap();
+        ledger.record(&risoles, 2, &LineDiscount::default(), Charges::untaxed(rp(50000)), Money::ZERO, 0, "2022-11-22 10:00:00");
+        ledger.record(&ikan, 1, &LineDiscount::default(), Charges::untaxed(rp(25182)), Money::ZERO, 0, "2022-11-22 12:30:00");
+        let sale = ledger.record(&risoles, 3, &LineDiscount::default(), Charges::untaxed(rp(75000)), Money::ZERO, 1, "2022-11-23 09:15:00");
+
+        assert_eq!(sale.id, 2);
+        assert_eq!(sale.date(), "2022-11-23");
+        assert_eq!(sale.total, rp(75000));
+
+        let reports = ledger.daily_reports().unwrap();
+        assert_eq!(reports.find_report("2022-11-22").unwrap().quantity, 3);
+        assert_eq!(reports.find_report("2022-11-22").unwrap().income, rp(75182));
+        assert_eq!(reports.find_report("2022-11-23").unwrap().income, rp(75000));
+    }
+
+    #[test]
+    fn refund_test(){
+        let mut items = Items::new();
+        items.add("Risoles", 164, rp(25000), 0, false);
+        let risoles = items.find_item("risoles").unwrap();
+
+        let mut ledger = Ledger::new();
+        let first = ledger.record(&risoles, 2, &LineDiscount::default(), Charges::untaxed(rp(50000)), Money::ZERO, 0, "2022-11-22 10:00:00");
+        let second = ledger.record(&risoles, 3, &LineDiscount::default(), Charges::untaxed(rp(75000)), Money::ZERO, 1, "2022-11-22 11:00:00");
+        ledger.refund(&second, 1, "salah input", "2022-11-23 08:00:00").unwrap();
+
+        assert_eq!(ledger.refundable(&first), 2);
+        assert_eq!(ledger.refundable(&second), 2);
+        assert_eq!(ledger.refundable_on("Risoles", "2022-11-22").iter().map(|(sale, _)| sale.id).collect::<Vec<_>>(), vec![1, 0]);
+
+        // The refund is made on the 23rd but corrects the report of the 22nd
+        let reports = ledger.daily_reports().unwrap();
+        assert_eq!(reports.find_report("2022-11-22").unwrap().quantity, 4);
+        assert_eq!(reports.find_report("2022-11-22").unwrap().income, rp(100000));
+        assert!(reports.find_report("2022-11-23").is_err());
+    }
+
+    #[test]
+    fn taxed_refund_test(){
+        let mut items = Items::new();
+        items.add("Es Teh", 10, rp(6000), 0, false);
+        let es_teh = items.find_item("es teh").unwrap();
+        let rules = TaxRules::parse(b"category,service,tax\n*,5,11\n").unwrap();
+
+        let mut ledger = Ledger::new();
+        let discount = LineDiscount{ amount: rp(3000), promotion: "Potongan".to_string() };
+        let sale = ledger.record(&es_teh, 3, &discount, rules.charges("", rp(18000 - 3000)).unwrap(), Money::ZERO, 0, "2022-11-22 10:00:00");
+        assert_eq!((sale.net().unwrap(), sale.service, sale.tax, sale.total), (rp(15000), rp(750), rp(1733), rp(17483)));
+
+        let first = ledger.refund(&sale, 1, "", "2022-11-22 10:10:00").unwrap();
+        let second = ledger.refund(&sale, 2, "", "2022-11-22 10:20:00").unwrap();
+        assert_eq!((first.service, first.tax, first.total), (rp(-250), rp(-577), rp(-5827)));
+        assert_eq!(first.total.checked_add(second.total).unwrap(), sale.total.checked_neg().unwrap());
+        assert_eq!(first.tax.checked_add(second.tax).unwrap(), sale.tax.checked_neg().unwrap());
+        assert_eq!((first.discount, second.discount), (rp(-1000), rp(-2000)));
+
+        let report = ledger.daily_reports().unwrap().find_report("2022-11-22").unwrap();
+        let zero = Money::ZERO;
+        assert_eq!((report.quantity, report.income, report.service, report.tax, report.discount), (0, zero, zero, zero, zero));
+    }
+}
@@ -0,0 +1,106 @@
+use std::str::FromStr;
+
+use crate::i18n::tr;
+use crate::money::Money;
+
+// How amounts and numbers are written for people. Storage files always keep plain numbers
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Locale{
+    // id-ID: Rp 25.000,50
+    #[default]
+    Indonesian,
+    // en-US: IDR 25,000.50
+    English
+}
+
+impl FromStr for Locale{
+    type Err = String;
+
+    // Accepts id, id-ID, id_ID.UTF-8 and the same for en
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        let tag = s.split('.').next().unwrap_or(s).replace('_', "-").to_lowercase();
+        match tag.as_str(){
+            "id" | "id-id" => Ok(Locale::Indonesian),
+            "en" | "en-us" => Ok(Locale::English),
+            _ => Err(tr!("locale \"{}\" tidak dikenal, seharusnya id-ID atau en-US", "unknown locale \"{}\", expected id-ID or en-US", s)),
+        }
+    }
+}
+
+impl Locale{
+    fn symbol(self) -> &'static str{
+        match self{
+            Locale::Indonesian => "Rp",
+            Locale::English => "IDR",
+        }
+    }
+
+    // Thousands separator and decimal mark
+    fn separators(self) -> (char, char){
+        match self{
+            Locale::Indonesian => ('.', ','),
+            Locale::English => (',', '.'),
+        }
+    }
+
+    fn group(self, digits: u64) -> String{
+        let digits = digits.to_string();
+        let mut grouped = String::new();
+        for (index, digit) in digits.chars().enumerate(){
+            if index > 0 && (digits.len() - index).is_multiple_of(3){
+                grouped.push(self.separators().0);
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+
+    // Whole number with thousands separators: 1.250 or 1,250
+    pub fn number(self, number: i64) -> String{
+        let sign = if number < 0 { "-" } else { "" };
+        format!("{}{}", sign, self.group(number.unsigned_abs()))
+    }
+
+    // Amount with the currency symbol, sen only when there are any: Rp 25.000, -Rp 1.500, Rp 2.500,50
+    pub fn money(self, amount: Money) -> String{
+        let sign = if amount.is_negative() { "-" } else { "" };
+        let sen = amount.sen().unsigned_abs();
+        let rupiah = self.group(sen / 100);
+        if sen.is_multiple_of(100){
+            format!("{}{} {}", sign, self.symbol(), rupiah)
+        } else {
+            format!("{}{} {}{}{:02}", sign, self.symbol(), rupiah, self.separators().1, sen % 100)
+        }
+    }
+
+    // Hundredths of a percent as a percentage: 24,39% or 24.39%
+    pub fn percent(self, hundredths: i64) -> String{
+        let sign = if hundredths < 0 { "-" } else { "" };
+        let hundredths = hundredths.unsigned_abs();
+        format!("{}{}{}{:02}%", sign, self.group(hundredths / 100), self.separators().1, hundredths % 100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::money::{rp, Money};
+    use super::Locale;
+
+    #[test]
+    fn format_test(){
+        assert_eq!("id_ID.UTF-8".parse::<Locale>(), Ok(Locale::Indonesian));
+        assert_eq!("en-US".parse::<Locale>(), Ok(Locale::English));
+        assert!("fr-FR".parse::<Locale>().is_err());
+
+        assert_eq!(Locale::Indonesian.money(rp(25000)), "Rp 25.000");
+        assert_eq!(Locale::English.money(rp(25000)), "IDR 25,000");
+        assert_eq!(Locale::Indonesian.money(Money::from_sen(-123456789)), "-Rp 1.234.567,89");
+        assert_eq!(Locale::English.money(Money::from_sen(250050)), "IDR 2,500.50");
+        assert_eq!(Locale::Indonesian.money(Money::ZERO), "Rp 0");
+
+        assert_eq!(Locale::Indonesian.number(9999999999), "9.999.999.999");
+        assert_eq!(Locale::English.number(-512), "-512");
+        assert_eq!(Locale::Indonesian.percent(2439), "24,39%");
+        assert_eq!(Locale::English.percent(-1250), "-12.50%");
+    }
+}
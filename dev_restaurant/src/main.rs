@@ -1,58 +1,299 @@
 use std::{
     collections::HashMap,
-    fs::{File, OpenOptions},
-    io::{Read, Write},
-    path:: PathBuf
+    path::{Path, PathBuf}
 };
+use chrono::NaiveDate;
+use serde::Deserialize;
 use structopt::StructOpt;
 use thiserror::Error;
-use chrono;
+
+mod events;
+mod i18n;
+mod item_report;
+mod ledger;
+mod locale;
+mod margin;
+mod money;
+mod order;
+mod output;
+mod period;
+mod pos;
+mod promo;
+mod purchase;
+mod receipt;
+mod recipe;
+mod server;
+mod shell;
+mod stock;
+mod storage;
+mod tax;
+
+use i18n::{tr, Lang};
+use item_report::SortBy;
+use ledger::{Ledger, Sale};
+use locale::Locale;
+use margin::MarginBy;
+use money::{Money, MoneyError};
+use order::{Order, OrderError, OrderLine, Orders};
+use output::{ItemRow, ItemSalesRow, MarginRow, OutputFormat, ReportRow, SaleRow};
+use period::Period;
+use promo::Promotions;
+use purchase::{PurchaseOrders, Suppliers};
+use receipt::{Receipt, ReceiptFormat};
+use recipe::{Ingredients, Recipes};
+use stock::{MovementKind, Movements};
+use storage::{Recovery, Storage, StorageError};
+use tax::TaxRules;
 
 // Struct for items
-// Consist of: ID, Item, Quantity, and Price
+// Consist of: ID, Item, Quantity, Price and Category (used for tax rules, may be empty)
+// Reorder level and target drive the low-stock alerts, 0 when not set
+// Cost is what one unit costs the restaurant, 0 when unknown
 #[derive(Clone, Debug, PartialEq)]
 struct Item{
     id: i64,
     name: String,
     quantity: i64,
-    price: i64
+    price: Money,
+    category: String,
+    reorder_level: i64,
+    reorder_target: i64,
+    cost: Money
 }
 
 struct Items{
     list: HashMap<i64, Item>
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 struct Report{
     // Date: Taken from Chrono in YYYY-MM-DD, then to String
+    // Income is net revenue after discounts, discounts, service charge and tax are kept apart
+    // Cost is what the units sold cost the restaurant at the time of the sale
     id: i64,
     date: String,
     quantity: i64,
-    income: i64
+    income: Money,
+    service: Money,
+    tax: Money,
+    discount: Money,
+    cost: Money
 }
 
 struct Reports{
     list: HashMap<i64, Report>
 }
 
+// Values given to add or sent to the server for an item, only the ones set are changed
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ItemChanges{
+    quantity: Option<i64>,
+    price: Option<Money>,
+    category: Option<String>,
+    reorder_level: Option<i64>,
+    reorder_target: Option<i64>,
+    cost: Option<Money>
+}
+
+// Everything a command works on, loaded from and saved to the storage backend together
+struct Restaurant{
+    items: Items,
+    reports: Reports,
+    ledger: Ledger,
+    orders: Orders,
+    movements: Movements,
+    suppliers: Suppliers,
+    purchases: PurchaseOrders,
+    ingredients: Ingredients,
+    recipes: Recipes,
+    // Read from their config files, not from storage
+    taxes: TaxRules,
+    promotions: Promotions
+}
+
+impl Restaurant{
+    // Storage builds it from what it loads, tests start from scratch
+    #[cfg(test)]
+    fn new() -> Self{
+        Self{
+            items: Items::new(),
+            reports: Reports::new(),
+            ledger: Ledger::new(),
+            orders: Orders::new(),
+            movements: Movements::new(),
+            suppliers: Suppliers::new(),
+            purchases: PurchaseOrders::new(),
+            ingredients: Ingredients::new(),
+            recipes: Recipes::new(),
+            taxes: TaxRules::new(),
+            promotions: Promotions::new()
+        }
+    }
+
+    // Adds or edits an item, changing only the values given. A new item needs both quantity and price.
+    // A change of stock on an existing item is kept in the stock history as an adjustment
+    fn edit_item(&mut self, name: &str, quantity: Option<i64>, price: Option<Money>, timestamp: &str) -> Result<Item, ItemError>{
+        let item = match self.items.find_item(name){
+            Ok(item) => item,
+            Err(e) => match (quantity, price){
+                (Some(quantity), Some(price)) => {
+                    self.items.add_or_update(name, quantity, price);
+                    return self.items.find_item(name);
+                },
+                _ => return Err(e),
+            },
+        };
+
+        let updated = self.items.edit(item.id, quantity, price)?;
+        if updated.quantity != item.quantity{
+            self.movements.record(&updated, MovementKind::Adjustment, updated.quantity - item.quantity, Money::ZERO, "", "", timestamp);
+        }
+        Ok(updated)
+    }
+
+    // Adds or edits an item with every value given. Values are checked first, so a rejected change leaves the item alone
+    fn change_item(&mut self, name: &str, changes: &ItemChanges, timestamp: &str) -> Result<Item, ItemError>{
+        if let Some(value) = [changes.quantity, changes.reorder_level, changes.reorder_target].into_iter().flatten().find(|value| *value < 0){
+            return Err(ItemError::NegativeValue(value));
+        }
+        if let Some(amount) = [changes.price, changes.cost].into_iter().flatten().find(|amount| amount.is_negative()){
+            return Err(ItemError::NegativeAmount(amount));
+        }
+
+        self.edit_item(name, changes.quantity, changes.price, timestamp)?;
+        if let Some(category) = &changes.category{
+            self.items.set_category(name, category)?;
+        }
+        let item = self.items.set_reorder(name, changes.reorder_level, changes.reorder_target)?;
+        match changes.cost{
+            Some(cost) => self.items.set_cost(name, cost),
+            None => Ok(item),
+        }
+    }
+
+    // Reverses `quantity` units of a sale: restocks the item, appends a refund line
+    // and takes the income off the report of the day the sale was made.
+    // Items cooked from a recipe are not restocked, their ingredients are used up
+    fn refund(&mut self, sale: &Sale, quantity: i64, reason: &str, timestamp: &str) -> Result<Sale, RefundError>{
+        let restock = !self.recipes.has_recipe(sale.item_id);
+        if restock{
+            self.items.stock_after(sale.item_id, quantity)?;
+        }
+        let refund = self.ledger.refund(sale, quantity, reason, timestamp)?;
+        self.reports.record_sale(&self.ledger.report_date(&refund), &refund)?;
+        if restock{
+            self.items.return_stock(sale.item_id, quantity)?;
+        }
+        Ok(refund)
+    }
+
+    // Reverses whatever is left of a sale
+    fn void(&mut self, sale_id: i64, reason: &str, timestamp: &str) -> Result<Sale, RefundError>{
+        let sale = match self.ledger.find_sale(sale_id){
+            Some(sale) if !sale.is_refund() => sale.clone(),
+            _ => return Err(RefundError::SaleNotFound(sale_id)),
+        };
+
+        let quantity = self.ledger.refundable(&sale);
+        if quantity <= 0{
+            return Err(RefundError::AlreadyRefunded(sale_id));
+        }
+        self.refund(&sale, quantity, reason, timestamp)
+    }
+
+    // Refunds `quantity` units of an item sold today, starting from the latest sale
+    fn refund_today(&mut self, name: &str, quantity: i64, reason: &str, timestamp: &str) -> Result<Vec<Sale>, RefundError>{
+        let item = self.items.find_item(name)?;
+        let date = timestamp.split(' ').next().unwrap_or(timestamp);
+        let sales = self.ledger.refundable_on(&item.name, date);
+
+        let available: i64 = sales.iter().map(|(_, refundable)| refundable).sum();
+        if available < quantity{
+            return Err(RefundError::NotEnoughSold(available, quantity));
+        }
+
+        let mut refunds = Vec::new();
+        let mut left = quantity;
+        for (sale, refundable) in sales{
+            if left == 0{
+                break;
+            }
+            let q = left.min(refundable);
+            refunds.push(self.refund(&sale, q, reason, timestamp)?);
+            left -= q;
+        }
+        Ok(refunds)
+    }
+}
+
 #[derive(Error, Debug, PartialEq)]
 enum ItemError{
-    #[error("value must be a number {0}")]
+    #[error("{}", tr!("nilai harus berupa angka {}", "value must be a number {}", .0))]
     InvalidValue(#[from] std::num::ParseIntError),
 
-    #[error("makanan tidak ditemukan {0} not found")]
+    #[error("{}", tr!("makanan {} tidak ditemukan", "item {} not found", .0))]
     ItemNotFound(String),
 
-    #[error("not enough stock, {0} < {0}")]
+    #[error("{}", tr!("stok tidak mencukupi, tersedia {} dari {}", "not enough stock, {} available of {}", .0, .1))]
     NotEnoughStock(i64, i64),
+
+    #[error("{}", tr!("nilai tidak boleh negatif {}", "value must not be negative {}", .0))]
+    NegativeValue(i64),
+
+    #[error("{}", tr!("jumlah harus lebih dari 0, bukan {}", "quantity must be more than 0, not {}", .0))]
+    InvalidQuantity(i64),
+
+    #[error("{}", tr!("jumlah uang tidak boleh negatif {}", "amount must not be negative {}", .0))]
+    NegativeAmount(Money),
+
+    #[error("{}", tr!("stok {} akan terlalu besar", "stock of {} would be too large", .0))]
+    StockTooLarge(String),
+
+    #[error("{0}")]
+    Money(#[from] MoneyError),
+}
+
+impl ItemError{
+    // Stable name of the error for the structured output formats
+    fn code(&self) -> &'static str{
+        match self{
+            ItemError::InvalidValue(_) => "invalid_value",
+            ItemError::ItemNotFound(_) => "item_not_found",
+            ItemError::NotEnoughStock(..) => "not_enough_stock",
+            ItemError::NegativeValue(_) => "negative_value",
+            ItemError::InvalidQuantity(_) => "invalid_quantity",
+            ItemError::NegativeAmount(_) => "negative_amount",
+            ItemError::StockTooLarge(_) => "invalid_value",
+            ItemError::Money(e) => e.code(),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+enum RefundError{
+    #[error("{}", tr!("transaksi {} tidak ditemukan", "transaction {} not found", .0))]
+    SaleNotFound(i64),
+
+    #[error("{}", tr!("transaksi {} sudah direfund", "transaction {} is already refunded", .0))]
+    AlreadyRefunded(i64),
+
+    #[error("{}", tr!("penjualan hari ini tidak mencukupi, terjual {} dari {}", "not enough sold today, {} sold of {}", .0, .1))]
+    NotEnoughSold(i64, i64),
+
+    #[error("{0}")]
+    Item(#[from] ItemError),
+
+    #[error("{0}")]
+    Money(#[from] MoneyError),
 }
 
 #[derive(Error, Debug)]
 enum ReportError{
-    #[error("value must be a number {0}")]
+    #[error("{}", tr!("nilai harus berupa angka {}", "value must be a number {}", .0))]
     InvalidValue(#[from] std::num::ParseIntError),
 
-    #[error("report tidak ditemukan")]
+    #[error("{}", tr!("report tidak ditemukan", "report not found"))]
     ReportNotFound(String),
 }
 
@@ -66,7 +307,7 @@ impl Items{
 
     fn find_item(&self, name: &str) -> Result<Item, ItemError>{
         let x = name.to_lowercase();
-        for (_, item) in &self.list{
+        for item in self.list.values(){
             if item.name.to_lowercase() == x{
                 return Ok(item.clone());
             }
@@ -74,7 +315,7 @@ impl Items{
         Err(ItemError::ItemNotFound(name.to_string()))
     }
 
-    fn add(&mut self, name: &str, quantity: i64, price: i64, id: i64, from_file: bool){
+    fn add(&mut self, name: &str, quantity: i64, price: Money, id: i64, from_file: bool){
         let mut new_id: i64 = 0;
         
         // If being read from file
@@ -92,26 +333,31 @@ impl Items{
             Item{
                 id: new_id,
                 name: name.to_string().to_lowercase(),
-                quantity: quantity,
-                price: price,
+                quantity,
+                price,
+                category: String::new(),
+                reorder_level: 0,
+                reorder_target: 0,
+                cost: Money::ZERO,
             };
 
         self.list.insert(new_item.id, new_item);
     }
 
-    // Sets quantity and price of item
-    fn update(&mut self, id: i64, name: &str, quantity: i64, price: i64){
-        let x = Item{
-            id,
-            name: name.to_string(),
-            quantity,
-            price,
-        };
-        self.list.insert(x.id, x);
+    // Sets quantity and price of item, the rest of the item is kept
+    fn update(&mut self, id: i64, name: &str, quantity: i64, price: Money){
+        match self.list.get_mut(&id){
+            Some(item) => {
+                item.name = name.to_string();
+                item.quantity = quantity;
+                item.price = price;
+            },
+            None => self.add(name, quantity, price, id, true),
+        }
     }
 
     // Helper function for adding new or updating existing items
-    fn add_or_update(&mut self, name: &str, quantity: i64, price: i64){
+    fn add_or_update(&mut self, name: &str, quantity: i64, price: Money){
         match self.find_item(name){
             // if item exists
             Ok(item) => {
@@ -124,13 +370,33 @@ impl Items{
         }
     }
 
+    // Sets whichever of quantity and price is given
+    fn edit(&mut self, id: i64, quantity: Option<i64>, price: Option<Money>) -> Result<Item, ItemError>{
+        let item = self.list.get_mut(&id).ok_or_else(|| ItemError::ItemNotFound(id.to_string()))?;
+        if let Some(quantity) = quantity{
+            item.quantity = quantity;
+        }
+        if let Some(price) = price{
+            item.price = price;
+        }
+        Ok(item.clone())
+    }
+
+    fn set_category(&mut self, name: &str, category: &str) -> Result<Item, ItemError>{
+        let id = self.find_item(name)?.id;
+        let item = self.list.get_mut(&id).ok_or_else(|| ItemError::ItemNotFound(name.to_string()))?;
+        item.category = category.trim().to_lowercase();
+        Ok(item.clone())
+    }
+
     // Uses find_item, if item is found, then go to update, with reduction in quantity of the specified item
-    fn buy(&mut self, name: &str, quantity: i64) -> Result<i64, ItemError>{
+    // Returns the item with its remaining stock
+    fn buy(&mut self, name: &str, quantity: i64) -> Result<Item, ItemError>{
         match self.find_item(name){
             Ok(e) => {
                 if e.quantity >= quantity{
                     self.update(e.id, &e.name, e.quantity - quantity, e.price);
-                    return Ok(e.price);
+                    return self.find_item(name);
                 }
                 // If quantity is lower than stock
                 Err(ItemError::NotEnoughStock(e.quantity, quantity))
@@ -139,6 +405,20 @@ impl Items{
         }
     }
 
+    // Stock of an item once `quantity` units are put back, so callers can check it before changing anything
+    fn stock_after(&self, id: i64, quantity: i64) -> Result<i64, ItemError>{
+        let item = self.list.get(&id).ok_or_else(|| ItemError::ItemNotFound(id.to_string()))?;
+        item.quantity.checked_add(quantity).ok_or_else(|| ItemError::StockTooLarge(item.name.clone()))
+    }
+
+    // Puts refunded units back into stock, the item keeps its current price
+    fn return_stock(&mut self, id: i64, quantity: i64) -> Result<Item, ItemError>{
+        let stock = self.stock_after(id, quantity)?;
+        let item = self.list.get_mut(&id).ok_or_else(|| ItemError::ItemNotFound(id.to_string()))?;
+        item.quantity = stock;
+        Ok(item.clone())
+    }
+
     // Deletes a key without doing anything else
     fn delete(&mut self, name: &str) -> Result<i32, ItemError>{
         match self.find_item(name){
@@ -153,31 +433,28 @@ impl Items{
     }
 
     // For saving
-    fn get_item_list(self) -> Vec<Item>{
-        let mut items: Vec<_> = self.list.into_values().collect();
+    fn get_item_list(&self) -> Vec<Item>{
+        let mut items: Vec<_> = self.list.values().cloned().collect();
         items.sort_by_key(|item| item.id);
         items
     }
 
-    fn repeat_char(times: usize, ch: char) -> String{
-        let mut str = String::new();
-        for _ in 0..times{
-            str.push(ch);
-        }
-        str
-    }
-
-    // Helper function to print all items
-    fn print_items(self){
+    // Helper function to print all items. Items cooked from a recipe show the portions their ingredients still make
+    fn print_items(&self, portions: &HashMap<i64, i64>, locale: Locale){
         if self.list.is_empty(){
-            println!("Data kosong atau file tidak ditemukan");
+            println!("{}", tr!("Data kosong atau file tidak ditemukan", "No data or file not found"));
             return;
         }
-        println!("ID |      Name      |      Stock      |   Price");
+        println!("{}", tr!(
+            "ID |      Nama      |      Stok       |     Harga     | Porsi",
+            "ID |      Name      |      Stock      |     Price     | Portions"
+        ));
         for item in &self.get_item_list(){
-            let name_length = "      Name      ".len() - item.name.len() - 1;
-            let quantity_length = "      Stock      ".len() - item.quantity.to_string().len() - 1;
-            println!("{}  | {}{}| {}{}| Rp.{}", item.id, item.name, Items::repeat_char(name_length, ' '),  item.quantity, Items::repeat_char(quantity_length, ' '), item.price)
+            let (stock, available) = match portions.get(&item.id){
+                Some(available) => (tr!("resep", "recipe"), *available),
+                None => (locale.number(item.quantity), item.quantity),
+            };
+            println!("{:<3}| {:<15}| {:<16}| {:<14}| {}", item.id, item.name, stock, locale.money(item.price), locale.number(available));
         }
     }
 }
@@ -192,7 +469,7 @@ impl Reports{
 
     // Helper function to search for a report
     fn find_report(&self, date: &str) -> Result<Report, ReportError>{
-        for (_, report) in &self.list{
+        for report in self.list.values(){
             if report.date.to_lowercase() == date.to_string().to_lowercase(){
                 return Ok(report.clone());
             }
@@ -201,18 +478,22 @@ impl Reports{
     }
 
     // Either add a new date entry or update existing, with the actual update being the quantity and income being added
-    fn add_or_update(&mut self, date: &str, quantity: i64, income: i64, id: i64, from_file: bool){
+    fn add_or_update(&mut self, date: &str, quantity: i64, income: Money, id: i64, from_file: bool) -> Result<(), MoneyError>{
         match self.find_report(date) {
             Ok(report) => {
                 // Update Existing report
-                let actual_income = quantity * income;
+                let actual_income = income.checked_mul(quantity)?;
 
                 let updated_report = 
                     Report{ 
                         id: report.id, 
                         date: report.date,
                         quantity: report.quantity + quantity, 
-                        income: report.income + actual_income,
+                        income: report.income.checked_add(actual_income)?,
+                        service: report.service,
+                        tax: report.tax,
+                        discount: report.discount,
+                        cost: report.cost,
                     };
 
                 self.list.insert(report.id, updated_report);
@@ -227,16 +508,15 @@ impl Reports{
                 if from_file{
                     // If being read from file
                     new_id = id;
-                } else
-                if !self.list.is_empty(){
+                } else if !self.list.is_empty(){
                     // If list has entries
                     tmp = self.list.keys().max().unwrap();
                     new_id = *tmp + 1;
-                    x = x * quantity;
+                    x = x.checked_mul(quantity)?;
                 } else {
                     // If list does not have anything
                     new_id = 0;
-                    x = x * quantity;
+                    x = x.checked_mul(quantity)?;
                 }
 
                 let new_report = 
@@ -245,220 +525,389 @@ impl Reports{
                         date: date.to_string(),
                         quantity, 
                         income: x,
+                        service: Money::ZERO,
+                        tax: Money::ZERO,
+                        discount: Money::ZERO,
+                        cost: Money::ZERO,
                     };
-                self.list.insert(id, new_report);
+                self.list.insert(new_id, new_report);
             },
         }
+        Ok(())
+    }
+
+    // Folds a ledger line into the report of the given date, refund lines subtract
+    fn record_sale(&mut self, date: &str, sale: &Sale) -> Result<(), MoneyError>{
+        self.add_or_update(date, sale.quantity, Money::ZERO, 0, false)?;
+
+        let id = self.find_report(date).map(|report| report.id).unwrap_or_default();
+        if let Some(report) = self.list.get_mut(&id){
+            report.merge(&Report{
+                quantity: 0,
+                income: sale.net()?,
+                service: sale.service,
+                tax: sale.tax,
+                discount: sale.discount,
+                cost: sale.cost,
+                ..report.clone()
+            })?;
+        }
+        Ok(())
     }
 
     // Helper function for saving
-    fn get_report_list(self) -> Vec<Report>{
-        let mut reports: Vec<_> = self.list.into_values().collect();
+    fn get_report_list(&self) -> Vec<Report>{
+        let mut reports: Vec<_> = self.list.values().cloned().collect();
         reports.sort_by_key(|rep| rep.id);
         reports
     }
 
-    fn repeat_char(times: usize, ch: char) -> String{
-        let mut str = String::new();
-        for _ in 0..times{
-            str.push(ch);
-        }
-        str
-    }
-
-    // Helper function to print all reports
-    fn print_reports(self){
+    // Helper function to print the reports between two dates, one row per period and a totals row
+    fn print_reports(&self, from: Option<NaiveDate>, to: Option<NaiveDate>, period: Period, locale: Locale) -> Result<(), MoneyError>{
         if self.list.is_empty(){
-            println!("Data kosong atau file tidak ditemukan");
-            return;
+            println!("{}", tr!("Data kosong atau file tidak ditemukan", "No data or file not found"));
+            return Ok(());
         }
-        println!("ID |      Date      |   Quantity   |   Income");
-        for report in &self.get_report_list(){
-            let date_length = "      Date      ".len() - report.date.len() - 1;
-            let quantity_length = "   Quantity   ".len() - report.quantity.to_string().len() - 1;
-            println!("{}  | {}{}| {}{}| Rp.{} ", report.id, report.date, Reports::repeat_char(date_length, ' '), report.quantity, Reports::repeat_char(quantity_length, ' '), report.income);
+        let rows = self.summary(from, to, period)?;
+        if rows.is_empty(){
+            println!("{}", tr!("Tidak ada penjualan pada periode tersebut", "No sales in that period"));
+            return Ok(());
         }
-    }
-}
-
-#[derive(Error, Debug)]
-enum ParseError{
-    #[error("value must be a number {0}")]
-    InvalidValue(#[from] std::num::ParseIntError),
 
-    #[error("empty item")]
-    EmptyItem,
+        let print_row = |id: &str, report: &Report| -> Result<(), MoneyError>{
+            println!(
+                "{:<3}| {:<15}| {:<13}| {:<14}| {:<14}| {:<14}| {:<14}| {:<14}| {}",
+                id, report.date, locale.number(report.quantity), locale.money(report.income.checked_add(report.discount)?),
+                locale.money(report.discount), locale.money(report.income), locale.money(report.service), locale.money(report.tax),
+                locale.money(Money::sum([report.income, report.service, report.tax])?)
+            );
+            Ok(())
+        };
 
-    #[error("missing field {0}")]
-    MissingField(String)
+        // Sales is before discounts, Gross is what customers paid
+        println!("{}", tr!(
+            "ID |    Periode     |    Jumlah    |   Penjualan   |    Diskon     |    Bersih     |    Layanan    |     Pajak     |   Bruto",
+            "ID |     Period     |   Quantity   |     Sales     |   Discount    |      Net      |    Service    |      Tax      |   Gross"
+        ));
+        let mut total = Report{
+            id: 0,
+            date: "Total".to_string(),
+            quantity: 0,
+            income: Money::ZERO,
+            service: Money::ZERO,
+            tax: Money::ZERO,
+            discount: Money::ZERO,
+            cost: Money::ZERO
+        };
+        for report in &rows{
+            print_row(&report.id.to_string(), report)?;
+            total.merge(report)?;
+        }
+        println!("{}", "-".repeat(139));
+        print_row("", &total)
+    }
 }
 
+impl Report{
+    // Adds the quantity and amounts of another report to this one
+    fn merge(&mut self, other: &Report) -> Result<(), MoneyError>{
+        self.quantity += other.quantity;
+        self.income = self.income.checked_add(other.income)?;
+        self.service = self.service.checked_add(other.service)?;
+        self.tax = self.tax.checked_add(other.tax)?;
+        self.discount = self.discount.checked_add(other.discount)?;
+        self.cost = self.cost.checked_add(other.cost)?;
+        Ok(())
+    }
+}
 
-// Item
-fn parse_line(buffer: &str, n2: &str, n3: &str, n4: &str) -> Result<(i64, String, i64, i64), ParseError>{
-    let fields: Vec<&str> = buffer.split(',').collect();
-    
-    // ID
-    let f1 = match fields.get(0){
-        Some(id) => i64::from_str_radix(id, 10)?,
-        None => return Err(ParseError::EmptyItem),
-    };
-    
-    // Name(Item) / Date(Report)
-    let f2 = match fields.get(1){
-        Some(name) => name.to_string(),
-        None => return Err(ParseError::MissingField(n2.to_string())),
-    };
-
-    // Quantity
-    let f3 = match fields.get(2){
-        Some(quantity) => i64::from_str_radix(quantity, 10)?,
-        None => return Err(ParseError::MissingField(n3.to_string())),
-    };
-    
-    // Price(Item) / Income(Report)
-    let f4 = match fields.get(3){
-        Some(price) => i64::from_str_radix(price.trim(), 10)?,
-        None => return Err(ParseError::MissingField(n4.to_string())),
-    };
-
-    Ok((f1, f2, f3, f4))
+#[derive(StructOpt, Debug)]
+enum Command{
+    // Adds a new item, or changes only the given values of an existing one
+    Add {
+        name: String,
+        #[structopt(help = "stock, - to keep the current stock")]
+        quantity: Option<String>,
+        #[structopt(help = "selling price, - to keep the current price")]
+        price: Option<String>,
+        #[structopt(long, help = "category used to pick the tax rule")]
+        category: Option<String>,
+        #[structopt(long, help = "stock at or below which the item is reported as low")]
+        reorder_level: Option<String>,
+        #[structopt(long, help = "stock to reorder up to")]
+        reorder_target: Option<String>,
+        #[structopt(long, help = "what one unit costs the restaurant")]
+        cost: Option<String>,
+        #[structopt(long, default_value = "table", help = "table, json, csv or ndjson")]
+        format: OutputFormat
+    },
+    Buy {
+        name: String,
+        quantity: String,
+        #[structopt(flatten)]
+        receipt: ReceiptOpt,
+        #[structopt(long, default_value = "table", help = "table, json, csv or ndjson")]
+        format: OutputFormat
+    }, 
+    // Buys several items at once, either every line goes through or none
+    Order {
+        #[structopt(help = "name=quantity pairs")]
+        lines: Vec<String>,
+        #[structopt(long, parse(from_os_str), help = "file with one name=quantity per line")]
+        file: Option<PathBuf>,
+        #[structopt(flatten)]
+        receipt: ReceiptOpt
+    },
+    Receipt(ReceiptCommand),
+    // Adds a delivery to the stock of an item, the selling price is left alone
+    Restock {
+        name: String,
+        quantity: String,
+        #[structopt(long, help = "purchase price per unit")]
+        cost: Option<String>,
+        #[structopt(long, default_value = "")]
+        supplier: String,
+        #[structopt(long, default_value = "")]
+        note: String
+    },
+    Stock(StockCommand),
+    Ingredient(IngredientCommand),
+    Recipe(RecipeCommand),
+    Supplier(SupplierCommand),
+    Purchase(PurchaseCommand),
+    Delete {
+        name: String,
+        #[structopt(long, default_value = "table", help = "table, json, csv or ndjson")]
+        format: OutputFormat
+    }, 
+    // Reverses what is left of a sale, by transaction id
+    Void {
+        id: String,
+        #[structopt(long, default_value = "")]
+        reason: String
+    },
+    // Reverses part of today's sales of an item
+    Refund {
+        name: String,
+        quantity: String,
+        #[structopt(long, default_value = "")]
+        reason: String
+    },
+    // Sales per day, week, month or year, optionally between two dates
+    Report {
+        #[structopt(flatten)]
+        range: DateRange,
+        #[structopt(long, default_value = "day", help = "day, week, month or year")]
+        group_by: Period,
+        #[structopt(long, default_value = "table", help = "table, json, csv or ndjson")]
+        format: OutputFormat,
+        #[structopt(subcommand)]
+        view: Option<ReportView>
+    },
+    List {
+        #[structopt(long, default_value = "table", help = "table, json, csv or ndjson")]
+        format: OutputFormat
+    },
+    // Serves items, purchases and reports as JSON over HTTP until stopped, and the kitchen feed as a WebSocket on /events
+    Serve {
+        #[structopt(long, default_value = "127.0.0.1:8080", help = "address to listen on, 0.0.0.0:8080 for the whole LAN")]
+        address: String
+    },
+    // Full screen till for the counter: search the menu, build the order, take payment
+    Pos {},
+    // Reads commands one line at a time with the data kept loaded, read again only when another process saved
+    Shell {},
+    // Finishes or rolls back a save that a crash interrupted and tells which. Every other command does this first as well
+    Recover {}
 }
 
-// name, quantity, price
-fn parse_items(buffer: String, verbose: bool) -> Items{
-    let mut items = Items::new();
-    
-    // Read each line
-    for (line_number, item) in buffer.split('\n').enumerate(){
-        // Not empty line
-        if item != ""{
-            // Parse each line
-            match parse_line(item, "name", "quantity", "price"){
-                // Add to items data
-                Ok((item_id, item_name, item_quantity, item_price)) => {
-                    if verbose {
-                        println!("Adding {} | {} | {} | {} to item list", item_id, item_name, item_quantity, item_price);
-                    };
-                    items.add(
-                        &item_name,
-                        item_quantity,
-                        item_price,
-                        item_id,
-                        true
-                    );
-                    },
-                // Ignore line if error
-                Err(e) => 
-                    if verbose{
-                        println!("Error parsing item line {}: {:?}", line_number + 1, e)
-                    }
-            }
+impl Command{
+    // Format asked for with --format, the other commands only print text
+    fn format(&self) -> OutputFormat{
+        match self{
+            Command::Add { format, .. } | Command::Buy { format, .. } | Command::Delete { format, .. }
+                | Command::Report { format, .. } | Command::List { format } => *format,
+            _ => OutputFormat::Table,
         }
     }
-    items
 }
 
-fn load_items_csv(csv_file: PathBuf, verbose: bool) -> std::io::Result<Items>{
-    let mut file = File::open(csv_file)?;
-
-    let mut buffer = String::new();
-    file.read_to_string(&mut buffer)?;
+#[derive(StructOpt, Debug)]
+enum ReportView{
+    // Units sold, revenue and share of revenue per item, best sellers first
+    Items {
+        #[structopt(flatten)]
+        range: DateRange,
+        #[structopt(long, default_value = "revenue", help = "quantity or revenue")]
+        sort_by: SortBy,
+        #[structopt(long, help = "only show the first N items")]
+        top: Option<usize>
+    },
+    // Revenue, cost of goods sold, gross profit and margin per item or per period
+    Margin {
+        #[structopt(flatten)]
+        range: DateRange,
+        #[structopt(long, default_value = "item", help = "item, day, week, month or year")]
+        by: MarginBy
+    }
+}
 
-    Ok(parse_items(buffer, verbose))
+// Dates a report is limited to, both included
+#[derive(StructOpt, Debug)]
+struct DateRange{
+    #[structopt(long, help = "first date included, YYYY-MM-DD")]
+    from: Option<String>,
+    #[structopt(long, help = "last date included, YYYY-MM-DD")]
+    to: Option<String>
 }
 
-// Report 
-fn parse_reports(buffer: String, verbose: bool) -> Reports{
-    let mut reports = Reports::new();
-    
-    for (line_number, item) in buffer.split('\n').enumerate(){
-        if item != ""{
-            match parse_line(item, "date", "quantity", "income"){
-                Ok((id, date, quantity, income)) => {
-                    if verbose {
-                        println!("Adding {} | {} | {} | {} to reports list", id, date, quantity, income);
-                    };
-                    reports.add_or_update(
-                        &date,
-                        quantity,
-                        income,
-                        id,
-                        true
-                    );
-                    },
-                Err(e) => 
-                    if verbose {
-                        println!("Error parsing report line {}: {:?}", line_number + 1, e)
-                    }
+impl DateRange{
+    // Fails with a message naming the invalid parameter when a date cannot be read
+    fn parse(&self) -> Result<(Option<NaiveDate>, Option<NaiveDate>), String>{
+        let mut range = [None, None];
+        for (bound, (name, date)) in range.iter_mut().zip([("from", &self.from), ("to", &self.to)]){
+            if let Some(date) = date{
+                match period::parse_date(date){
+                    Some(date) => *bound = Some(date),
+                    None => return Err(tr!("Nilai tidak valid pada parameter {}: {}", "Invalid value found on {} parameter: {}", name, date)),
+                }
             }
         }
+        Ok((range[0], range[1]))
     }
-    reports
 }
 
-fn load_reports_csv(csv_file: PathBuf, verbose: bool) -> std::io::Result<Reports>{
-    let mut file = File::open(csv_file)?;
-
-    let mut buffer = String::new();
-    file.read_to_string(&mut buffer)?;
 
-    Ok(parse_reports(buffer, verbose))
+#[derive(StructOpt, Debug)]
+enum ReceiptCommand{
+    // Prints the receipt of an order of the day again
+    Reprint {
+        #[structopt(help = "order number of the day")]
+        number: i64,
+        #[structopt(long, help = "business day of the order, YYYY-MM-DD, defaults to today")]
+        date: Option<String>,
+        #[structopt(long, default_value = "text", help = "text or html")]
+        format: ReceiptFormat,
+        #[structopt(long, parse(from_os_str), default_value = "-", help = "file to write the receipt to, - for stdout")]
+        output: PathBuf
+    }
 }
 
-// Save items list, create a new file if it doesnt exist
-fn save_items(file_name: PathBuf, items: Items) -> std::io::Result<()>{
-    let mut file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(file_name)?;
-
-    file.write(b"id,name,stock,price\n")?;
-
-    for item in items.get_item_list().into_iter(){
-        let line = format!("{},{},{},{}\n", item.id, item.name, item.quantity, item.price);
-        file.write(line.as_bytes())?;
+#[derive(StructOpt, Debug)]
+enum StockCommand{
+    // Lists the items at or below their reorder level with how much to order
+    Alerts {},
+    // Lists deliveries and stock adjustments, oldest first
+    History {
+        #[structopt(help = "only show this item")]
+        name: Option<String>
     }
-    Ok(())
 }
 
-// Save reports list, create a new file if it doesnt exist
-fn save_reports(file_name: PathBuf, reports: Reports) -> std::io::Result<()>{
-    let mut file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(file_name)?;
-
-    file.write(b"id,date,quantity,income\n")?;
+#[derive(StructOpt, Debug)]
+enum IngredientCommand{
+    // Adds an ingredient, or changes the given values of an existing one
+    Add {
+        name: String,
+        #[structopt(help = "stock, - to keep the current stock")]
+        quantity: Option<String>,
+        #[structopt(long, help = "unit the stock and recipes are counted in, e.g. gram")]
+        unit: Option<String>,
+        #[structopt(long, help = "price of one unit")]
+        cost: Option<String>
+    },
+    // Adds delivered units to the stock of an ingredient
+    Restock {
+        name: String,
+        quantity: String,
+        #[structopt(long, help = "purchase price per unit")]
+        cost: Option<String>
+    },
+    List {}
+}
 
-    for report in reports.get_report_list().into_iter(){
-        let line = format!("{},{},{},{}\n", report.id, report.date, report.quantity, report.income);
-        file.write(line.as_bytes())?;
+#[derive(StructOpt, Debug)]
+enum RecipeCommand{
+    // Replaces the recipe of a menu item, without ingredients the recipe is removed
+    Set {
+        item: String,
+        #[structopt(help = "ingredient=quantity per portion")]
+        ingredients: Vec<String>
+    },
+    Show {
+        item: String
     }
-    Ok(())
 }
 
-
 #[derive(StructOpt, Debug)]
-enum Command{
+enum SupplierCommand{
     Add {
         name: String,
-        quantity: String,
-        price: String
+        #[structopt(long, default_value = "")]
+        phone: String,
+        #[structopt(long, default_value = "")]
+        note: String
+    },
+    // Records that a supplier delivers an item and at what cost per unit
+    Item {
+        supplier: String,
+        item: String,
+        cost: String
     },
-    Buy {
-        name: String,
-        quantity: String
-    }, 
-    Delete {
-        name: String
-    }, 
-    Report {},
     List {}
 }
 
+#[derive(StructOpt, Debug)]
+enum PurchaseCommand{
+    // Starts a draft purchase order
+    Create {
+        supplier: String,
+        #[structopt(help = "name=quantity pairs")]
+        lines: Vec<String>,
+        #[structopt(long, help = "expected delivery date, YYYY-MM-DD")]
+        expected: Option<String>
+    },
+    // Marks a draft as sent to the supplier
+    Send {
+        id: String
+    },
+    // Adds delivered units to the stock, everything outstanding when no lines are given
+    Receive {
+        id: String,
+        #[structopt(help = "name=quantity pairs")]
+        lines: Vec<String>
+    },
+    Show {
+        id: String
+    },
+    // Lists purchase orders not fully received, with their expected dates
+    List {
+        #[structopt(long, help = "include received orders")]
+        all: bool
+    }
+}
+
+// Payment and receipt options shared by buy and order
+#[derive(StructOpt, Debug)]
+struct ReceiptOpt{
+    #[structopt(long, help = "amount paid by the customer, defaults to the exact total")]
+    paid: Option<Money>,
+    #[structopt(long, parse(from_os_str), help = "write a receipt to this file, - for stdout")]
+    receipt: Option<PathBuf>,
+    #[structopt(long, default_value = "text", help = "receipt format: text or html")]
+    receipt_format: ReceiptFormat
+}
+
+impl ReceiptOpt{
+    fn print(&self, data: &Restaurant, order: &Order, locale: Locale) -> Result<(), StorageError>{
+        if let Some(output) = &self.receipt{
+            if let Some(receipt) = Receipt::for_order(data, order.id)?{
+                receipt.write(self.receipt_format, locale, output)?;
+            }
+        }
+        Ok(())
+    }
+}
 
 #[derive(StructOpt, Debug)]
 #[structopt(about = "Dev Restaurant CLI, For Assignment 4 by Eric Hartanto")]
@@ -467,131 +916,647 @@ struct Opt{
     items_csv: PathBuf,
     #[structopt(short, parse(from_os_str), default_value = "report.csv")]
     reports_csv: PathBuf,
+    #[structopt(short, parse(from_os_str), default_value = "sales.csv")]
+    sales_csv: PathBuf,
+    #[structopt(short, parse(from_os_str), default_value = "orders.csv")]
+    orders_csv: PathBuf,
+    #[structopt(short, parse(from_os_str), default_value = "movements.csv")]
+    movements_csv: PathBuf,
+    #[structopt(long, parse(from_os_str), default_value = "suppliers.csv")]
+    suppliers_csv: PathBuf,
+    #[structopt(long, parse(from_os_str), default_value = "supplier_items.csv")]
+    supplier_items_csv: PathBuf,
+    #[structopt(long, parse(from_os_str), default_value = "purchases.csv")]
+    purchases_csv: PathBuf,
+    #[structopt(long, parse(from_os_str), default_value = "ingredients.csv")]
+    ingredients_csv: PathBuf,
+    #[structopt(long, parse(from_os_str), default_value = "recipes.csv")]
+    recipes_csv: PathBuf,
+    #[structopt(long, parse(from_os_str), default_value = "tax.csv", help = "service charge and tax rules per item category")]
+    tax_config: PathBuf,
+    #[structopt(long, parse(from_os_str), default_value = "promotions.csv", help = "promotions applied to buy and order")]
+    promotions: PathBuf,
+    #[structopt(long, default_value = "csv", help = "storage backend: csv or sqlite")]
+    backend: storage::Backend,
+    #[structopt(long, parse(from_os_str), default_value = "restaurant.db", help = "database file for the sqlite backend")]
+    database: PathBuf,
+    #[structopt(long, parse(from_os_str), default_value = "events.ndjson", help = "kitchen feed the orders, refunds and stock-outs are appended to")]
+    events: PathBuf,
+    #[structopt(long, env = "DEV_RESTAURANT_LOCALE", default_value = "id-ID", help = "how amounts and numbers are written: id-ID or en-US")]
+    locale: Locale,
+    // Taken by i18n::requested_lang before parsing, kept here for --help and to reject unknown languages
+    #[allow(dead_code)]
+    #[structopt(long, env = "DEV_RESTAURANT_LANG", default_value = "id", help = "language of messages: id or en")]
+    lang: Lang,
     #[structopt(subcommand)]
     cmd: Command,
     #[structopt(short, help = "verbose")]
     verbose: bool
 }
 
-fn run(opt: Opt) -> Result<(), std::io::Error>{
-    let mut items = 
-        match load_items_csv(opt.items_csv.clone(), opt.verbose.clone()){
-            Ok(item) => item,
-            Err(_) => Items::new(),
-        };
+fn run(opt: Opt) -> Result<(), StorageError>{
+    let mut storage = storage::open(&opt)?;
+    // Held from load to save so no other process changes the data in between
+    let mut lock = Some(storage.lock()?);
+    // A save cut off by a crash is finished or dropped before anything reads the files
+    let recovery = storage.recover()?;
+    if let Command::Recover {} = opt.cmd{
+        println!("{}", recovery);
+        return Ok(());
+    }
+    if recovery != Recovery::Clean{
+        // On stderr, so output meant for scripts stays as it is
+        eprintln!("{}", recovery);
+    }
+    let mut data = storage.load(opt.verbose)?;
+    data.taxes = match TaxRules::load(&opt.tax_config){
+        Ok(rules) => rules,
+        Err(e) => {
+            println!("{}", tr!("Konfigurasi pajak {} tidak valid: {}", "Invalid tax config {}: {}", opt.tax_config.display(), e));
+            return Ok(());
+        },
+    };
+    data.promotions = match Promotions::load(&opt.promotions){
+        Ok(promotions) => promotions,
+        Err(e) => {
+            println!("{}", tr!("Promosi {} tidak valid: {}", "Invalid promotions {}: {}", opt.promotions.display(), e));
+            return Ok(());
+        },
+    };
 
-    let mut reports = 
-        match load_reports_csv(opt.reports_csv.clone(), opt.verbose.clone()){
-            Ok(report) => report,
-            Err(_) => Reports::new(),
-        };
-    
-    let curr_date = chrono::Local::now().format("%Y-%m-%d").to_string();
+    // Processes that stay open take the lock again for every change instead of keeping everyone else out
+    if matches!(opt.cmd, Command::Serve { .. } | Command::Pos {} | Command::Shell {}){
+        lock.take();
+    }
     match opt.cmd{
+        Command::Serve { address } => server::serve(&address, storage, data, opt.events, opt.verbose),
+        Command::Pos {} => pos::run(storage.as_mut(), &mut data, &opt.events, opt.locale),
+        Command::Shell {} => shell::run(storage.as_mut(), &mut data, &opt.events, opt.locale),
+        cmd => execute(cmd, storage.as_mut(), &mut data, &opt.events, opt.locale),
+    }
+}
+
+// Runs one command against data already loaded, saving whatever it changed
+fn execute(cmd: Command, storage: &mut dyn Storage, data: &mut Restaurant, events: &Path, locale: Locale) -> Result<(), StorageError>{
+    let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    match cmd{
         // Add or update item
-        Command::Add { name, quantity, price } => {
+        Command::Add { name, quantity, price, category, reorder_level, reorder_target, cost, format } => {
+            // Only the values given are changed, "-" keeps the current one
+            let mut counts = [None, None, None];
+            for (value, (parameter, given)) in counts.iter_mut().zip([("quantity", &quantity), ("reorder-level", &reorder_level), ("reorder-target", &reorder_target)]){
+                match given.as_deref(){
+                    None | Some("-") => {},
+                    Some(given) => match given.parse::<i64>(){
+                        Ok(e) if e >= 0 => *value = Some(e),
+                        _ => {
+                            let message = tr!("Nilai tidak valid pada parameter {}: {}", "Invalid value found on {} parameter: {}", parameter, given);
+                            output::fail(format, "invalid_value", message)?;
+                            return Ok(());
+                        },
+                    },
+                }
+            }
+            let mut amounts = [None, None];
+            for (value, (parameter, given)) in amounts.iter_mut().zip([("price", &price), ("cost", &cost)]){
+                match given.as_deref(){
+                    None | Some("-") => {},
+                    Some(given) => match given.parse::<Money>(){
+                        Ok(e) if !e.is_negative() => *value = Some(e),
+                        _ => {
+                            let message = tr!("Nilai tidak valid pada parameter {}: {}", "Invalid value found on {} parameter: {}", parameter, given);
+                            output::fail(format, "invalid_value", message)?;
+                            return Ok(());
+                        },
+                    },
+                }
+            }
+            let ([quantity, reorder_level, reorder_target], [price, cost]) = (counts, amounts);
+            let changes = ItemChanges{ quantity, price, category, reorder_level, reorder_target, cost };
+
+            let item = match data.change_item(&name, &changes, &now){
+                Ok(item) => item,
+                Err(ItemError::ItemNotFound(_)) => {
+                    let message = tr!("Makanan baru {} harus diberi kuantitas dan harga", "New item {} needs a quantity and a price", name);
+                    output::fail(format, "incomplete_item", message)?;
+                    return Ok(());
+                },
+                Err(e) => {
+                    output::fail(format, e.code(), e.to_string())?;
+                    return Ok(());
+                },
+            };
+            storage.save(data)?;
+            match format{
+                OutputFormat::Table => println!("{}", tr!("Berhasil menambahkan {} ke list makanan", "Added {} to the item list", name)),
+                format => {
+                    let portions = data.recipes.portions(&data.ingredients);
+                    output::emit_one(format, &ItemRow::new(&item, portions.get(&item.id).copied()))?;
+                },
+            }
+        },
+
+        // Updates existing item, by reducing the quantity then updates the report with quantity * price for that particular date
+        Command::Buy { name, quantity, receipt, format } => {
+
             let q = match quantity.parse::<i64>(){
                 Ok(e) => e,
                 Err(_) => {
-                    println!("Invalid value found on quantity parameter: {}", quantity);
+                    let message = tr!("Nilai tidak valid pada parameter quantity: {}", "Invalid value found on quantity parameter: {}", quantity);
+                    output::fail(format, "invalid_value", message)?;
+                    return Ok(());
+                },
+            };
+
+
+            let line = OrderLine{
+                name: name.clone(),
+                quantity: q
+            };
+            let (order, sales) = match data.order(&[line], receipt.paid, &now){
+                Ok(sold) => sold,
+                Err(e) => {
+                    let message = match &e{
+                        OrderError::Item(ItemError::ItemNotFound(_)) => tr!("Tidak ada makanan dengan nama \"{}\"", "There is no item named \"{}\"", name),
+                        OrderError::Item(ItemError::NotEnoughStock(q1, q2)) => tr!(
+                            "Maaf, kuantitas makanan tidak mencukupi, hanya tersedia stok {} dari {}",
+                            "Sorry, there is not enough stock, only {} available of {}", locale.number(*q1), locale.number(*q2)
+                        ),
+                        e => e.to_string(),
+                    };
+                    output::fail(format, e.code(), message)?;
+                    return Ok(());
+                },
+            };
+
+            storage.save(data)?;
+            if let Err(message) = events::publish_saved(events, &data.order_events(&order, &sales)){
+                eprintln!("{}", message);
+            }
+
+            let sale = &sales[0];
+            let item = data.items.list.get(&sale.item_id);
+            if format != OutputFormat::Table{
+                output::emit_one(format, &SaleRow::new(sale, order.number, item.is_some_and(Item::is_low)))?;
+                receipt.print(data, &order, locale)?;
+                return Ok(());
+            }
+            println!("{}", tr!(
+                "Berhasil membeli makanan {} dengan kuantitas {} dan total {} (transaksi {}, pesanan #{})",
+                "Bought {} with quantity {} for a total of {} (transaction {}, order #{})",
+                name, locale.number(q), locale.money(sale.total), sale.id, order.number
+            ));
+            receipt.print(data, &order, locale)?;
+            if let Some(item) = item{
+                stock::print_low_stock_warning(item, locale);
+            }
+        },
+
+        // Sells every line of the order, or nothing if any line cannot be sold
+        Command::Order { lines, file, receipt } => {
+            let mut order = Vec::new();
+            if let Some(file) = file{
+                match order::parse_order(&std::fs::read_to_string(&file)?){
+                    Ok(mut parsed) => order.append(&mut parsed),
+                    Err(e) => {
+                        println!("{}", e);
+                        return Ok(());
+                    },
+                }
+            }
+            for line in &lines{
+                match order::parse_order_line(line){
+                    Ok(parsed) => order.push(parsed),
+                    Err(e) => {
+                        println!("{}", e);
+                        return Ok(());
+                    },
+                }
+            }
+
+            let (order, sales) = match data.order(&order, receipt.paid, &now){
+                Ok(sold) => sold,
+                Err(e) => {
+                    println!("{}", tr!("Pesanan gagal, tidak ada yang dibeli: {}", "Order failed, nothing was bought: {}", e));
                     return Ok(());
                 },
             };
+            storage.save(data)?;
+            if let Err(message) = events::publish_saved(events, &data.order_events(&order, &sales)){
+                eprintln!("{}", message);
+            }
+
+            println!("{}", tr!("Berhasil membeli pesanan #{}", "Bought order #{}", order.number));
+            for sale in &sales{
+                println!(
+                    "  {:<20} {:>4} x {:<13} {}", sale.item_name, locale.number(sale.quantity), locale.money(sale.unit_price), locale.money(sale.total)
+                );
+                if sale.discount > Money::ZERO{
+                    println!("    {} -{}", sale.promotion, locale.money(sale.discount));
+                }
+            }
+            println!("{}", tr!("  Total: {}", "  Total: {}", locale.money(Money::sum(sales.iter().map(|sale| sale.total))?)));
+            receipt.print(data, &order, locale)?;
+            for sale in &sales{
+                if let Some(item) = data.items.list.get(&sale.item_id){
+                    stock::print_low_stock_warning(item, locale);
+                }
+            }
+        },
+
+        // Prints the receipt of an earlier order again
+        Command::Receipt(ReceiptCommand::Reprint { number, date, format, output }) => {
+            let date = date.unwrap_or_else(|| now.split(' ').next().unwrap_or(&now).to_string());
+            let receipt = match data.orders.find_by_number(&date, number){
+                Some(order) => Receipt::for_order(data, order.id)?,
+                None => None,
+            };
 
-            let p = match price.parse::<i64>(){
+            match receipt{
+                Some(receipt) => receipt.write(format, locale, &output)?,
+                None => println!("{}", tr!("Pesanan #{} pada tanggal {} tidak ditemukan", "Order #{} on {} not found", number, date)),
+            }
+        },
+
+        // Deletes existing entry
+        Command::Delete { name, format } => {
+            match data.items.find_item(&name).and_then(|item| data.items.delete(&name).map(|_| item)){
+                Ok(item) => {
+                    storage.save(data)?;
+                    match format{
+                        OutputFormat::Table => println!("{}", tr!("Berhasil menghapus {} dari list makanan", "Deleted {} from the item list", name)),
+                        format => {
+                            let portions = data.recipes.portions(&data.ingredients);
+                            output::emit_one(format, &ItemRow::new(&item, portions.get(&item.id).copied()))?;
+                        },
+                    }
+                },
+                Err(e) => {
+                    let message = tr!("Makanan dengan nama \"{}\" tidak ditemukan", "Item named \"{}\" not found", name);
+                    output::fail(format, e.code(), message)?;
+                },
+            }
+        },
+        
+        // Reverses a whole sale, restocking the item and correcting the report of the sale date
+        Command::Void { id, reason } => {
+            let sale_id = match id.parse::<i64>(){
                 Ok(e) => e,
                 Err(_) => {
-                    println!("Invalid value found on price parameter: {}", price);
+                    println!("{}", tr!("Nilai tidak valid pada parameter id: {}", "Invalid value found on id parameter: {}", id));
                     return Ok(());
                 },
             };
-            items.add_or_update(&name, q, p);
-            save_items(opt.items_csv, items)?;
-            println!("Berhasil menambahkan {} ke list makanan", name);
+
+            match data.void(sale_id, &reason, &now){
+                Ok(refund) => {
+                    storage.save(data)?;
+                    if let Err(message) = events::publish_saved(events, &data.refund_events(std::slice::from_ref(&refund))){
+                        eprintln!("{}", message);
+                    }
+                    println!("{}", tr!(
+                        "Berhasil membatalkan transaksi {} ({} {}, total {})",
+                        "Voided transaction {} ({} {}, total {})",
+                        sale_id, refund.item_name, -refund.quantity, locale.money(refund.total.checked_neg()?)
+                    ));
+                },
+                Err(e) => println!("{}", tr!("Gagal membatalkan transaksi {}: {}", "Could not void transaction {}: {}", sale_id, e)),
+            }
         },
 
-        // Updates existing item, by reducing the quantity then updates the report with quantity * price for that particular date
-        Command::Buy { name, quantity } => {
+        // Refunds part of today's sales of an item
+        Command::Refund { name, quantity, reason } => {
+            let q = match quantity.parse::<i64>(){
+                Ok(e) if e > 0 => e,
+                _ => {
+                    println!("{}", tr!("Nilai tidak valid pada parameter quantity: {}", "Invalid value found on quantity parameter: {}", quantity));
+                    return Ok(());
+                },
+            };
+
+            match data.refund_today(&name, q, &reason, &now){
+                Ok(refunds) => {
+                    storage.save(data)?;
+                    if let Err(message) = events::publish_saved(events, &data.refund_events(&refunds)){
+                        eprintln!("{}", message);
+                    }
+                    let total = Money::sum(refunds.iter().map(|refund| refund.total))?.checked_neg()?;
+                    println!("{}", tr!(
+                        "Berhasil refund makanan {} dengan kuantitas {} dan total {}",
+                        "Refunded {} with quantity {} for a total of {}", name, locale.number(q), locale.money(total)
+                    ));
+                },
+                Err(e) => println!("{}", tr!("Gagal refund makanan {}: {}", "Could not refund {}: {}", name, e)),
+            }
+        },
 
+        // Show Reports
+        Command::Report { range, group_by, format, view } => {
+            // Dates given after "items" or "margin" win over the ones before it
+            let view_range = match &view{
+                None => Ok((None, None)),
+                Some(ReportView::Items { range, .. } | ReportView::Margin { range, .. }) => range.parse(),
+            };
+            let ((from, to), (view_from, view_to)) = match range.parse().and_then(|range| Ok((range, view_range?))){
+                Ok(ranges) => ranges,
+                Err(message) => {
+                    output::fail(format, "invalid_value", message)?;
+                    return Ok(());
+                },
+            };
+            let (from, to) = (view_from.or(from), view_to.or(to));
+            match (view, format){
+                (None, OutputFormat::Table) => data.reports.print_reports(from, to, group_by, locale)?,
+                (None, format) => {
+                    let rows = data.reports.summary(from, to, group_by)?.iter().map(ReportRow::new).collect::<Result<Vec<_>, _>>()?;
+                    output::emit_rows(format, &rows)?;
+                },
+                (Some(ReportView::Items { sort_by, top, .. }), format) => {
+                    let mut rows = data.ledger.item_sales(from, to, sort_by)?;
+                    if let Some(top) = top{
+                        rows.truncate(top);
+                    }
+                    match format{
+                        OutputFormat::Table => item_report::print_item_sales(&rows, locale),
+                        format => output::emit_rows(format, &rows.iter().map(ItemSalesRow::new).collect::<Vec<_>>())?,
+                    }
+                },
+                (Some(ReportView::Margin { by, .. }), format) => {
+                    let (label, rows) = match by{
+                        MarginBy::Item => (tr!("Nama", "Name"), data.ledger.item_margins(from, to)?),
+                        MarginBy::Period(period) => (tr!("Periode", "Period"), data.reports.period_margins(from, to, period)?),
+                    };
+                    match format{
+                        OutputFormat::Table => margin::print_margins(&label, &rows, locale)?,
+                        format => output::emit_rows(format, &rows.iter().map(MarginRow::new).collect::<Result<Vec<_>, _>>()?)?,
+                    }
+                },
+            }
+        },
+
+        // Adds delivered units to the stock and records the delivery
+        Command::Restock { name, quantity, cost, supplier, note } => {
             let q = match quantity.parse::<i64>(){
-                Ok(e) => e,
-                Err(_) => {
-                    println!("Invalid value found on quantity parameter: {}", quantity);
+                Ok(e) if e > 0 => e,
+                _ => {
+                    println!("{}", tr!("Nilai tidak valid pada parameter quantity: {}", "Invalid value found on quantity parameter: {}", quantity));
+                    return Ok(());
+                },
+            };
+            let c = match cost.as_deref().map(str::parse::<Money>){
+                None => Money::ZERO,
+                Some(Ok(e)) if !e.is_negative() => e,
+                _ => {
+                    println!("{}", tr!("Nilai tidak valid pada parameter cost: {}", "Invalid value found on cost parameter: {}", cost.unwrap_or_default()));
                     return Ok(());
                 },
             };
 
+            match data.restock(&name, q, c, &supplier, &note, &now){
+                Ok(item) => {
+                    storage.save(data)?;
+                    println!("{}", tr!(
+                        "Berhasil menambah stok {} sebanyak {}, stok sekarang {}",
+                        "Added {1} units to the stock of {0}, stock is now {2}", item.name, locale.number(q), locale.number(item.quantity)
+                    ));
+                },
+                Err(ItemError::ItemNotFound(_)) => println!("{}", tr!("Tidak ada makanan dengan nama \"{}\"", "There is no item named \"{}\"", name)),
+                Err(e) => println!("{}", e),
+            }
+        },
 
-            let income = match items.buy(&name, q){
-                Ok(price) => { 
-                    price * q
-                }
-                Err(e) => {
-                    match e{
-                        ItemError::ItemNotFound(_) => {
-                            println!("Tidak ada makanan dengan nama \"{}\"", name);
+        Command::Ingredient(command) => {
+            let result = match command{
+                IngredientCommand::Add { name, quantity, unit, cost } => {
+                    let q = match quantity.as_deref(){
+                        None | Some("-") => None,
+                        Some(given) => match given.parse::<i64>(){
+                            Ok(e) if e >= 0 => Some(e),
+                            _ => {
+                                println!("{}", tr!("Nilai tidak valid pada parameter quantity: {}", "Invalid value found on quantity parameter: {}", given));
+                                return Ok(());
+                            },
+                        },
+                    };
+                    let c = match cost.as_deref().map(str::parse::<Money>){
+                        None => None,
+                        Some(Ok(e)) if !e.is_negative() => Some(e),
+                        _ => {
+                            println!("{}", tr!("Nilai tidak valid pada parameter cost: {}", "Invalid value found on cost parameter: {}", cost.unwrap_or_default()));
+                            return Ok(());
+                        },
+                    };
+                    data.ingredients.set(&name, q, unit.as_deref(), c)
+                },
+                IngredientCommand::Restock { name, quantity, cost } => {
+                    let q = match quantity.parse::<i64>(){
+                        Ok(e) if e > 0 => e,
+                        _ => {
+                            println!("{}", tr!("Nilai tidak valid pada parameter quantity: {}", "Invalid value found on quantity parameter: {}", quantity));
                             return Ok(());
                         },
-                        ItemError::NotEnoughStock(q1, q2) => {
-                            println!("Maaf, kuantitas makanan tidak mencukupi, hanya tersedia stok {} dari {}", q1, q2);
+                    };
+                    let c = match cost.as_deref().map(str::parse::<Money>){
+                        None => Money::ZERO,
+                        Some(Ok(e)) if !e.is_negative() => e,
+                        _ => {
+                            println!("{}", tr!("Nilai tidak valid pada parameter cost: {}", "Invalid value found on cost parameter: {}", cost.unwrap_or_default()));
                             return Ok(());
                         },
-                        _ => {return Ok(());}
-                        
-                    }
+                    };
+                    data.ingredients.restock(&name, q, c)
+                },
+                IngredientCommand::List {  } => {
+                    data.ingredients.print_ingredients(locale);
+                    return Ok(());
                 },
             };
 
-            reports.add_or_update(&curr_date, q, income, 0, false);
+            match result{
+                Ok(ingredient) => {
+                    storage.save(data)?;
+                    println!("{}", tr!("Stok bahan {} sekarang {} {}", "Stock of {} is now {} {}", ingredient.name, locale.number(ingredient.quantity), ingredient.unit));
+                },
+                Err(e) => println!("{}", e),
+            }
+        },
 
-            save_items(opt.items_csv, items)?;
-            save_reports(opt.reports_csv, reports)?;
+        Command::Recipe(command) => {
+            let item_name = match &command{
+                RecipeCommand::Set { item, .. } | RecipeCommand::Show { item } => item.clone(),
+            };
+            let item = match data.items.find_item(&item_name){
+                Ok(item) => item,
+                Err(_) => {
+                    println!("{}", tr!("Tidak ada makanan dengan nama \"{}\"", "There is no item named \"{}\"", item_name));
+                    return Ok(());
+                },
+            };
 
-            println!("Berhasil membeli makanan {} dengan kuantitas {} dan total {}", name, quantity, income);
+            if let RecipeCommand::Set { ingredients, .. } = command{
+                let mut lines = Vec::new();
+                for ingredient in &ingredients{
+                    match order::parse_order_line(ingredient){
+                        Ok(line) => lines.push((line.name, line.quantity)),
+                        Err(_) => {
+                            println!("{}", tr!("Nilai tidak valid pada parameter ingredients: {}", "Invalid value found on ingredients parameter: {}", ingredient));
+                            return Ok(());
+                        },
+                    }
+                }
+                if let Err(e) = data.set_recipe(&item, &lines){
+                    println!("{}", e);
+                    return Ok(());
+                }
+                storage.save(data)?;
+            }
+            data.print_recipe(&item, locale);
         },
 
-        // Deletes existing entry
-        Command::Delete { name } => {
-            match items.delete(&name){
-                Ok(_) => {
-                    save_items(opt.items_csv, items)?;
-                    println!("Berhasil menghapus {} dari list makanan", name);
+        Command::Supplier(command) => {
+            match command{
+                SupplierCommand::Add { name, phone, note } => match data.suppliers.add(&name, &phone, &note){
+                    Ok(supplier) => {
+                        storage.save(data)?;
+                        println!("{}", tr!("Berhasil menambahkan supplier {} ({})", "Added supplier {} ({})", supplier.name, supplier.id));
+                    },
+                    Err(e) => println!("{}", e),
+                },
+                SupplierCommand::Item { supplier, item, cost } => {
+                    let c = match cost.parse::<Money>(){
+                        Ok(e) if !e.is_negative() => e,
+                        _ => {
+                            println!("{}", tr!("Nilai tidak valid pada parameter cost: {}", "Invalid value found on cost parameter: {}", cost));
+                            return Ok(());
+                        },
+                    };
+                    let found = data.suppliers.find(&supplier).map(|supplier| supplier.id)
+                        .and_then(|id| Ok((id, data.items.find_item(&item)?)));
+                    match found.and_then(|(id, item)| data.suppliers.set_cost(id, &item, c)){
+                        Ok(()) => {
+                            storage.save(data)?;
+                            println!("{}", tr!("Berhasil mencatat {} dari {} seharga {}", "Recorded {} from {} at {}", item, supplier, locale.money(c)));
+                        },
+                        Err(e) => println!("{}", e),
+                    }
                 },
-                Err(_) => println!("Makanan dengan nama \"{}\" tidak ditemukan", name),
+                SupplierCommand::List {  } => data.suppliers.print_suppliers(locale),
             }
         },
-        
-        // Show Reports
-        Command::Report {  } => {
-            reports.print_reports();
+
+        Command::Purchase(command) => {
+            let today = now.split(' ').next().unwrap_or(&now).to_string();
+            let parse_id = |id: &str| match id.parse::<i64>(){
+                Ok(id) => Some(id),
+                Err(_) => {
+                    println!("{}", tr!("Nilai tidak valid pada parameter id: {}", "Invalid value found on id parameter: {}", id));
+                    None
+                },
+            };
+            let parse_lines = |lines: &[String]| -> Option<Vec<OrderLine>>{
+                match lines.iter().map(|line| order::parse_order_line(line)).collect(){
+                    Ok(lines) => Some(lines),
+                    Err(e) => {
+                        println!("{}", e);
+                        None
+                    },
+                }
+            };
+
+            let result = match command{
+                PurchaseCommand::Create { supplier, lines, expected } => {
+                    let expected = match expected{
+                        Some(date) if period::parse_date(&date).is_none() => {
+                            println!("{}", tr!("Nilai tidak valid pada parameter expected: {}", "Invalid value found on expected parameter: {}", date));
+                            return Ok(());
+                        },
+                        expected => expected.unwrap_or_default(),
+                    };
+                    let Some(lines) = parse_lines(&lines) else { return Ok(()) };
+                    data.create_purchase(&supplier, &lines, &expected, &now)
+                },
+                PurchaseCommand::Send { id } => {
+                    let Some(id) = parse_id(&id) else { return Ok(()) };
+                    data.send_purchase(id)
+                },
+                PurchaseCommand::Receive { id, lines } => {
+                    let Some(id) = parse_id(&id) else { return Ok(()) };
+                    let Some(lines) = parse_lines(&lines) else { return Ok(()) };
+                    data.receive_purchase(id, &lines, &now)
+                },
+                PurchaseCommand::Show { id } => {
+                    let Some(id) = parse_id(&id) else { return Ok(()) };
+                    match data.purchases.find(id){
+                        Ok(order) => data.purchases.print_order(&data.suppliers, order, locale)?,
+                        Err(e) => println!("{}", e),
+                    }
+                    return Ok(());
+                },
+                PurchaseCommand::List { all } => {
+                    let orders = if all { data.purchases.list.iter().collect() } else { data.purchases.open() };
+                    data.purchases.print_orders(&data.suppliers, &orders, &today, locale)?;
+                    return Ok(());
+                },
+            };
+
+            match result{
+                Ok(order) => {
+                    storage.save(data)?;
+                    data.purchases.print_order(&data.suppliers, &order, locale)?;
+                },
+                Err(e) => println!("{}", tr!("Purchase order gagal: {}", "Purchase order failed: {}", e)),
+            }
+        },
+
+        Command::Stock(StockCommand::Alerts {  }) => {
+            data.items.print_alerts(locale);
+        },
+
+        Command::Stock(StockCommand::History { name }) => {
+            data.movements.print_movements(name.as_deref(), locale);
+        },
+        // Only started from the command line, they take over the terminal or the process. The shell recovers before every command anyway
+        Command::Serve { .. } | Command::Pos {} | Command::Shell {} | Command::Recover {} => {
+            println!("{}", tr!("Perintah ini tidak bisa dijalankan dari shell", "This command cannot be run from the shell"));
         },
 
         // Show Item List
-        Command::List {  } => {
-            items.print_items();
+        Command::List { format } => {
+            let portions = data.recipes.portions(&data.ingredients);
+            match format{
+                OutputFormat::Table => data.items.print_items(&portions, locale),
+                format => {
+                    let rows: Vec<_> = data.items.get_item_list().iter().map(|item| ItemRow::new(item, portions.get(&item.id).copied())).collect();
+                    output::emit_rows(format, &rows)?;
+                },
+            }
         },
     }
     Ok(())
 }
 
 fn main(){
+    i18n::set_lang(i18n::requested_lang(std::env::args()));
     let opt = Opt::from_args();
+    let format = opt.cmd.format();
     if let Err(e) = run(opt){
-        println!("an error occured: {}", e);
+        // Nothing is left to report to when writing the failure fails too
+        let _ = output::fail(format, e.code(), tr!("terjadi kesalahan: {}", "an error occurred: {}", e));
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Items, ItemError, Reports};
+    use crate::{Items, ItemError, OrderLine, RefundError, Reports, Restaurant};
+    use crate::money::{rp, Money, MoneyError};
     
     #[test]
     fn add_items_test(){
         let mut items = Items::new();
-        items.add("Risoles", 164, 25000, 0, false);
-        items.add("Ikan Asin", 512, 25182, 0, false);
-        items.add("Sayur Asin", 59999, 9999999, 0, false);
-        items.add("Telur", 9999999999, 99999999999, 0, false);
+        items.add("Risoles", 164, rp(25000), 0, false);
+        items.add("Ikan Asin", 512, rp(25182), 0, false);
+        items.add("Sayur Asin", 59999, rp(9999999), 0, false);
+        items.add("Telur", 9999999999, rp(99999999999), 0, false);
 
         assert_eq!(items.list.get(&0).unwrap().name, "risoles");
         assert_eq!(items.list.get(&1).unwrap().name, "ikan asin");
@@ -603,44 +1568,44 @@ mod tests {
         assert_eq!(items.list.get(&2).unwrap().quantity, 59999);
         assert_eq!(items.list.get(&3).unwrap().quantity, 9999999999);
 
-        assert_eq!(items.list.get(&0).unwrap().price, 25000);
-        assert_eq!(items.list.get(&1).unwrap().price, 25182);
-        assert_eq!(items.list.get(&2).unwrap().price, 9999999);
-        assert_eq!(items.list.get(&3).unwrap().price, 99999999999);
+        assert_eq!(items.list.get(&0).unwrap().price, rp(25000));
+        assert_eq!(items.list.get(&1).unwrap().price, rp(25182));
+        assert_eq!(items.list.get(&2).unwrap().price, rp(9999999));
+        assert_eq!(items.list.get(&3).unwrap().price, rp(99999999999));
     }
 
     #[test]
     fn update_items_test(){
         let mut items = Items::new();
-        items.add("Risoles", 164, 25000, 0, false);
-        items.add("Ikan Asin", 512, 25182, 0, false);
-        items.add("Sayur Asin", 59999, 9999999, 0, false);
-        items.add("Telur", 9999999999, 99999999999, 0, false);
+        items.add("Risoles", 164, rp(25000), 0, false);
+        items.add("Ikan Asin", 512, rp(25182), 0, false);
+        items.add("Sayur Asin", 59999, rp(9999999), 0, false);
+        items.add("Telur", 9999999999, rp(99999999999), 0, false);
 
-        items.update(0, "Risoles", 100, 200);
-        items.update(1, "Ikan Asin", 500, 600);
-        items.update(2, "Sayur Asin", 900, 1);
-        items.update(3, "Telur", 18953279, 83274914);
+        items.update(0, "Risoles", 100, rp(200));
+        items.update(1, "Ikan Asin", 500, rp(600));
+        items.update(2, "Sayur Asin", 900, rp(1));
+        items.update(3, "Telur", 18953279, rp(83274914));
 
         assert_eq!(items.list.get(&0).unwrap().quantity, 100);
         assert_eq!(items.list.get(&1).unwrap().quantity, 500);
         assert_eq!(items.list.get(&2).unwrap().quantity, 900);
         assert_eq!(items.list.get(&3).unwrap().quantity, 18953279);
 
-        assert_eq!(items.list.get(&0).unwrap().price, 200);
-        assert_eq!(items.list.get(&1).unwrap().price, 600);
-        assert_eq!(items.list.get(&2).unwrap().price, 1);
-        assert_eq!(items.list.get(&3).unwrap().price, 83274914);
+        assert_eq!(items.list.get(&0).unwrap().price, rp(200));
+        assert_eq!(items.list.get(&1).unwrap().price, rp(600));
+        assert_eq!(items.list.get(&2).unwrap().price, rp(1));
+        assert_eq!(items.list.get(&3).unwrap().price, rp(83274914));
     }
 
     #[test]
     #[allow(unused_must_use)]
     fn buy_items_test(){
         let mut items = Items::new();
-        items.add("Risoles", 164, 25000, 0, false);
-        items.add("Ikan Asin", 512, 25182, 0, false);
-        items.add("Sayur Asin", 59999, 9999999, 0, false);
-        items.add("Telur", 9999999999, 99999999999, 0, false);
+        items.add("Risoles", 164, rp(25000), 0, false);
+        items.add("Ikan Asin", 512, rp(25182), 0, false);
+        items.add("Sayur Asin", 59999, rp(9999999), 0, false);
+        items.add("Telur", 9999999999, rp(99999999999), 0, false);
         
         items.buy("Risoles", 1);
         items.buy("Ikan Asin", 50);
@@ -657,10 +1622,10 @@ mod tests {
     #[allow(unused_must_use)]
     fn delete_items_test(){
         let mut items = Items::new();
-        items.add("Risoles", 164, 25000, 0, false);
-        items.add("Ikan Asin", 512, 25182, 0, false);
-        items.add("Sayur Asin", 59999, 9999999, 0, false);
-        items.add("Telur", 9999999999, 99999999999, 0, false);
+        items.add("Risoles", 164, rp(25000), 0, false);
+        items.add("Ikan Asin", 512, rp(25182), 0, false);
+        items.add("Sayur Asin", 59999, rp(9999999), 0, false);
+        items.add("Telur", 9999999999, rp(99999999999), 0, false);
 
         assert_eq!(&items.find_item("Risoles").unwrap(), items.list.get(&0).unwrap());
 
@@ -675,10 +1640,10 @@ mod tests {
     #[test]
     fn add_reports_test(){
         let mut reports = Reports::new();
-        reports.add_or_update("22-11-2022", 512, 512, 0, false);
-        reports.add_or_update("01-02-2022", 761, 314, 1, false);
-        reports.add_or_update("04-07-2022", 71234, 233, 2, false);
-        reports.add_or_update("28-04-2022", 123456, 51, 3, false);
+        reports.add_or_update("22-11-2022", 512, rp(512), 0, false).unwrap();
+        reports.add_or_update("01-02-2022", 761, rp(314), 1, false).unwrap();
+        reports.add_or_update("04-07-2022", 71234, rp(233), 2, false).unwrap();
+        reports.add_or_update("28-04-2022", 123456, rp(51), 3, false).unwrap();
 
         assert_eq!(reports.list.get(&0).unwrap().date, "22-11-2022".to_string());
         assert_eq!(reports.list.get(&1).unwrap().date, "01-02-2022".to_string());
@@ -688,15 +1653,15 @@ mod tests {
     #[test]
     fn update_reports_test(){
         let mut reports = Reports::new();
-        reports.add_or_update("22-11-2022", 512, 5, 0, false);
-        reports.add_or_update("01-02-2022", 761, 10, 1, false);
-        reports.add_or_update("04-07-2022", 71234, 20, 2, false);
-        reports.add_or_update("28-04-2022", 123456, 30, 3, false);
+        reports.add_or_update("22-11-2022", 512, rp(5), 0, false).unwrap();
+        reports.add_or_update("01-02-2022", 761, rp(10), 1, false).unwrap();
+        reports.add_or_update("04-07-2022", 71234, rp(20), 2, false).unwrap();
+        reports.add_or_update("28-04-2022", 123456, rp(30), 3, false).unwrap();
 
-        reports.add_or_update("22-11-2022", 612, 5, 0, false);
-        reports.add_or_update("01-02-2022", 515, 6, 1, false);
-        reports.add_or_update("04-07-2022", 3571234, 7, 2, false);
-        reports.add_or_update("28-04-2022", 1243611, 8, 3, false);
+        reports.add_or_update("22-11-2022", 612, rp(5), 0, false).unwrap();
+        reports.add_or_update("01-02-2022", 515, rp(6), 1, false).unwrap();
+        reports.add_or_update("04-07-2022", 3571234, rp(7), 2, false).unwrap();
+        reports.add_or_update("28-04-2022", 1243611, rp(8), 3, false).unwrap();
 
         // quantity * income
         assert_eq!(reports.list.get(&0).unwrap().quantity, 1124);
@@ -704,9 +1669,39 @@ mod tests {
         assert_eq!(reports.list.get(&2).unwrap().quantity, 3642468);
         assert_eq!(reports.list.get(&3).unwrap().quantity, 1367067);
         
-        assert_eq!(reports.list.get(&0).unwrap().income, 5620);
-        assert_eq!(reports.list.get(&1).unwrap().income, 10700);
-        assert_eq!(reports.list.get(&2).unwrap().income, 26423318);
-        assert_eq!(reports.list.get(&3).unwrap().income, 13652568);
+        assert_eq!(reports.list.get(&0).unwrap().income, rp(5620));
+        assert_eq!(reports.list.get(&1).unwrap().income, rp(10700));
+        assert_eq!(reports.list.get(&2).unwrap().income, rp(26423318));
+        assert_eq!(reports.list.get(&3).unwrap().income, rp(13652568));
+
+        // An income too large to keep is refused and the report is left as it was
+        assert_eq!(reports.add_or_update("22-11-2022", 9999999999, rp(99999999999), 0, false), Err(MoneyError::Overflow));
+        assert_eq!(reports.list.get(&0).unwrap().income, rp(5620));
+    }
+
+    #[test]
+    fn refund_and_void_test(){
+        let mut data = Restaurant::new();
+        data.items.add("Risoles", 10, rp(25000), 0, false);
+
+        for q in [2, 3]{
+            let line = OrderLine{ name: "Risoles".to_string(), quantity: q };
+            data.order(&[line], None, "2022-11-22 10:00:00").unwrap();
+        }
+
+        // Newest sale is refunded first, then the rest comes from the older one
+        let refunds = data.refund_today("risoles", 4, "salah input", "2022-11-22 12:00:00").unwrap();
+        assert_eq!(refunds.iter().map(|refund| (refund.refund_of, refund.quantity)).collect::<Vec<_>>(), vec![(Some(1), -3), (Some(0), -1)]);
+        assert_eq!(data.items.find_item("risoles").unwrap().quantity, 9);
+        assert_eq!(data.reports.find_report("2022-11-22").unwrap().quantity, 1);
+        assert_eq!(data.reports.find_report("2022-11-22").unwrap().income, rp(25000));
+
+        assert_eq!(data.refund_today("risoles", 2, "", "2022-11-22 12:00:00"), Err(RefundError::NotEnoughSold(1, 2)));
+
+        data.void(0, "", "2022-11-22 12:05:00").unwrap();
+        assert_eq!(data.void(0, "", "2022-11-22 12:05:00"), Err(RefundError::AlreadyRefunded(0)));
+        assert_eq!(data.void(2, "", "2022-11-22 12:05:00"), Err(RefundError::SaleNotFound(2)));
+        assert_eq!(data.items.find_item("risoles").unwrap().quantity, 10);
+        assert_eq!(data.reports.find_report("2022-11-22").unwrap().income, Money::ZERO);
     }
 }
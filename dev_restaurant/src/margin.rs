@@ -0,0 +1,216 @@
+use std::str::FromStr;
+use chrono::NaiveDate;
+
+use crate::{Item, ItemError, Items, Ledger, Reports, Restaurant};
+use crate::i18n::tr;
+use crate::item_report::SortBy;
+use crate::locale::Locale;
+use crate::money::{Money, MoneyError};
+use crate::period::Period;
+
+// Rows the margin report is split into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MarginBy{
+    Item,
+    Period(Period)
+}
+
+impl FromStr for MarginBy{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        match s.to_lowercase().as_str(){
+            "item" | "menu" => Ok(MarginBy::Item),
+            _ => s.parse::<Period>()
+                .map(MarginBy::Period)
+                .map_err(|_| tr!(
+                    "baris margin \"{}\" tidak dikenal, seharusnya item, day, week, month atau year",
+                    "unknown margin rows \"{}\", expected item, day, week, month or year", s
+                )),
+        }
+    }
+}
+
+// Revenue is net of discounts, service charge and tax, cost is what the units sold cost when they were sold
+#[derive(Clone, Debug, PartialEq)]
+pub struct Margin{
+    pub label: String,
+    pub quantity: i64,
+    pub revenue: Money,
+    pub cost: Money
+}
+
+impl Margin{
+    pub fn profit(&self) -> Result<Money, MoneyError>{
+        self.revenue.checked_sub(self.cost)
+    }
+
+    // Gross profit in hundredths of a percent of the revenue, None when nothing was earned
+    pub fn margin(&self) -> Option<i64>{
+        self.profit().ok()?.ratio(self.revenue)
+    }
+}
+
+// Weighted average cost of the stock on hand and a delivery, rounded half up.
+// A delivery without a cost keeps the current one, stock without a known cost takes the delivery cost
+pub fn average_cost(stock: i64, cost: Money, quantity: i64, unit_cost: Money) -> Result<Money, MoneyError>{
+    if unit_cost.is_zero(){
+        return Ok(cost);
+    }
+    if stock <= 0 || cost.is_zero(){
+        return Ok(unit_cost);
+    }
+    Money::sum([cost.checked_mul(stock)?, unit_cost.checked_mul(quantity)?])?.scale(1, stock + quantity)
+}
+
+impl Items{
+    pub fn set_cost(&mut self, name: &str, cost: Money) -> Result<Item, ItemError>{
+        if cost.is_negative(){
+            return Err(ItemError::NegativeAmount(cost));
+        }
+        let id = self.find_item(name)?.id;
+        let item = self.list.get_mut(&id).ok_or_else(|| ItemError::ItemNotFound(name.to_string()))?;
+        item.cost = cost;
+        Ok(item.clone())
+    }
+}
+
+impl Restaurant{
+    // What one unit of an item costs right now: its recipe ingredients, or the cost kept on the item
+    pub fn unit_cost(&self, item: &Item) -> Result<Money, MoneyError>{
+        if self.recipes.has_recipe(item.id){
+            self.recipes.cost(item.id, &self.ingredients)
+        } else {
+            Ok(item.cost)
+        }
+    }
+}
+
+impl Ledger{
+    // Margin of every item sold between `from` and `to` (both included), most gross profit first
+    pub fn item_margins(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<Vec<Margin>, MoneyError>{
+        let mut rows: Vec<Margin> = self.item_sales(from, to, SortBy::Revenue)?.into_iter()
+            .map(|row| Margin{
+                label: row.name,
+                quantity: row.quantity,
+                revenue: row.revenue,
+                cost: row.cost
+            })
+            .collect();
+        rows.sort_by(|a, b| b.profit().ok().cmp(&a.profit().ok()).then_with(|| a.label.cmp(&b.label)));
+        Ok(rows)
+    }
+}
+
+impl Reports{
+    // Margin per period from the daily reports, oldest first
+    pub fn period_margins(&self, from: Option<NaiveDate>, to: Option<NaiveDate>, period: Period) -> Result<Vec<Margin>, MoneyError>{
+        Ok(self.summary(from, to, period)?.into_iter()
+            .map(|report| Margin{
+                label: report.date,
+                quantity: report.quantity,
+                revenue: report.income,
+                cost: report.cost
+            })
+            .collect())
+    }
+}
+
+fn format_margin(margin: Option<i64>, locale: Locale) -> String{
+    match margin{
+        Some(margin) => locale.percent(margin),
+        None => "-".to_string(),
+    }
+}
+
+// Prints the rows and their total, `label` names the first column
+pub fn print_margins(label: &str, rows: &[Margin], locale: Locale) -> Result<(), MoneyError>{
+    if rows.is_empty(){
+        println!("{}", tr!("Tidak ada penjualan pada periode tersebut", "No sales in that period"));
+        return Ok(());
+    }
+    let print_row = |row: &Margin| -> Result<(), MoneyError>{
+        println!(
+            "{:<16}| {:<13}| {:<14}| {:<14}| {:<14}| {}",
+            row.label, locale.number(row.quantity), locale.money(row.revenue), locale.money(row.cost), locale.money(row.profit()?),
+            format_margin(row.margin(), locale)
+        );
+        Ok(())
+    };
+
+    println!("{}", tr!(
+        "{:<16}|    Jumlah    |  Pendapatan   |      HPP      |  Laba Kotor   | Margin",
+        "{:<16}|   Quantity   |    Revenue    |     COGS      | Gross Profit  | Margin",
+        label
+    ));
+    let mut total = Margin{
+        label: "Total".to_string(),
+        quantity: 0,
+        revenue: Money::ZERO,
+        cost: Money::ZERO
+    };
+    for row in rows{
+        print_row(row)?;
+        total.quantity += row.quantity;
+        total.revenue = total.revenue.checked_add(row.revenue)?;
+        total.cost = total.cost.checked_add(row.cost)?;
+    }
+    println!("{}", "-".repeat(90));
+    print_row(&total)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Restaurant;
+    use crate::locale::Locale;
+    use crate::money::rp;
+    use crate::order::parse_order;
+    use crate::period::{parse_date, Period};
+    use super::{average_cost, format_margin};
+
+    #[test]
+    fn average_cost_test(){
+        assert_eq!(average_cost(10, rp(18000), 30, rp(20000)), Ok(rp(19500)));
+        assert_eq!(average_cost(3, rp(1000), 0, rp(0)), Ok(rp(1000)));
+        // Nothing known about the old stock
+        assert_eq!(average_cost(0, rp(5000), 10, rp(6000)), Ok(rp(6000)));
+        assert_eq!(average_cost(10, rp(0), 10, rp(6000)), Ok(rp(6000)));
+        assert_eq!(format_margin(Some(-1250), Locale::English), "-12.50%");
+        assert_eq!(format_margin(Some(7800), Locale::Indonesian), "78,00%");
+        assert_eq!(format_margin(None, Locale::Indonesian), "-");
+    }
+
+    #[test]
+    fn margin_test(){
+        let mut data = Restaurant::new();
+        data.items.add("Risoles", 0, rp(5000), 0, false);
+        data.items.add("Nasi Goreng", 0, rp(25000), 0, false);
+        data.restock("risoles", 10, rp(2000), "", "", "2022-11-21 08:00:00").unwrap();
+        data.ingredients.set("Nasi", Some(1000), Some("gram"), Some(rp(15))).unwrap();
+        data.ingredients.set("Telur", Some(10), Some("butir"), Some(rp(2500))).unwrap();
+        let nasi_goreng = data.items.find_item("nasi goreng").unwrap();
+        data.set_recipe(&nasi_goreng, &[("nasi".to_string(), 200), ("telur".to_string(), 1)]).unwrap();
+        assert_eq!(data.unit_cost(&nasi_goreng), Ok(rp(5500)));
+
+        let (_, sales) = data.order(&parse_order("risoles=4\nnasi goreng=2").unwrap(), None, "2022-11-22 10:00:00").unwrap();
+        assert_eq!((sales[0].cost, sales[1].cost), (rp(8000), rp(11000)));
+
+        // Later cost changes do not touch what was already sold
+        data.restock("risoles", 10, rp(4000), "", "", "2022-11-23 08:00:00").unwrap();
+        assert_eq!(data.items.find_item("risoles").unwrap().cost, rp(3250));
+        data.order(&parse_order("risoles=1").unwrap(), None, "2022-11-23 10:00:00").unwrap();
+        data.void(sales[0].id, "", "2022-11-23 11:00:00").unwrap();
+
+        let rows = data.ledger.item_margins(None, None).unwrap();
+        let rows: Vec<_> = rows.iter().map(|row| (row.label.as_str(), row.revenue, row.cost, row.profit().unwrap(), row.margin())).collect();
+        assert_eq!(rows, vec![
+            ("nasi goreng", rp(50000), rp(11000), rp(39000), Some(7800)),
+            ("risoles", rp(5000), rp(3250), rp(1750), Some(3500)),
+        ]);
+
+        // The voided sale is taken off the day it was sold
+        let rows = data.reports.period_margins(parse_date("2022-11-22"), None, Period::Day).unwrap();
+        let rows: Vec<_> = rows.iter().map(|row| (row.label.as_str(), row.cost, row.profit().unwrap())).collect();
+        assert_eq!(rows, vec![("2022-11-22", rp(11000), rp(39000)), ("2022-11-23", rp(3250), rp(1750))]);
+    }
+}
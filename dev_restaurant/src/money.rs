@@ -0,0 +1,193 @@
+use std::{
+    fmt,
+    str::FromStr
+};
+use thiserror::Error;
+
+use crate::i18n::tr;
+
+// Sen in one rupiah
+const SEN: i64 = 100;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum MoneyError{
+    #[error("{}", tr!("jumlah uang terlalu besar", "amount is too large"))]
+    Overflow,
+
+    #[error("{}", tr!("jumlah uang tidak valid: \"{}\", contoh 25000 atau 2500.50", "invalid amount: \"{}\", e.g. 25000 or 2500.50", .0))]
+    Invalid(String),
+}
+
+impl MoneyError{
+    pub fn code(&self) -> &'static str{
+        match self{
+            MoneyError::Overflow => "amount_too_large",
+            MoneyError::Invalid(_) => "invalid_amount",
+        }
+    }
+}
+
+// An amount of money in sen. Arithmetic is checked, overflow is an error instead of a wrapped value
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+impl Money{
+    pub const ZERO: Money = Money(0);
+
+    pub fn from_sen(sen: i64) -> Self{
+        Money(sen)
+    }
+
+    pub fn sen(self) -> i64{
+        self.0
+    }
+
+    pub fn rupiah(rupiah: i64) -> Result<Self, MoneyError>{
+        rupiah.checked_mul(SEN).map(Money).ok_or(MoneyError::Overflow)
+    }
+
+    pub fn is_zero(self) -> bool{
+        self.0 == 0
+    }
+
+    pub fn is_negative(self) -> bool{
+        self.0 < 0
+    }
+
+    pub fn checked_add(self, other: Money) -> Result<Money, MoneyError>{
+        self.0.checked_add(other.0).map(Money).ok_or(MoneyError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, MoneyError>{
+        self.0.checked_sub(other.0).map(Money).ok_or(MoneyError::Overflow)
+    }
+
+    pub fn checked_neg(self) -> Result<Money, MoneyError>{
+        self.0.checked_neg().map(Money).ok_or(MoneyError::Overflow)
+    }
+
+    // Price of `quantity` units
+    pub fn checked_mul(self, quantity: i64) -> Result<Money, MoneyError>{
+        self.0.checked_mul(quantity).map(Money).ok_or(MoneyError::Overflow)
+    }
+
+    // amount * numerator / denominator rounded half up to the sen, the denominator must be above 0
+    pub fn scale(self, numerator: i64, denominator: i64) -> Result<Money, MoneyError>{
+        let denominator = denominator as i128;
+        let rounded = (self.0 as i128 * numerator as i128 + denominator / 2).div_euclid(denominator);
+        i64::try_from(rounded).map(Money).map_err(|_| MoneyError::Overflow)
+    }
+
+    // amount * numerator / denominator cut down to the sen, used for pro rata shares where the last share takes the rest
+    pub fn share(self, numerator: i64, denominator: i64) -> Result<Money, MoneyError>{
+        let share = self.0 as i128 * numerator as i128 / denominator as i128;
+        i64::try_from(share).map(Money).map_err(|_| MoneyError::Overflow)
+    }
+
+    // Rounded half up to whole rupiah, the smallest amount charged or paid back in cash
+    pub fn round_rupiah(self) -> Result<Money, MoneyError>{
+        let rounded = (self.0 as i128 + SEN as i128 / 2).div_euclid(SEN as i128) * SEN as i128;
+        i64::try_from(rounded).map(Money).map_err(|_| MoneyError::Overflow)
+    }
+
+    // Cut down to whole rupiah, for shares where the last one takes the rest
+    pub fn trunc_rupiah(self) -> Money{
+        Money(self.0 / SEN * SEN)
+    }
+
+    // Hundredths of a percent `self` is of `total`, None when the total is 0
+    pub fn ratio(self, total: Money) -> Option<i64>{
+        if total.0 == 0{
+            return None;
+        }
+        let total = total.0 as i128;
+        i64::try_from((self.0 as i128 * 10000 + total / 2).div_euclid(total)).ok()
+    }
+
+    pub fn sum<I: IntoIterator<Item = Money>>(amounts: I) -> Result<Money, MoneyError>{
+        amounts.into_iter().try_fold(Money::ZERO, Money::checked_add)
+    }
+}
+
+// Rupiah with the sen only when there are any: 25000, -1500, 2500.50
+impl fmt::Display for Money{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let (rupiah, sen) = (self.0.unsigned_abs() / SEN as u64, self.0.unsigned_abs() % SEN as u64);
+        let text = if sen == 0{
+            format!("{}{}", sign, rupiah)
+        } else {
+            format!("{}{}.{:02}", sign, rupiah, sen)
+        };
+        f.pad(&text)
+    }
+}
+
+// Parses rupiah with up to two decimals of sen, "25000", "2500.5" or "-1500"
+impl FromStr for Money{
+    type Err = MoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        let invalid = || MoneyError::Invalid(s.to_string());
+        let amount = s.trim();
+        let (negative, amount) = match amount.strip_prefix('-'){
+            Some(amount) => (true, amount),
+            None => (false, amount),
+        };
+        let (rupiah, sen) = amount.split_once('.').unwrap_or((amount, ""));
+
+        let digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if rupiah.is_empty() || !digits(rupiah) || sen.len() > 2 || !digits(sen){
+            return Err(invalid());
+        }
+        let sen = format!("{:0<2}", sen).parse::<i64>().map_err(|_| invalid())?;
+        let rupiah = rupiah.parse::<i64>().map_err(|_| MoneyError::Overflow)?;
+
+        let amount = Money::rupiah(rupiah)?.checked_add(Money(sen))?;
+        if negative { amount.checked_neg() } else { Ok(amount) }
+    }
+}
+
+// Shorthand for amounts in tests
+#[cfg(test)]
+pub fn rp(rupiah: i64) -> Money{
+    Money::rupiah(rupiah).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rp, Money, MoneyError};
+
+    #[test]
+    fn parse_and_display_test(){
+        assert_eq!("25000".parse::<Money>(), Ok(rp(25000)));
+        assert_eq!("2500.5".parse::<Money>(), Ok(Money::from_sen(250050)));
+        assert_eq!(" -1500.05 ".parse::<Money>(), Ok(Money::from_sen(-150005)));
+        assert!(matches!("25.000".parse::<Money>(), Err(MoneyError::Invalid(_))));
+        assert!(matches!("Rp 100".parse::<Money>(), Err(MoneyError::Invalid(_))));
+        assert_eq!("99999999999999999".parse::<Money>(), Err(MoneyError::Overflow));
+
+        assert_eq!(rp(25000).to_string(), "25000");
+        assert_eq!(Money::from_sen(-150005).to_string(), "-1500.05");
+        assert_eq!(format!("{:<8}|", Money::from_sen(250)), "2.50    |");
+    }
+
+    #[test]
+    fn checked_arithmetic_test(){
+        assert_eq!(rp(25000).checked_mul(3), Ok(rp(75000)));
+        assert_eq!(rp(99999999999).checked_mul(9999999999), Err(MoneyError::Overflow));
+        assert_eq!(Money::from_sen(i64::MAX).checked_add(Money::from_sen(1)), Err(MoneyError::Overflow));
+        assert_eq!(Money::rupiah(i64::MAX / 10), Err(MoneyError::Overflow));
+        assert_eq!(Money::sum([rp(1), rp(2), Money::from_sen(50)]), Ok(Money::from_sen(350)));
+
+        // 11% of 15750 is 1732.50, kept to the sen until it is charged in whole rupiah
+        assert_eq!(rp(15750).scale(1100, 10000), Ok(Money::from_sen(173250)));
+        assert_eq!(Money::from_sen(173250).round_rupiah(), Ok(rp(1733)));
+        assert_eq!(Money::from_sen(-173250).round_rupiah(), Ok(rp(-1732)));
+        assert_eq!(Money::from_sen(100).scale(1, 3), Ok(Money::from_sen(33)));
+        assert_eq!(rp(1733).share(1, 3), Ok(Money::from_sen(57766)));
+        assert_eq!(rp(-1733).share(1, 3).map(Money::trunc_rupiah), Ok(rp(-577)));
+        assert_eq!(rp(2500).ratio(rp(10000)), Some(2500));
+        assert_eq!(rp(1).ratio(Money::ZERO), None);
+    }
+}
@@ -0,0 +1,320 @@
+use thiserror::Error;
+
+use crate::{ItemError, Restaurant, Sale};
+use crate::i18n::tr;
+use crate::money::{Money, MoneyError};
+use crate::recipe::RecipeError;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum OrderError{
+    #[error("{}", tr!("baris pesanan \"{}\" tidak valid, seharusnya nama=kuantitas", "invalid order line \"{}\", expected name=quantity", .0))]
+    InvalidLine(String),
+
+    #[error("{}", tr!("pesanan kosong", "the order is empty"))]
+    EmptyOrder,
+
+    #[error("{}", tr!("kuantitas {} terlalu besar", "quantity of {} is too large", .0))]
+    QuantityTooLarge(String),
+
+    #[error("{}", tr!("pembayaran kurang, {} < {}", "not enough paid, {} < {}", .0, .1))]
+    NotEnoughPayment(Money, Money),
+
+    #[error("{0}")]
+    Item(#[from] ItemError),
+
+    #[error("{0}")]
+    Ingredient(#[from] RecipeError),
+
+    #[error("{0}")]
+    Money(#[from] MoneyError),
+}
+
+impl OrderError{
+    // Stable name of the error for the structured output formats
+    pub fn code(&self) -> &'static str{
+        match self{
+            OrderError::InvalidLine(_) => "invalid_line",
+            OrderError::EmptyOrder => "empty_order",
+            OrderError::QuantityTooLarge(_) => "invalid_value",
+            OrderError::NotEnoughPayment(..) => "not_enough_payment",
+            OrderError::Item(e) => e.code(),
+            OrderError::Ingredient(e) => e.code(),
+            OrderError::Money(e) => e.code(),
+        }
+    }
+}
+
+// One requested dish of an order
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderLine{
+    pub name: String,
+    pub quantity: i64
+}
+
+// An order as a whole, its lines are the ledger sales sharing its id
+#[derive(Clone, Debug, PartialEq)]
+pub struct Order{
+    pub id: i64,
+    // Sequential per business day starting at 1, used to call the customer
+    pub number: i64,
+    // Local time in YYYY-MM-DD HH:MM:SS
+    pub timestamp: String,
+    // Amount handed over by the customer
+    pub paid: Money
+}
+
+impl Order{
+    pub fn date(&self) -> &str{
+        self.timestamp.split(' ').next().unwrap_or(&self.timestamp)
+    }
+}
+
+// Append-only list of orders, ordered by id
+pub struct Orders{
+    pub list: Vec<Order>
+}
+
+impl Orders{
+    pub fn new() -> Self{
+        Self{
+            list: Vec::new()
+        }
+    }
+
+    // Used when reading orders back from storage
+    pub fn push(&mut self, order: Order){
+        self.list.push(order);
+    }
+
+    pub fn find(&self, id: i64) -> Option<&Order>{
+        self.list.iter().find(|order| order.id == id)
+    }
+
+    pub fn find_by_number(&self, date: &str, number: i64) -> Option<&Order>{
+        self.list.iter().find(|order| order.date() == date && order.number == number)
+    }
+
+    fn next_number(&self, date: &str) -> i64{
+        self.list.iter()
+            .filter(|order| order.date() == date)
+            .map(|order| order.number)
+            .max()
+            .unwrap_or(0) + 1
+    }
+}
+
+// Parses "name=quantity", the name itself may contain '='
+pub fn parse_order_line(line: &str) -> Result<OrderLine, OrderError>{
+    let invalid = || OrderError::InvalidLine(line.to_string());
+    let (name, quantity) = line.rsplit_once('=').ok_or_else(invalid)?;
+
+    let name = name.trim();
+    let quantity = quantity.trim().parse::<i64>().map_err(|_| invalid())?;
+    if name.is_empty() || quantity <= 0{
+        return Err(invalid());
+    }
+
+    Ok(OrderLine{
+        name: name.to_string(),
+        quantity
+    })
+}
+
+// Order file: one name=quantity per line, blank lines and lines starting with '#' are skipped
+pub fn parse_order(buffer: &str) -> Result<Vec<OrderLine>, OrderError>{
+    buffer.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_order_line)
+        .collect()
+}
+
+// Lines naming the same item are merged, so stock is checked against the whole order
+fn merge_lines(lines: &[OrderLine]) -> Result<Vec<OrderLine>, OrderError>{
+    let mut merged: Vec<OrderLine> = Vec::new();
+    for line in lines{
+        match merged.iter_mut().find(|m| m.name.to_lowercase() == line.name.to_lowercase()){
+            Some(m) => {
+                m.quantity = m.quantity.checked_add(line.quantity).ok_or_else(|| OrderError::QuantityTooLarge(line.name.clone()))?;
+            },
+            None => merged.push(line.clone()),
+        }
+    }
+    Ok(merged)
+}
+
+impl Restaurant{
+    // Sells every line of an order under one order id. Stock and payment are checked first,
+    // so either the whole order is recorded or nothing changes. Without `paid` the order is paid exactly,
+    // service charge and tax included. Items with a recipe use up their ingredients instead of their own stock
+    pub fn order(&mut self, lines: &[OrderLine], paid: Option<Money>, timestamp: &str) -> Result<(Order, Vec<Sale>), OrderError>{
+        // A negative line would put stock back and book negative income without being a refund
+        if let Some(line) = lines.iter().find(|line| line.quantity <= 0){
+            return Err(OrderError::InvalidLine(format!("{}={}", line.name, line.quantity)));
+        }
+        let lines = merge_lines(lines)?;
+        if lines.is_empty(){
+            return Err(OrderError::EmptyOrder);
+        }
+
+        let mut sold = Vec::new();
+        for line in &lines{
+            let item = self.items.find_item(&line.name)?;
+            if item.quantity < line.quantity && !self.recipes.has_recipe(item.id){
+                return Err(OrderError::Item(ItemError::NotEnoughStock(item.quantity, line.quantity)));
+            }
+            sold.push((item, line.quantity));
+        }
+        let ingredients = self.recipes.requirements(&sold);
+        self.ingredients.check(&ingredients)?;
+
+        // Promotions come off the menu price, service and tax are charged on what is left
+        let discounts = self.promotions.apply(&sold, timestamp)?;
+        let mut charges = Vec::new();
+        let mut costs = Vec::new();
+        for ((item, quantity), discount) in sold.iter().zip(&discounts){
+            let amount = item.price.checked_mul(*quantity)?.checked_sub(discount.amount)?;
+            charges.push(self.taxes.charges(&item.category, amount)?);
+            // Cost is fixed now, later deliveries and recipe changes do not change what this sale cost
+            costs.push(self.unit_cost(item)?.checked_mul(*quantity)?);
+        }
+
+        let total = Money::sum(charges.iter().map(|charges| charges.gross))?;
+        let paid = paid.unwrap_or(total);
+        if paid < total{
+            return Err(OrderError::NotEnoughPayment(paid, total));
+        }
+
+        let order_id = self.ledger.next_order_id();
+        let mut sales = Vec::new();
+        for ((((line, (item, _)), discount), charges), cost) in lines.iter().zip(&sold).zip(&discounts).zip(charges).zip(costs){
+            let item = if self.recipes.has_recipe(item.id){
+                item.clone()
+            } else {
+                self.items.buy(&line.name, line.quantity)?
+            };
+            let sale = self.ledger.record(&item, line.quantity, discount, charges, cost, order_id, timestamp);
+            self.reports.record_sale(sale.date(), &sale)?;
+            sales.push(sale);
+        }
+        self.ingredients.consume(&ingredients);
+
+        let order = Order{
+            id: order_id,
+            number: self.orders.next_number(sales[0].date()),
+            timestamp: timestamp.to_string(),
+            paid
+        };
+        self.orders.push(order.clone());
+        Ok((order, sales))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ItemError, Restaurant};
+    use crate::money::{rp, Money};
+    use crate::promo::Promotions;
+    use crate::tax::TaxRules;
+    use super::{parse_order, parse_order_line, OrderError, OrderLine};
+
+    fn restaurant() -> Restaurant{
+        let mut data = Restaurant::new();
+        data.items.add("Risoles", 5, rp(25000), 0, false);
+        data.items.add("Es Teh", 10, rp(5000), 0, false);
+        data
+    }
+
+    #[test]
+    fn parse_order_test(){
+        assert_eq!(parse_order_line("Es Teh = 2"), Ok(OrderLine{ name: "Es Teh".to_string(), quantity: 2 }));
+        assert_eq!(parse_order_line("risoles"), Err(OrderError::InvalidLine("risoles".to_string())));
+        assert_eq!(parse_order_line("risoles=0"), Err(OrderError::InvalidLine("risoles=0".to_string())));
+        assert_eq!(parse_order("# meja 4\nrisoles=2\n\nes teh=3\n").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn order_is_atomic_test(){
+        let mut data = restaurant();
+        let lines = parse_order("risoles=3\nes teh=2\nrisoles=3").unwrap();
+
+        // 6 risoles in total while only 5 are in stock: nothing is sold
+        assert_eq!(data.order(&lines, None, "2022-11-22 10:00:00"), Err(OrderError::Item(ItemError::NotEnoughStock(5, 6))));
+        assert_eq!(data.items.find_item("es teh").unwrap().quantity, 10);
+        assert!(data.ledger.list.is_empty());
+        assert!(data.reports.list.is_empty());
+
+        let lines = parse_order("risoles=2\nes teh=2\nrisoles=3").unwrap();
+        assert_eq!(data.order(&lines, Some(rp(100000)), "2022-11-22 10:00:00"), Err(OrderError::NotEnoughPayment(rp(100000), rp(135000))));
+        assert!(data.ledger.list.is_empty());
+
+        let (order, sales) = data.order(&lines, Some(rp(150000)), "2022-11-22 10:00:00").unwrap();
+        assert_eq!(sales.len(), 2);
+        assert!(sales.iter().all(|sale| sale.order_id == order.id));
+        assert_eq!(order.paid, rp(150000));
+        assert_eq!(data.items.find_item("risoles").unwrap().quantity, 0);
+        assert_eq!(data.reports.find_report("2022-11-22").unwrap().income, rp(135000));
+    }
+
+    #[test]
+    fn order_quantity_test(){
+        let mut data = restaurant();
+        for quantity in [-5, 0]{
+            let lines = [OrderLine{ name: "risoles".to_string(), quantity }];
+            assert_eq!(data.order(&lines, None, "2022-11-22 10:00:00"), Err(OrderError::InvalidLine(format!("risoles={}", quantity))));
+        }
+        // Two lines of the same item that add up past what a quantity can hold
+        let lines = parse_order(&format!("risoles={}\nRisoles=1", i64::MAX)).unwrap();
+        assert_eq!(data.order(&lines, None, "2022-11-22 10:00:00"), Err(OrderError::QuantityTooLarge("Risoles".to_string())));
+        assert_eq!(data.items.find_item("risoles").unwrap().quantity, 5);
+        assert!(data.ledger.list.is_empty());
+    }
+
+    #[test]
+    fn daily_order_number_test(){
+        let mut data = restaurant();
+        let lines = parse_order("es teh=1").unwrap();
+
+        let numbers: Vec<_> = ["2022-11-22 10:00:00", "2022-11-22 11:00:00", "2022-11-23 09:00:00"].iter()
+            .map(|timestamp| data.order(&lines, None, timestamp).unwrap().0.number)
+            .collect();
+        assert_eq!(numbers, vec![1, 2, 1]);
+        assert_eq!(data.orders.find_by_number("2022-11-22", 2).unwrap().id, 1);
+    }
+
+    #[test]
+    fn taxed_order_test(){
+        let mut data = restaurant();
+        data.items.set_category("es teh", "minuman").unwrap();
+        data.taxes = TaxRules::parse(b"category,service,tax,inclusive\n*,5,11,false\nminuman,0,11,true\n").unwrap();
+
+        // Risoles pays service and tax on top, es teh already includes them
+        let lines = parse_order("risoles=2\nes teh=2").unwrap();
+        assert_eq!(data.order(&lines, Some(rp(68000)), "2022-11-22 10:00:00"), Err(OrderError::NotEnoughPayment(rp(68000), rp(68275))));
+
+        let (_, sales) = data.order(&lines, None, "2022-11-22 10:00:00").unwrap();
+        assert_eq!((sales[0].net().unwrap(), sales[0].service, sales[0].tax, sales[0].total), (rp(50000), rp(2500), rp(5775), rp(58275)));
+        assert_eq!((sales[1].net().unwrap(), sales[1].service, sales[1].tax, sales[1].total), (rp(9009), Money::ZERO, rp(991), rp(10000)));
+
+        let report = data.reports.find_report("2022-11-22").unwrap();
+        assert_eq!((report.income, report.service, report.tax), (rp(59009), rp(2500), rp(6766)));
+    }
+
+    #[test]
+    fn promoted_order_test(){
+        let mut data = restaurant();
+        data.taxes = TaxRules::parse(b"category,service,tax\n*,0,10\n").unwrap();
+        data.promotions = Promotions::parse(b"name,kind,items,value\nDiskon Risoles,percent,risoles,20\n").unwrap();
+
+        let lines = parse_order("risoles=2\nes teh=1").unwrap();
+        let (_, sales) = data.order(&lines, None, "2022-11-22 10:00:00").unwrap();
+
+        // 50000 - 20% = 40000, + 10% tax
+        assert_eq!((sales[0].discount, sales[0].net().unwrap(), sales[0].tax, sales[0].total), (rp(10000), rp(40000), rp(4000), rp(44000)));
+        assert_eq!(sales[0].promotion, "Diskon Risoles");
+        assert_eq!((sales[1].discount, sales[1].total), (Money::ZERO, rp(5500)));
+
+        let report = data.reports.find_report("2022-11-22").unwrap();
+        assert_eq!((report.discount, report.income, report.tax), (rp(10000), rp(45000), rp(4500)));
+    }
+}
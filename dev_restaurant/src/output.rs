@@ -0,0 +1,315 @@
+use std::{
+    fmt,
+    io::{self, Write},
+    str::FromStr
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Item, Report};
+use crate::i18n::tr;
+use crate::item_report::ItemSales;
+use crate::ledger::Sale;
+use crate::margin::Margin;
+use crate::money::{Money, MoneyError};
+
+// How a command prints its result. Everything but table is meant for scripts:
+// field names and error codes are stable, amounts are plain numbers and never follow the locale
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat{
+    #[default]
+    Table,
+    // One JSON document, an array for lists
+    Json,
+    // A header line, then one line per row
+    Csv,
+    // One JSON object per line
+    Ndjson
+}
+
+impl FromStr for OutputFormat{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        match s.to_lowercase().as_str(){
+            "table" | "text" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            _ => Err(tr!("format keluaran \"{}\" tidak dikenal, seharusnya table, json, csv atau ndjson", "unknown output format \"{}\", expected table, json, csv or ndjson", s)),
+        }
+    }
+}
+
+// Rupiah as a number, with sen only when there are any: 25000 or 2500.5
+impl Serialize for Money{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>{
+        if self.sen() % 100 == 0{
+            serializer.serialize_i64(self.sen() / 100)
+        } else {
+            serializer.serialize_f64(self.sen() as f64 / 100.0)
+        }
+    }
+}
+
+// Read back from a number or a string like 2500.50, with the same limits as amounts given on the command line
+impl<'de> Deserialize<'de> for Money{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>{
+        struct AmountVisitor;
+
+        impl de::Visitor<'_> for AmountVisitor{
+            type Value = Money;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result{
+                write!(f, "an amount in rupiah")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Money, E>{
+                Money::rupiah(v).map_err(E::custom)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Money, E>{
+                i64::try_from(v).map_err(|_| E::custom(MoneyError::Overflow)).and_then(|v| self.visit_i64(v))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Money, E>{
+                self.visit_str(&v.to_string())
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Money, E>{
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(AmountVisitor)
+    }
+}
+
+// Printed instead of a result when a command fails, `error` is a stable code and the message follows --lang
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Failure{
+    pub error: &'static str,
+    pub message: String
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ItemRow{
+    pub id: i64,
+    pub name: String,
+    pub category: String,
+    pub quantity: i64,
+    pub price: Money,
+    pub cost: Money,
+    pub reorder_level: i64,
+    pub reorder_target: i64,
+    // Items cooked from a recipe have no stock of their own, available is the portions their ingredients make
+    pub recipe: bool,
+    pub available: i64
+}
+
+impl ItemRow{
+    pub fn new(item: &Item, portions: Option<i64>) -> Self{
+        Self{
+            id: item.id,
+            name: item.name.clone(),
+            category: item.category.clone(),
+            quantity: item.quantity,
+            price: item.price,
+            cost: item.cost,
+            reorder_level: item.reorder_level,
+            reorder_target: item.reorder_target,
+            recipe: portions.is_some(),
+            available: portions.unwrap_or(item.quantity)
+        }
+    }
+}
+
+// Same columns as the report table, sales is before discounts and gross is what customers paid
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ReportRow{
+    pub period: String,
+    pub quantity: i64,
+    pub sales: Money,
+    pub discount: Money,
+    pub net: Money,
+    pub service: Money,
+    pub tax: Money,
+    pub gross: Money,
+    pub cost: Money
+}
+
+impl ReportRow{
+    pub fn new(report: &Report) -> Result<Self, MoneyError>{
+        Ok(Self{
+            period: report.date.clone(),
+            quantity: report.quantity,
+            sales: report.income.checked_add(report.discount)?,
+            discount: report.discount,
+            net: report.income,
+            service: report.service,
+            tax: report.tax,
+            gross: Money::sum([report.income, report.service, report.tax])?,
+            cost: report.cost
+        })
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ItemSalesRow{
+    pub rank: usize,
+    pub item_id: i64,
+    pub name: String,
+    pub quantity: i64,
+    pub revenue: Money,
+    // Percent of the revenue of all items in the period
+    pub share: f64
+}
+
+impl ItemSalesRow{
+    pub fn new(row: &ItemSales) -> Self{
+        Self{
+            rank: row.rank,
+            item_id: row.item_id,
+            name: row.name.clone(),
+            quantity: row.quantity,
+            revenue: row.revenue,
+            share: row.share as f64 / 100.0
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct MarginRow{
+    pub label: String,
+    pub quantity: i64,
+    pub revenue: Money,
+    pub cost: Money,
+    pub profit: Money,
+    // Percent of the revenue, empty when nothing was earned
+    pub margin: Option<f64>
+}
+
+impl MarginRow{
+    pub fn new(row: &Margin) -> Result<Self, MoneyError>{
+        Ok(Self{
+            label: row.label.clone(),
+            quantity: row.quantity,
+            revenue: row.revenue,
+            cost: row.cost,
+            profit: row.profit()?,
+            margin: row.margin().map(|margin| margin as f64 / 100.0)
+        })
+    }
+}
+
+// One line sold by buy
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SaleRow{
+    pub id: i64,
+    pub order_id: i64,
+    pub order_number: i64,
+    pub timestamp: String,
+    pub item_id: i64,
+    pub name: String,
+    pub quantity: i64,
+    pub unit_price: Money,
+    pub discount: Money,
+    pub promotion: String,
+    pub service: Money,
+    pub tax: Money,
+    pub total: Money,
+    // Set when the sale left the item at or below its reorder level
+    pub low_stock: bool
+}
+
+impl SaleRow{
+    pub fn new(sale: &Sale, order_number: i64, low_stock: bool) -> Self{
+        Self{
+            id: sale.id,
+            order_id: sale.order_id,
+            order_number,
+            timestamp: sale.timestamp.clone(),
+            item_id: sale.item_id,
+            name: sale.item_name.clone(),
+            quantity: sale.quantity,
+            unit_price: sale.unit_price,
+            discount: sale.discount,
+            promotion: sale.promotion.clone(),
+            service: sale.service,
+            tax: sale.tax,
+            total: sale.total,
+            low_stock
+        }
+    }
+}
+
+// Writes a list of rows, a JSON array or one line per row. Table is printed by the commands themselves
+pub fn emit_rows<T: Serialize>(format: OutputFormat, rows: &[T]) -> io::Result<()>{
+    let mut out = io::stdout().lock();
+    match format{
+        OutputFormat::Json | OutputFormat::Table => {
+            serde_json::to_writer_pretty(&mut out, rows)?;
+            writeln!(out)
+        },
+        OutputFormat::Ndjson => {
+            for row in rows{
+                serde_json::to_writer(&mut out, row)?;
+                writeln!(out)?;
+            }
+            Ok(())
+        },
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            for row in rows{
+                writer.serialize(row)?;
+            }
+            writer.flush()
+        },
+    }
+}
+
+// Writes a single result, a JSON object instead of an array
+pub fn emit_one<T: Serialize>(format: OutputFormat, row: &T) -> io::Result<()>{
+    match format{
+        OutputFormat::Json => {
+            let mut out = io::stdout().lock();
+            serde_json::to_writer_pretty(&mut out, row)?;
+            writeln!(out)
+        },
+        _ => emit_rows(format, std::slice::from_ref(row)),
+    }
+}
+
+// Reports a failed command, the message alone for table and an error object otherwise
+pub fn fail(format: OutputFormat, error: &'static str, message: String) -> io::Result<()>{
+    match format{
+        OutputFormat::Table => {
+            println!("{}", message);
+            Ok(())
+        },
+        format => emit_one(format, &Failure{ error, message }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::money::{rp, Money};
+    use super::{Failure, OutputFormat};
+
+    #[test]
+    fn serialize_test(){
+        assert_eq!("NDJSON".parse::<OutputFormat>(), Ok(OutputFormat::Ndjson));
+        assert!("xml".parse::<OutputFormat>().is_err());
+
+        assert_eq!(serde_json::to_string(&rp(25000)).unwrap(), "25000");
+        assert_eq!(serde_json::to_string(&Money::from_sen(-250050)).unwrap(), "-2500.5");
+        assert_eq!(serde_json::from_str::<Money>("2500.5").unwrap(), Money::from_sen(250050));
+        assert_eq!(serde_json::from_str::<Money>("\"25000\"").unwrap(), rp(25000));
+        assert!(serde_json::from_str::<Money>("2500.555").is_err());
+
+        let failure = Failure{ error: "item_not_found", message: "makanan lemper tidak ditemukan".to_string() };
+        assert_eq!(
+            serde_json::to_string(&failure).unwrap(),
+            r#"{"error":"item_not_found","message":"makanan lemper tidak ditemukan"}"#
+        );
+    }
+}
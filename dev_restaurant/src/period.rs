@@ -0,0 +1,119 @@
+use std::str::FromStr;
+use chrono::{Datelike, NaiveDate};
+
+use crate::{Report, Reports};
+use crate::i18n::tr;
+use crate::money::MoneyError;
+
+// Length of the rows a report is rolled up into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period{
+    Day,
+    Week,
+    Month,
+    Year
+}
+
+impl FromStr for Period{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        match s.to_lowercase().as_str(){
+            "day" | "hari" => Ok(Period::Day),
+            "week" | "minggu" => Ok(Period::Week),
+            "month" | "bulan" => Ok(Period::Month),
+            "year" | "tahun" => Ok(Period::Year),
+            _ => Err(tr!("periode \"{}\" tidak dikenal, seharusnya day, week, month atau year", "unknown period \"{}\", expected day, week, month or year", s)),
+        }
+    }
+}
+
+impl Period{
+    // Row label of the period a date falls in: 2022-11-22, 2022-W47, 2022-11 or 2022.
+    // Weeks are ISO weeks, so the first days of January may belong to the last week of the year before
+    pub fn label(&self, date: NaiveDate) -> String{
+        match self{
+            Period::Day => date.format("%Y-%m-%d").to_string(),
+            Period::Week => format!("{}-W{:02}", date.iso_week().year(), date.iso_week().week()),
+            Period::Month => date.format("%Y-%m").to_string(),
+            Period::Year => date.year().to_string(),
+        }
+    }
+}
+
+pub fn parse_date(date: &str) -> Option<NaiveDate>{
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok()
+}
+
+impl Reports{
+    // Daily reports between `from` and `to` (both included) summed per period, oldest first.
+    // Reports whose date cannot be read are only kept when no date filter is given and rows are days
+    pub fn summary(&self, from: Option<NaiveDate>, to: Option<NaiveDate>, period: Period) -> Result<Vec<Report>, MoneyError>{
+        let mut reports = self.get_report_list();
+        reports.sort_by(|a, b| a.date.cmp(&b.date));
+
+        let mut rows: Vec<Report> = Vec::new();
+        for report in reports{
+            let label = match parse_date(&report.date){
+                Some(date) if from.is_some_and(|from| date < from) || to.is_some_and(|to| date > to) => continue,
+                Some(date) => period.label(date),
+                None if from.is_none() && to.is_none() && period == Period::Day => report.date.clone(),
+                None => continue,
+            };
+
+            match rows.iter_mut().find(|row| row.date == label){
+                Some(row) => row.merge(&report)?,
+                None => rows.push(Report{
+                    id: rows.len() as i64,
+                    date: label,
+                    ..report
+                }),
+            }
+        }
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Reports;
+    use crate::money::rp;
+    use super::{parse_date, Period};
+
+    fn reports() -> Reports{
+        let mut reports = Reports::new();
+        for (date, quantity, income) in [
+            ("2022-11-28", 1, 10000), ("2022-12-31", 2, 20000), ("2023-01-01", 3, 30000), ("2023-01-02", 4, 40000),
+        ]{
+            reports.add_or_update(date, quantity, rp(income / quantity), 0, false).unwrap();
+        }
+        reports
+    }
+
+    #[test]
+    fn period_label_test(){
+        let date = parse_date("2023-01-01").unwrap();
+        assert_eq!(Period::Day.label(date), "2023-01-01");
+        assert_eq!(Period::Week.label(date), "2022-W52");
+        assert_eq!(Period::Month.label(date), "2023-01");
+        assert_eq!(Period::Year.label(date), "2023");
+        assert_eq!("Bulan".parse::<Period>(), Ok(Period::Month));
+        assert!("quarter".parse::<Period>().is_err());
+    }
+
+    #[test]
+    fn summary_test(){
+        let reports = reports();
+
+        let months = reports.summary(None, None, Period::Month).unwrap();
+        let months: Vec<_> = months.iter().map(|row| (row.date.as_str(), row.quantity, row.income)).collect();
+        assert_eq!(months, vec![("2022-11", 1, rp(10000)), ("2022-12", 2, rp(20000)), ("2023-01", 7, rp(70000))]);
+
+        let weeks = reports.summary(parse_date("2022-12-01"), None, Period::Week).unwrap();
+        let weeks: Vec<_> = weeks.iter().map(|row| (row.date.as_str(), row.quantity)).collect();
+        assert_eq!(weeks, vec![("2022-W52", 5), ("2023-W01", 4)]);
+
+        let days = reports.summary(parse_date("2022-12-31"), parse_date("2023-01-01"), Period::Day).unwrap();
+        assert_eq!(days.iter().map(|row| row.quantity).sum::<i64>(), 5);
+    }
+}
@@ -0,0 +1,371 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant}
+};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, TableState},
+    Frame
+};
+
+use crate::Restaurant;
+use crate::events;
+use crate::i18n::tr;
+use crate::locale::Locale;
+use crate::money::Money;
+use crate::order::OrderLine;
+use crate::storage::{Storage, StorageError};
+
+// How often the stock column is read again, other tills and the server sell from the same stock
+const REFRESH: Duration = Duration::from_secs(3);
+
+// An item as the menu shows it, available is the stock or the portions a recipe still makes
+#[derive(Clone, Debug, PartialEq)]
+struct MenuEntry{
+    name: String,
+    price: Money,
+    available: i64
+}
+
+fn menu_entries(data: &Restaurant) -> Vec<MenuEntry>{
+    let portions = data.recipes.portions(&data.ingredients);
+    data.items.get_item_list().into_iter()
+        .map(|item| MenuEntry{
+            available: portions.get(&item.id).copied().unwrap_or(item.quantity),
+            name: item.name,
+            price: item.price
+        })
+        .collect()
+}
+
+// Which part of the screen the keys go to
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Focus{
+    Menu,
+    Cart,
+    Checkout
+}
+
+// What a key asks of the loop besides changing the screen
+#[derive(Debug, PartialEq)]
+enum Action{
+    Nothing,
+    // Sell the cart, None is paid exactly
+    Checkout(Option<Money>),
+    Quit
+}
+
+struct Pos{
+    search: String,
+    // Row of the filtered menu
+    selected: usize,
+    cart: Vec<OrderLine>,
+    cart_selected: usize,
+    focus: Focus,
+    paid: String,
+    status: String
+}
+
+impl Pos{
+    fn new() -> Self{
+        Self{
+            search: String::new(),
+            selected: 0,
+            cart: Vec::new(),
+            cart_selected: 0,
+            focus: Focus::Menu,
+            paid: String::new(),
+            status: String::new()
+        }
+    }
+
+    // Menu entries whose name contains the search, case is ignored
+    fn filter<'a>(&self, menu: &'a [MenuEntry]) -> Vec<&'a MenuEntry>{
+        let search = self.search.to_lowercase();
+        menu.iter().filter(|entry| entry.name.to_lowercase().contains(&search)).collect()
+    }
+
+    fn in_cart(&self, name: &str) -> i64{
+        self.cart.iter().find(|line| line.name == name).map_or(0, |line| line.quantity)
+    }
+
+    // Adds one more of an item, never more than is available
+    fn add(&mut self, entry: &MenuEntry, locale: Locale){
+        if self.in_cart(&entry.name) >= entry.available{
+            self.status = tr!("Stok {} tidak mencukupi, tersedia {}", "Not enough {} in stock, {} available", entry.name, locale.number(entry.available));
+            return;
+        }
+        match self.cart.iter_mut().find(|line| line.name == entry.name){
+            Some(line) => line.quantity += 1,
+            None => self.cart.push(OrderLine{ name: entry.name.clone(), quantity: 1 }),
+        }
+        self.status.clear();
+    }
+
+    // Removes one of the selected cart line, the line goes once none are left
+    fn remove(&mut self, all: bool){
+        let Some(line) = self.cart.get_mut(self.cart_selected) else { return };
+        line.quantity -= 1;
+        if all || line.quantity <= 0{
+            self.cart.remove(self.cart_selected);
+            self.cart_selected = self.cart_selected.min(self.cart.len().saturating_sub(1));
+        }
+        if self.cart.is_empty(){
+            self.focus = Focus::Menu;
+        }
+    }
+
+    // Menu price of the cart, promotions, service and tax are worked out at checkout
+    fn subtotal(&self, menu: &[MenuEntry]) -> Option<Money>{
+        let lines = self.cart.iter().map(|line| {
+            menu.iter().find(|entry| entry.name == line.name).and_then(|entry| entry.price.checked_mul(line.quantity).ok())
+        });
+        Money::sum(lines.collect::<Option<Vec<_>>>()?).ok()
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, menu: &[MenuEntry], locale: Locale) -> Action{
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c'){
+            return Action::Quit;
+        }
+        if key.code == KeyCode::F(2) && !self.cart.is_empty() && self.focus != Focus::Checkout{
+            self.focus = Focus::Checkout;
+            self.paid.clear();
+            return Action::Nothing;
+        }
+
+        match self.focus{
+            Focus::Menu => {
+                let shown = self.filter(menu);
+                match key.code{
+                    KeyCode::Esc if self.search.is_empty() => return Action::Quit,
+                    KeyCode::Esc => self.search.clear(),
+                    KeyCode::Char(c) => self.search.push(c),
+                    KeyCode::Backspace => { self.search.pop(); },
+                    KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+                    KeyCode::Down => self.selected += 1,
+                    KeyCode::Enter => {
+                        if let Some(entry) = shown.get(self.selected.min(shown.len().saturating_sub(1))){
+                            let entry = (*entry).clone();
+                            self.add(&entry, locale);
+                        }
+                    },
+                    KeyCode::Tab if !self.cart.is_empty() => self.focus = Focus::Cart,
+                    _ => {},
+                }
+                self.selected = self.selected.min(self.filter(menu).len().saturating_sub(1));
+            },
+            Focus::Cart => match key.code{
+                KeyCode::Up => self.cart_selected = self.cart_selected.saturating_sub(1),
+                KeyCode::Down => self.cart_selected = (self.cart_selected + 1).min(self.cart.len().saturating_sub(1)),
+                KeyCode::Char('+') | KeyCode::Right => {
+                    let entry = self.cart.get(self.cart_selected).and_then(|line| menu.iter().find(|entry| entry.name == line.name));
+                    if let Some(entry) = entry.cloned(){
+                        self.add(&entry, locale);
+                    }
+                },
+                KeyCode::Char('-') | KeyCode::Left => self.remove(false),
+                KeyCode::Delete | KeyCode::Backspace => self.remove(true),
+                KeyCode::Tab | KeyCode::Esc => self.focus = Focus::Menu,
+                _ => {},
+            },
+            Focus::Checkout => match key.code{
+                KeyCode::Char(c) if c.is_ascii_digit() || c == '.' => self.paid.push(c),
+                KeyCode::Backspace => { self.paid.pop(); },
+                KeyCode::Esc => self.focus = Focus::Cart,
+                KeyCode::Enter if self.paid.is_empty() => return Action::Checkout(None),
+                KeyCode::Enter => match self.paid.parse::<Money>(){
+                    Ok(paid) => return Action::Checkout(Some(paid)),
+                    Err(e) => self.status = e.to_string(),
+                },
+                _ => {},
+            },
+        }
+        Action::Nothing
+    }
+
+    fn draw(&self, frame: &mut Frame, menu: &[MenuEntry], locale: Locale){
+        let [search, body, footer] = Layout::vertical([Constraint::Length(3), Constraint::Min(6), Constraint::Length(3)]).areas(frame.area());
+        let [menu_area, cart_area] = Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(body);
+        let focused = |focus: Focus| if self.focus == focus { Style::default().add_modifier(Modifier::BOLD) } else { Style::default() };
+        let highlight = Style::default().add_modifier(Modifier::REVERSED);
+
+        frame.render_widget(
+            Paragraph::new(self.search.as_str()).block(Block::default().borders(Borders::ALL).title(tr!("Cari", "Search"))),
+            search
+        );
+
+        // Stock left after what is already in the cart
+        let shown = self.filter(menu);
+        let rows = shown.iter().map(|entry| {
+            let left = entry.available - self.in_cart(&entry.name);
+            let style = if left <= 0 { Style::default().add_modifier(Modifier::DIM) } else { Style::default() };
+            Row::new([Cell::from(entry.name.clone()), Cell::from(locale.money(entry.price)), Cell::from(locale.number(left))]).style(style)
+        });
+        let table = Table::new(rows, [Constraint::Min(16), Constraint::Length(16), Constraint::Length(8)])
+            .header(Row::new([tr!("Nama", "Name"), tr!("Harga", "Price"), tr!("Stok", "Stock")]).style(Style::default().add_modifier(Modifier::UNDERLINED)))
+            .block(Block::default().borders(Borders::ALL).title("Menu").border_style(focused(Focus::Menu)))
+            .row_highlight_style(highlight)
+            .highlight_symbol("> ");
+        let mut state = TableState::default().with_selected((!shown.is_empty() && self.focus == Focus::Menu).then_some(self.selected));
+        frame.render_stateful_widget(table, menu_area, &mut state);
+
+        let rows = self.cart.iter().map(|line| {
+            let price = menu.iter().find(|entry| entry.name == line.name).and_then(|entry| entry.price.checked_mul(line.quantity).ok());
+            Row::new([line.name.clone(), locale.number(line.quantity), price.map_or_else(|| "-".to_string(), |price| locale.money(price))])
+        });
+        let subtotal = self.subtotal(menu).map_or_else(|| "-".to_string(), |total| locale.money(total));
+        let table = Table::new(rows, [Constraint::Min(12), Constraint::Length(5), Constraint::Length(16)])
+            .header(Row::new([tr!("Nama", "Name"), tr!("Jml", "Qty"), "Subtotal".to_string()]).style(Style::default().add_modifier(Modifier::UNDERLINED)))
+            .footer(Row::new(["Total".to_string(), String::new(), subtotal.clone()]).style(Style::default().add_modifier(Modifier::BOLD)))
+            .block(Block::default().borders(Borders::ALL).title(tr!("Keranjang", "Cart")).border_style(focused(Focus::Cart)))
+            .row_highlight_style(highlight)
+            .highlight_symbol("> ");
+        let mut state = TableState::default().with_selected((self.focus == Focus::Cart).then_some(self.cart_selected));
+        frame.render_stateful_widget(table, cart_area, &mut state);
+
+        let help = match self.focus{
+            Focus::Menu => tr!(
+                "Ketik untuk cari | Enter tambah | Tab keranjang | F2 bayar | Esc keluar",
+                "Type to search | Enter add | Tab cart | F2 pay | Esc quit"
+            ),
+            Focus::Cart => tr!("+/- ubah jumlah | Del hapus | Tab menu | F2 bayar", "+/- change quantity | Del remove | Tab menu | F2 pay"),
+            Focus::Checkout => tr!("Enter bayar, kosong berarti uang pas | Esc kembali", "Enter to pay, empty is the exact amount | Esc back"),
+        };
+        let footer_text = if self.status.is_empty() { help } else { format!("{}  |  {}", self.status, help) };
+        frame.render_widget(Paragraph::new(footer_text).block(Block::default().borders(Borders::ALL)), footer);
+
+        if self.focus == Focus::Checkout{
+            let area = centered(frame.area(), 44, 7);
+            let text = format!(
+                "{}: {}\n{}: {}\n\n{}",
+                tr!("Subtotal menu", "Menu subtotal"), subtotal,
+                tr!("Dibayar", "Paid"), self.paid,
+                tr!("Promo, service dan pajak dihitung saat bayar", "Promotions, service and tax are added on payment")
+            );
+            frame.render_widget(Clear, area);
+            frame.render_widget(Paragraph::new(text).block(Block::default().borders(Borders::ALL).title(tr!("Bayar", "Checkout"))), area);
+        }
+    }
+}
+
+fn centered(area: Rect, width: u16, height: u16) -> Rect{
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(area.x + (area.width - width) / 2, area.y + (area.height - height) / 2, width, height)
+}
+
+// Runs the till until the cashier quits. Every checkout is sold against freshly read stock,
+// with the same order logic as buy, then saved and sent to the kitchen feed
+pub fn run(storage: &mut dyn Storage, data: &mut Restaurant, feed: &Path, locale: Locale) -> Result<(), StorageError>{
+    let mut terminal = ratatui::init();
+    let result = till(&mut terminal, storage, data, feed, locale);
+    ratatui::restore();
+    result
+}
+
+fn till(terminal: &mut ratatui::DefaultTerminal, storage: &mut dyn Storage, data: &mut Restaurant, feed: &Path, locale: Locale) -> Result<(), StorageError>{
+    let mut pos = Pos::new();
+    let mut refreshed = Instant::now();
+    loop{
+        if refreshed.elapsed() >= REFRESH{
+            storage.begin(data)?;
+            refreshed = Instant::now();
+        }
+        let menu = menu_entries(data);
+        terminal.draw(|frame| pos.draw(frame, &menu, locale))?;
+
+        if !event::poll(Duration::from_millis(500))?{
+            continue;
+        }
+        let Event::Key(key) = event::read()? else { continue };
+        if key.kind != KeyEventKind::Press{
+            continue;
+        }
+
+        match pos.handle_key(key, &menu, locale){
+            Action::Nothing => {},
+            Action::Quit => return Ok(()),
+            Action::Checkout(paid) => {
+                let lock = storage.begin(data)?;
+                refreshed = Instant::now();
+                let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+                match data.order(&pos.cart, paid, &now){
+                    Ok((order, sales)) => {
+                        storage.save(data)?;
+                        drop(lock);
+                        let total = Money::sum(sales.iter().map(|sale| sale.total))?;
+                        pos.status = tr!(
+                            "Pesanan #{} berhasil, total {}, kembali {}", "Order #{} done, total {}, change {}",
+                            order.number, locale.money(total), locale.money(order.paid.checked_sub(total)?)
+                        );
+                        // The sale stands, the cashier only has to tell the kitchen by hand
+                        if let Err(message) = events::publish_saved(feed, &data.order_events(&order, &sales)){
+                            pos.status = format!("{}. {}", pos.status, message);
+                        }
+                        pos.cart.clear();
+                        pos.cart_selected = 0;
+                        pos.focus = Focus::Menu;
+                    },
+                    Err(e) => {
+                        pos.status = e.to_string();
+                        pos.focus = Focus::Cart;
+                    },
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use crate::locale::Locale;
+    use crate::money::rp;
+    use super::{Action, Focus, MenuEntry, Pos};
+
+    fn press(pos: &mut Pos, menu: &[MenuEntry], code: KeyCode) -> Action{
+        pos.handle_key(KeyEvent::new(code, KeyModifiers::NONE), menu, Locale::Indonesian)
+    }
+
+    #[test]
+    fn cart_test(){
+        let menu = vec![
+            MenuEntry{ name: "ikan asin".to_string(), price: rp(25000), available: 2 },
+            MenuEntry{ name: "risoles".to_string(), price: rp(5000), available: 10 },
+        ];
+        let mut pos = Pos::new();
+
+        // Searching narrows the menu, Enter adds the selected item but never more than the stock
+        for c in "RIS".chars(){
+            press(&mut pos, &menu, KeyCode::Char(c));
+        }
+        assert_eq!(pos.filter(&menu).len(), 1);
+        press(&mut pos, &menu, KeyCode::Enter);
+        press(&mut pos, &menu, KeyCode::Esc);
+        press(&mut pos, &menu, KeyCode::Enter);
+        press(&mut pos, &menu, KeyCode::Enter);
+        press(&mut pos, &menu, KeyCode::Enter);
+        assert_eq!(pos.cart.iter().map(|line| (line.name.as_str(), line.quantity)).collect::<Vec<_>>(), vec![("risoles", 1), ("ikan asin", 2)]);
+        assert!(!pos.status.is_empty());
+        assert_eq!(pos.subtotal(&menu), Some(rp(55000)));
+
+        // In the cart quantities change with + and -, a line at 0 is removed
+        press(&mut pos, &menu, KeyCode::Tab);
+        press(&mut pos, &menu, KeyCode::Char('+'));
+        press(&mut pos, &menu, KeyCode::Char('-'));
+        press(&mut pos, &menu, KeyCode::Char('-'));
+        assert_eq!(pos.cart.len(), 1);
+
+        press(&mut pos, &menu, KeyCode::F(2));
+        assert_eq!(pos.focus, Focus::Checkout);
+        for c in "100000".chars(){
+            press(&mut pos, &menu, KeyCode::Char(c));
+        }
+        assert_eq!(press(&mut pos, &menu, KeyCode::Enter), Action::Checkout(Some(rp(100000))));
+        press(&mut pos, &menu, KeyCode::Esc);
+        press(&mut pos, &menu, KeyCode::Esc);
+        assert_eq!(press(&mut pos, &menu, KeyCode::Esc), Action::Quit);
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    fs,
+    io,
+    path::Path
+};
+use chrono::{NaiveDateTime, NaiveTime};
+use csv::ReaderBuilder;
+use thiserror::Error;
+
+use crate::Item;
+use crate::i18n::tr;
+use crate::money::{Money, MoneyError};
+use crate::tax::{apply_rate, parse_rate, TaxError};
+
+// Promotion for every item, used with percent and fixed discounts
+const ALL_ITEMS: &str = "*";
+
+#[derive(Error, Debug)]
+pub enum PromoError{
+    #[error("{}", tr!("file promosi rusak {}", "malformed promotions file {}", .0))]
+    Csv(#[from] csv::Error),
+
+    #[error("{}", tr!("tidak bisa dibaca: {}", "cannot be read: {}", .0))]
+    Io(#[from] io::Error),
+
+    #[error("{}", tr!(
+        "jenis promosi \"{}\" tidak dikenal, seharusnya percent, fixed, buy_get atau bundle",
+        "unknown promotion kind \"{}\", expected percent, fixed, buy_get or bundle", .0
+    ))]
+    UnknownKind(String),
+
+    #[error("{}", tr!("nilai \"{}\" tidak valid untuk {}", "invalid value \"{}\" for {}", .1, .0))]
+    InvalidValue(String, String),
+
+    #[error("{0}")]
+    Rate(#[from] TaxError),
+
+    #[error("{}", tr!("kolom {} tidak ada", "missing field {}", .0))]
+    MissingField(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PromoKind{
+    // Hundredths of a percent off every unit
+    Percent(i64),
+    // Amount off every unit, never below zero
+    Fixed(Money),
+    // For every `buy` units paid, `get` more are free
+    BuyGet{ buy: i64, get: i64 },
+    // The listed items together for one price
+    Bundle(Money)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Promotion{
+    pub name: String,
+    pub kind: PromoKind,
+    // Item names with the units a bundle needs, 1 for other kinds
+    pub items: Vec<(String, i64)>,
+    // Daily time window such as a happy hour, may wrap past midnight
+    pub from: Option<NaiveTime>,
+    pub until: Option<NaiveTime>
+}
+
+// Discount given on one order line and the promotions behind it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LineDiscount{
+    pub amount: Money,
+    pub promotion: String
+}
+
+impl LineDiscount{
+    fn add(&mut self, amount: Money, promotion: &str) -> Result<(), MoneyError>{
+        if amount <= Money::ZERO{
+            return Ok(());
+        }
+        self.amount = self.amount.checked_add(amount)?;
+        if !self.promotion.is_empty(){
+            self.promotion.push_str(" + ");
+        }
+        self.promotion.push_str(promotion);
+        Ok(())
+    }
+}
+
+impl Promotion{
+    fn is_active(&self, time: NaiveTime) -> bool{
+        match (self.from, self.until){
+            (Some(from), Some(until)) if from <= until => from <= time && time < until,
+            (Some(from), Some(until)) => time >= from || time < until,
+            (Some(from), None) => time >= from,
+            (None, Some(until)) => time < until,
+            (None, None) => true,
+        }
+    }
+
+    fn applies_to(&self, item: &Item) -> bool{
+        self.items.iter().any(|(name, _)| name == ALL_ITEMS || *name == item.name.to_lowercase())
+    }
+
+    // Discount on `quantity` units of an item for the per-unit kinds, bundles are handled apart
+    fn unit_discount(&self, item: &Item, quantity: i64) -> Result<Money, MoneyError>{
+        match self.kind{
+            PromoKind::Percent(rate) => apply_rate(item.price.checked_mul(quantity)?, rate)?.round_rupiah(),
+            PromoKind::Fixed(amount) => amount.min(item.price).checked_mul(quantity),
+            PromoKind::BuyGet{ buy, get } => item.price.checked_mul(quantity / (buy + get) * get),
+            PromoKind::Bundle(_) => Ok(Money::ZERO),
+        }
+    }
+}
+
+fn parse_time(value: &str) -> Result<Option<NaiveTime>, PromoError>{
+    let value = value.trim();
+    if value.is_empty(){
+        return Ok(None);
+    }
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .map(Some)
+        .map_err(|_| PromoError::InvalidValue("time".to_string(), value.to_string()))
+}
+
+fn parse_amount(field: &str, value: &str) -> Result<i64, PromoError>{
+    match value.trim().parse::<i64>(){
+        Ok(amount) if amount >= 0 => Ok(amount),
+        _ => Err(PromoError::InvalidValue(field.to_string(), value.to_string())),
+    }
+}
+
+fn parse_money(field: &str, value: &str) -> Result<Money, PromoError>{
+    match value.parse::<Money>(){
+        Ok(amount) if !amount.is_negative() => Ok(amount),
+        _ => Err(PromoError::InvalidValue(field.to_string(), value.to_string())),
+    }
+}
+
+// "risoles|es teh*2": item names separated by '|', with an optional unit count for bundles
+fn parse_items(value: &str) -> Result<Vec<(String, i64)>, PromoError>{
+    value.split('|')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| match name.rsplit_once('*'){
+            Some((name, count)) if !name.trim().is_empty() => Ok((name.trim().to_lowercase(), parse_amount("items", count)?.max(1))),
+            _ => Ok((name.to_lowercase(), 1)),
+        })
+        .collect()
+}
+
+pub struct Promotions{
+    pub list: Vec<Promotion>
+}
+
+impl Promotions{
+    pub fn new() -> Self{
+        Self{
+            list: Vec::new()
+        }
+    }
+
+    // Promotions file: name,kind,items,value,buy,get,from,until. A missing file means no promotions,
+    // one that cannot be read is reported instead of quietly selling at full price
+    pub fn load(file: &Path) -> Result<Self, PromoError>{
+        match fs::read(file){
+            Ok(buffer) => Self::parse(&buffer),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn parse(buffer: &[u8]) -> Result<Self, PromoError>{
+        let mut promotions = Self::new();
+        let mut reader = ReaderBuilder::new().flexible(true).trim(csv::Trim::All).from_reader(buffer);
+
+        for record in reader.records(){
+            let record = record?;
+            let field = |index: usize, name: &str| record.get(index).ok_or_else(|| PromoError::MissingField(name.to_string()));
+            let optional = |index: usize| record.get(index).unwrap_or("");
+
+            let value = field(3, "value")?;
+            let kind = match field(1, "kind")?.to_lowercase().as_str(){
+                "percent" => PromoKind::Percent(parse_rate(value)?),
+                "fixed" => PromoKind::Fixed(parse_money("value", value)?),
+                "buy_get" => {
+                    let buy = parse_amount("buy", optional(4))?;
+                    let get = parse_amount("get", optional(5))?;
+                    if buy + get == 0{
+                        return Err(PromoError::InvalidValue("buy".to_string(), optional(4).to_string()));
+                    }
+                    PromoKind::BuyGet{ buy, get }
+                },
+                "bundle" => PromoKind::Bundle(parse_money("value", value)?),
+                kind => return Err(PromoError::UnknownKind(kind.to_string())),
+            };
+
+            promotions.list.push(Promotion{
+                name: field(0, "name")?.to_string(),
+                kind,
+                items: parse_items(field(2, "items")?)?,
+                from: parse_time(optional(6))?,
+                until: parse_time(optional(7))?,
+            });
+        }
+        Ok(promotions)
+    }
+
+    // Discount for every line of an order sold at `timestamp`. Bundles are taken first and the units
+    // they use are not discounted again, the rest of each line gets its single best promotion
+    pub fn apply(&self, lines: &[(Item, i64)], timestamp: &str) -> Result<Vec<LineDiscount>, MoneyError>{
+        let mut discounts = vec![LineDiscount::default(); lines.len()];
+        let time = match NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S"){
+            Ok(time) => time.time(),
+            Err(_) => return Ok(discounts),
+        };
+        let active: Vec<&Promotion> = self.list.iter().filter(|promo| promo.is_active(time)).collect();
+        let mut remaining: Vec<i64> = lines.iter().map(|(_, quantity)| *quantity).collect();
+
+        for promo in &active{
+            if let PromoKind::Bundle(price) = promo.kind{
+                self.apply_bundle(promo, price, lines, &mut remaining, &mut discounts)?;
+            }
+        }
+
+        for (index, (item, _)) in lines.iter().enumerate(){
+            let amounts = active.iter()
+                .filter(|promo| promo.applies_to(item))
+                .map(|promo| Ok((promo.unit_discount(item, remaining[index])?, promo)))
+                .collect::<Result<Vec<_>, MoneyError>>()?;
+            if let Some((amount, promo)) = amounts.into_iter().max_by_key(|(amount, _)| *amount){
+                discounts[index].add(amount, &promo.name)?;
+            }
+        }
+
+        // A line is never discounted below zero
+        for (discount, (item, quantity)) in discounts.iter_mut().zip(lines){
+            discount.amount = discount.amount.min(item.price.checked_mul(*quantity)?);
+        }
+        Ok(discounts)
+    }
+
+    fn apply_bundle(
+        &self, promo: &Promotion, price: Money, lines: &[(Item, i64)], remaining: &mut [i64], discounts: &mut [LineDiscount]
+    ) -> Result<(), MoneyError>{
+        // Line used for each bundle component, a bundle naming an item not ordered does not apply
+        let mut parts = Vec::new();
+        for (name, needed) in &promo.items{
+            match lines.iter().position(|(item, _)| item.name.to_lowercase() == *name){
+                Some(index) => parts.push((index, *needed)),
+                None => return Ok(()),
+            }
+        }
+
+        let count = parts.iter().map(|(index, needed)| remaining[*index] / needed).min().unwrap_or(0);
+        let mut list_price = Money::ZERO;
+        for (index, needed) in &parts{
+            list_price = list_price.checked_add(lines[*index].0.price.checked_mul(*needed)?)?;
+        }
+        if count == 0 || list_price <= price{
+            return Ok(());
+        }
+
+        // The saving is shared between the lines by their list price, the last line takes the rounding
+        let saving = list_price.checked_sub(price)?.checked_mul(count)?;
+        let mut left = saving;
+        for (position, (index, needed)) in parts.iter().enumerate(){
+            let share = if position + 1 == parts.len(){
+                left
+            } else {
+                saving.share(lines[*index].0.price.checked_mul(*needed)?.sen(), list_price.sen())?.trunc_rupiah()
+            };
+            discounts[*index].add(share, &promo.name)?;
+            remaining[*index] -= needed * count;
+            left = left.checked_sub(share)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Items;
+    use crate::money::rp;
+    use super::{PromoError, PromoKind, Promotions};
+
+    fn lines(order: &[(&str, i64)]) -> Vec<(crate::Item, i64)>{
+        let mut items = Items::new();
+        items.add("Risoles", 100, rp(10000), 0, false);
+        items.add("Es Teh", 100, rp(5000), 0, false);
+        items.add("Nasi Goreng", 100, rp(25000), 0, false);
+        order.iter().map(|(name, quantity)| (items.find_item(name).unwrap(), *quantity)).collect()
+    }
+
+    const PROMOTIONS: &[u8] = b"name,kind,items,value,buy,get,from,until
+Diskon Nasi,percent,nasi goreng,10
+Potongan,fixed,*,1000
+Beli 2 Gratis 1,buy_get,risoles,,2,1
+Paket Hemat,bundle,nasi goreng|es teh*2,30000
+Happy Hour,percent,es teh,50,,,15:00,17:00
+";
+
+    #[test]
+    fn parse_promotions_test(){
+        let promotions = Promotions::parse(PROMOTIONS).unwrap();
+        assert_eq!(promotions.list.len(), 5);
+        assert_eq!(promotions.list[2].kind, PromoKind::BuyGet{ buy: 2, get: 1 });
+        assert_eq!(promotions.list[3].items, vec![("nasi goreng".to_string(), 1), ("es teh".to_string(), 2)]);
+        assert!(Promotions::parse(b"name,kind,items,value\nx,gratis,risoles,1\n").is_err());
+        assert!(Promotions::load(&std::env::temp_dir().join("dev_restaurant_no_promotions.csv")).unwrap().list.is_empty());
+        assert!(matches!(Promotions::load(&std::env::temp_dir()), Err(PromoError::Io(_))));
+    }
+
+    #[test]
+    fn apply_promotions_test(){
+        let promotions = Promotions::parse(PROMOTIONS).unwrap();
+
+        // Best single promotion per line: 1 free risoles beats 1000 off each of 5
+        let discounts = promotions.apply(&lines(&[("risoles", 5), ("nasi goreng", 1)]), "2022-11-22 10:00:00").unwrap();
+        assert_eq!(discounts[0].amount, rp(10000));
+        assert_eq!(discounts[0].promotion, "Beli 2 Gratis 1");
+        assert_eq!(discounts[1].amount, rp(2500));
+
+        // One bundle (35000 for 30000), the third es teh gets the fixed discount
+        let discounts = promotions.apply(&lines(&[("nasi goreng", 1), ("es teh", 3)]), "2022-11-22 10:00:00").unwrap();
+        assert_eq!(discounts[0].amount.checked_add(discounts[1].amount), Ok(rp(5000 + 1000)));
+        assert_eq!(discounts[1].promotion, "Paket Hemat + Potongan");
+
+        // Happy hour
+        let discounts = promotions.apply(&lines(&[("es teh", 1)]), "2022-11-22 15:30:00").unwrap();
+        assert_eq!((discounts[0].amount, discounts[0].promotion.as_str()), (rp(2500), "Happy Hour"));
+        let discounts = promotions.apply(&lines(&[("es teh", 1)]), "2022-11-22 17:00:00").unwrap();
+        assert_eq!(discounts[0].amount, rp(1000));
+    }
+}
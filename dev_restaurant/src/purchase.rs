@@ -0,0 +1,442 @@
+use std::{
+    fmt,
+    str::FromStr
+};
+use thiserror::Error;
+
+use crate::{Item, ItemError, Restaurant};
+use crate::i18n::tr;
+use crate::locale::Locale;
+use crate::money::{Money, MoneyError};
+use crate::order::OrderLine;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PurchaseError{
+    #[error("{}", tr!("supplier {} tidak ditemukan", "supplier {} not found", .0))]
+    SupplierNotFound(String),
+
+    #[error("{}", tr!("supplier {} sudah ada", "supplier {} already exists", .0))]
+    DuplicateSupplier(String),
+
+    #[error("{}", tr!("purchase order {} tidak ditemukan", "purchase order {} not found", .0))]
+    OrderNotFound(i64),
+
+    #[error("{}", tr!("purchase order kosong", "the purchase order is empty"))]
+    EmptyOrder,
+
+    #[error("{}", tr!("purchase order {} berstatus {}, tidak bisa {}", "purchase order {} is {}, it cannot be {}", .0, .1, .2))]
+    WrongStatus(i64, &'static str, PurchaseStep),
+
+    #[error("{}", tr!("{} tidak ada di purchase order", "{} is not on the purchase order", .0))]
+    NotOrdered(String),
+
+    #[error("{}", tr!("{} hanya tersisa {} untuk diterima, bukan {}", "only {1} of {0} left to receive, not {2}", .0, .1, .2))]
+    TooMany(String, i64, i64),
+
+    #[error("{0}")]
+    Item(#[from] ItemError),
+
+    #[error("{0}")]
+    Money(#[from] MoneyError),
+}
+
+// What was asked of a purchase order whose status does not allow it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PurchaseStep{
+    Send,
+    Receive
+}
+
+impl fmt::Display for PurchaseStep{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match self{
+            PurchaseStep::Send => write!(f, "{}", tr!("dikirim", "sent")),
+            PurchaseStep::Receive => write!(f, "{}", tr!("diterima", "received")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PurchaseStatus{
+    // Still being put together, lines can change
+    Draft,
+    // Sent to the supplier, waiting for delivery
+    Sent,
+    PartiallyReceived,
+    Received
+}
+
+impl PurchaseStatus{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            PurchaseStatus::Draft => "draft",
+            PurchaseStatus::Sent => "sent",
+            PurchaseStatus::PartiallyReceived => "partially-received",
+            PurchaseStatus::Received => "received",
+        }
+    }
+}
+
+impl FromStr for PurchaseStatus{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        match s.trim().to_lowercase().as_str(){
+            "draft" => Ok(PurchaseStatus::Draft),
+            "sent" => Ok(PurchaseStatus::Sent),
+            "partially-received" => Ok(PurchaseStatus::PartiallyReceived),
+            "received" => Ok(PurchaseStatus::Received),
+            _ => Err(tr!("status purchase order \"{}\" tidak dikenal", "unknown purchase order status \"{}\"", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Supplier{
+    pub id: i64,
+    pub name: String,
+    pub phone: String,
+    pub note: String
+}
+
+// An item a supplier delivers and what it costs per unit
+#[derive(Clone, Debug, PartialEq)]
+pub struct SupplierItem{
+    pub supplier_id: i64,
+    pub item_id: i64,
+    pub item_name: String,
+    pub unit_cost: Money
+}
+
+pub struct Suppliers{
+    pub list: Vec<Supplier>,
+    pub catalog: Vec<SupplierItem>
+}
+
+impl Suppliers{
+    pub fn new() -> Self{
+        Self{
+            list: Vec::new(),
+            catalog: Vec::new()
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Result<&Supplier, PurchaseError>{
+        let lowercase = name.trim().to_lowercase();
+        self.list.iter()
+            .find(|supplier| supplier.name.to_lowercase() == lowercase)
+            .ok_or_else(|| PurchaseError::SupplierNotFound(name.trim().to_string()))
+    }
+
+    pub fn find_by_id(&self, id: i64) -> Option<&Supplier>{
+        self.list.iter().find(|supplier| supplier.id == id)
+    }
+
+    pub fn add(&mut self, name: &str, phone: &str, note: &str) -> Result<Supplier, PurchaseError>{
+        if self.find(name).is_ok(){
+            return Err(PurchaseError::DuplicateSupplier(name.to_string()));
+        }
+        let supplier = Supplier{
+            id: self.list.iter().map(|supplier| supplier.id + 1).max().unwrap_or(0),
+            name: name.trim().to_string(),
+            phone: phone.trim().to_string(),
+            note: note.trim().to_string()
+        };
+        self.list.push(supplier.clone());
+        Ok(supplier)
+    }
+
+    // Adds the item to what the supplier delivers, or changes its cost
+    pub fn set_cost(&mut self, supplier_id: i64, item: &Item, unit_cost: Money) -> Result<(), PurchaseError>{
+        if unit_cost.is_negative(){
+            return Err(PurchaseError::Item(ItemError::NegativeAmount(unit_cost)));
+        }
+        match self.catalog.iter_mut().find(|entry| entry.supplier_id == supplier_id && entry.item_id == item.id){
+            Some(entry) => {
+                entry.item_name = item.name.clone();
+                entry.unit_cost = unit_cost;
+            },
+            None => self.catalog.push(SupplierItem{
+                supplier_id,
+                item_id: item.id,
+                item_name: item.name.clone(),
+                unit_cost
+            }),
+        }
+        Ok(())
+    }
+
+    pub fn cost(&self, supplier_id: i64, item_id: i64) -> Option<Money>{
+        self.catalog.iter()
+            .find(|entry| entry.supplier_id == supplier_id && entry.item_id == item_id)
+            .map(|entry| entry.unit_cost)
+    }
+
+    pub fn print_suppliers(&self, locale: Locale){
+        if self.list.is_empty(){
+            println!("{}", tr!("Belum ada supplier", "No suppliers yet"));
+            return;
+        }
+        for supplier in &self.list{
+            println!("{:<3}| {:<20}| {:<15}| {}", supplier.id, supplier.name, supplier.phone, supplier.note);
+            for entry in self.catalog.iter().filter(|entry| entry.supplier_id == supplier.id){
+                println!("   |   {:<18}| {}", entry.item_name, locale.money(entry.unit_cost));
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PurchaseLine{
+    pub item_id: i64,
+    pub item_name: String,
+    pub quantity: i64,
+    pub received: i64,
+    pub unit_cost: Money
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PurchaseOrder{
+    pub id: i64,
+    pub supplier_id: i64,
+    pub status: PurchaseStatus,
+    // Local time in YYYY-MM-DD HH:MM:SS
+    pub created: String,
+    // Expected delivery date in YYYY-MM-DD, empty when not agreed
+    pub expected: String,
+    pub lines: Vec<PurchaseLine>
+}
+
+impl PurchaseOrder{
+    pub fn is_open(&self) -> bool{
+        self.status != PurchaseStatus::Received
+    }
+
+    pub fn total_cost(&self) -> Result<Money, MoneyError>{
+        self.lines.iter().try_fold(Money::ZERO, |total, line| total.checked_add(line.unit_cost.checked_mul(line.quantity)?))
+    }
+}
+
+pub struct PurchaseOrders{
+    pub list: Vec<PurchaseOrder>
+}
+
+impl PurchaseOrders{
+    pub fn new() -> Self{
+        Self{
+            list: Vec::new()
+        }
+    }
+
+    pub fn find(&self, id: i64) -> Result<&PurchaseOrder, PurchaseError>{
+        self.list.iter().find(|order| order.id == id).ok_or(PurchaseError::OrderNotFound(id))
+    }
+
+    fn find_mut(&mut self, id: i64) -> Result<&mut PurchaseOrder, PurchaseError>{
+        self.list.iter_mut().find(|order| order.id == id).ok_or(PurchaseError::OrderNotFound(id))
+    }
+
+    // Orders not fully received, those expected first
+    pub fn open(&self) -> Vec<&PurchaseOrder>{
+        let mut open: Vec<_> = self.list.iter().filter(|order| order.is_open()).collect();
+        open.sort_by_key(|order| (order.expected.is_empty(), order.expected.clone(), order.id));
+        open
+    }
+
+    pub fn print_orders(&self, suppliers: &Suppliers, orders: &[&PurchaseOrder], today: &str, locale: Locale) -> Result<(), MoneyError>{
+        if orders.is_empty(){
+            println!("{}", tr!("Tidak ada purchase order", "No purchase orders"));
+            return Ok(());
+        }
+        println!("{}", tr!(
+            "ID |      Pemasok       |       Status       |   Dibuat   |  Perkiraan | Diterima  |  Total Harga Pokok",
+            "ID |      Supplier      |       Status       |  Created   |  Expected  | Received  |  Total Cost"
+        ));
+        for order in orders{
+            let supplier = suppliers.find_by_id(order.supplier_id).map_or("-", |supplier| supplier.name.as_str());
+            let ordered: i64 = order.lines.iter().map(|line| line.quantity).sum();
+            let received: i64 = order.lines.iter().map(|line| line.received).sum();
+            let late = if order.is_open() && !order.expected.is_empty() && order.expected.as_str() < today { tr!(" terlambat", " late") } else { String::new() };
+            println!(
+                "{:<3}| {:<19}| {:<19}| {:<11}| {:<11}| {:<10}| {}{}",
+                order.id, supplier, order.status.as_str(), order.created.split(' ').next().unwrap_or(""),
+                if order.expected.is_empty() { "-" } else { &order.expected },
+                format!("{}/{}", locale.number(received), locale.number(ordered)), locale.money(order.total_cost()?), late
+            );
+        }
+        Ok(())
+    }
+
+    pub fn print_order(&self, suppliers: &Suppliers, order: &PurchaseOrder, locale: Locale) -> Result<(), MoneyError>{
+        let supplier = suppliers.find_by_id(order.supplier_id).map_or("-", |supplier| supplier.name.as_str());
+        println!("{}", tr!("Purchase order {} ke {} ({})", "Purchase order {} to {} ({})", order.id, supplier, order.status.as_str()));
+        for line in &order.lines{
+            println!("{}", tr!(
+                "  {:<20} {:>4} x {:<13} diterima {}", "  {:<20} {:>4} x {:<13} received {}",
+                line.item_name, locale.number(line.quantity), locale.money(line.unit_cost), locale.number(line.received)
+            ));
+        }
+        println!("{}", tr!("  Total: {}", "  Total: {}", locale.money(order.total_cost()?)));
+        Ok(())
+    }
+}
+
+impl Restaurant{
+    // Starts a draft order. Costs come from what the supplier charges for each item, 0 when not listed
+    pub fn create_purchase(&mut self, supplier: &str, lines: &[OrderLine], expected: &str, timestamp: &str) -> Result<PurchaseOrder, PurchaseError>{
+        let supplier_id = self.suppliers.find(supplier)?.id;
+        if lines.is_empty(){
+            return Err(PurchaseError::EmptyOrder);
+        }
+
+        let mut purchase_lines: Vec<PurchaseLine> = Vec::new();
+        for line in lines{
+            let item = self.items.find_item(&line.name)?;
+            match purchase_lines.iter_mut().find(|purchase_line| purchase_line.item_id == item.id){
+                Some(purchase_line) => purchase_line.quantity += line.quantity,
+                None => purchase_lines.push(PurchaseLine{
+                    item_id: item.id,
+                    item_name: item.name.clone(),
+                    quantity: line.quantity,
+                    received: 0,
+                    unit_cost: self.suppliers.cost(supplier_id, item.id).unwrap_or_default()
+                }),
+            }
+        }
+
+        let order = PurchaseOrder{
+            id: self.purchases.list.iter().map(|order| order.id + 1).max().unwrap_or(0),
+            supplier_id,
+            status: PurchaseStatus::Draft,
+            created: timestamp.to_string(),
+            expected: expected.to_string(),
+            lines: purchase_lines
+        };
+        self.purchases.list.push(order.clone());
+        Ok(order)
+    }
+
+    pub fn send_purchase(&mut self, id: i64) -> Result<PurchaseOrder, PurchaseError>{
+        let order = self.purchases.find_mut(id)?;
+        if order.status != PurchaseStatus::Draft{
+            return Err(PurchaseError::WrongStatus(id, order.status.as_str(), PurchaseStep::Send));
+        }
+        order.status = PurchaseStatus::Sent;
+        Ok(order.clone())
+    }
+
+    // Books delivered units into stock. Without lines everything still outstanding is received.
+    // Each received line is kept in the stock history with the supplier and order id
+    pub fn receive_purchase(&mut self, id: i64, lines: &[OrderLine], timestamp: &str) -> Result<PurchaseOrder, PurchaseError>{
+        let order = self.purchases.find(id)?.clone();
+        if !matches!(order.status, PurchaseStatus::Sent | PurchaseStatus::PartiallyReceived){
+            return Err(PurchaseError::WrongStatus(id, order.status.as_str(), PurchaseStep::Receive));
+        }
+
+        // Work out every quantity first, so a bad line receives nothing
+        let mut received = vec![0; order.lines.len()];
+        if lines.is_empty(){
+            for (index, line) in order.lines.iter().enumerate(){
+                received[index] = line.quantity - line.received;
+            }
+        }
+        for line in lines{
+            let name = line.name.to_lowercase();
+            let index = order.lines.iter()
+                .position(|purchase_line| purchase_line.item_name.to_lowercase() == name)
+                .ok_or_else(|| PurchaseError::NotOrdered(line.name.clone()))?;
+            let outstanding = order.lines[index].quantity - order.lines[index].received - received[index];
+            if line.quantity > outstanding{
+                return Err(PurchaseError::TooMany(line.name.clone(), outstanding, line.quantity));
+            }
+            received[index] += line.quantity;
+        }
+        for (purchase_line, quantity) in order.lines.iter().zip(&received){
+            if *quantity > 0 && !self.items.list.contains_key(&purchase_line.item_id){
+                return Err(PurchaseError::Item(ItemError::ItemNotFound(purchase_line.item_name.clone())));
+            }
+            self.items.stock_after(purchase_line.item_id, *quantity)?;
+        }
+
+        let supplier = self.suppliers.find_by_id(order.supplier_id).map(|supplier| supplier.name.clone()).unwrap_or_default();
+        let note = format!("purchase order {}", id);
+        for (purchase_line, quantity) in order.lines.iter().zip(&received){
+            if *quantity > 0{
+                let name = self.items.list[&purchase_line.item_id].name.clone();
+                self.restock(&name, *quantity, purchase_line.unit_cost, &supplier, &note, timestamp)?;
+            }
+        }
+
+        let order = self.purchases.find_mut(id)?;
+        for (purchase_line, quantity) in order.lines.iter_mut().zip(received){
+            purchase_line.received += quantity;
+        }
+        order.status = if order.lines.iter().all(|line| line.received >= line.quantity){
+            PurchaseStatus::Received
+        } else {
+            PurchaseStatus::PartiallyReceived
+        };
+        Ok(order.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Restaurant;
+    use crate::money::{rp, Money};
+    use crate::order::parse_order;
+    use super::{PurchaseError, PurchaseStatus};
+
+    fn restaurant() -> Restaurant{
+        let mut data = Restaurant::new();
+        data.items.add("Risoles", 5, rp(25000), 0, false);
+        data.items.add("Es Teh", 10, rp(5000), 0, false);
+        data.suppliers.add("Pak Budi", "0812", "").unwrap();
+        let risoles = data.items.find_item("risoles").unwrap();
+        data.suppliers.set_cost(0, &risoles, rp(15000)).unwrap();
+        data
+    }
+
+    #[test]
+    fn supplier_test(){
+        let mut data = restaurant();
+        assert_eq!(data.suppliers.add("pak budi", "", ""), Err(PurchaseError::DuplicateSupplier("pak budi".to_string())));
+        let risoles = data.items.find_item("risoles").unwrap();
+        data.suppliers.set_cost(0, &risoles, rp(16000)).unwrap();
+        assert_eq!(data.suppliers.catalog.len(), 1);
+        assert_eq!(data.suppliers.cost(0, risoles.id), Some(rp(16000)));
+    }
+
+    #[test]
+    fn purchase_order_test(){
+        let mut data = restaurant();
+        let lines = parse_order("risoles=20\nes teh=30").unwrap();
+        let order = data.create_purchase("PAK BUDI", &lines, "2022-11-25", "2022-11-22 08:00:00").unwrap();
+        assert_eq!(order.total_cost(), Ok(rp(300000)));
+
+        // A draft cannot be received
+        assert!(matches!(data.receive_purchase(order.id, &[], "2022-11-22 09:00:00"), Err(PurchaseError::WrongStatus(..))));
+        data.send_purchase(order.id).unwrap();
+
+        // Part of the risoles first, too many es teh is refused without receiving anything
+        assert_eq!(
+            data.receive_purchase(order.id, &parse_order("risoles=5\nes teh=31").unwrap(), "2022-11-23 09:00:00"),
+            Err(PurchaseError::TooMany("es teh".to_string(), 30, 31))
+        );
+        let order = data.receive_purchase(order.id, &parse_order("risoles=5").unwrap(), "2022-11-23 09:00:00").unwrap();
+        assert_eq!(order.status, PurchaseStatus::PartiallyReceived);
+        assert_eq!(data.items.find_item("risoles").unwrap().quantity, 10);
+        assert_eq!(data.purchases.open().len(), 1);
+
+        // The rest arrives
+        let order = data.receive_purchase(order.id, &[], "2022-11-25 09:00:00").unwrap();
+        assert_eq!(order.status, PurchaseStatus::Received);
+        assert_eq!(data.items.find_item("risoles").unwrap().quantity, 25);
+        assert_eq!(data.items.find_item("es teh").unwrap().quantity, 40);
+        assert!(data.purchases.open().is_empty());
+
+        let history: Vec<_> = data.movements.list.iter().map(|movement| (movement.quantity, movement.unit_cost)).collect();
+        assert_eq!(history, vec![(5, rp(15000)), (15, rp(15000)), (30, Money::ZERO)]);
+        assert_eq!(data.movements.list[0].supplier, "Pak Budi");
+    }
+}
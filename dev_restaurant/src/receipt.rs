@@ -0,0 +1,214 @@
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    str::FromStr
+};
+
+use crate::{Order, Restaurant, Sale};
+use crate::i18n::tr;
+use crate::locale::Locale;
+use crate::money::{Money, MoneyError};
+
+const STORE_NAME: &str = "Dev Restaurant";
+const WIDTH: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReceiptFormat{
+    Text,
+    Html
+}
+
+impl FromStr for ReceiptFormat{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        match s.to_lowercase().as_str(){
+            "text" | "txt" => Ok(ReceiptFormat::Text),
+            "html" => Ok(ReceiptFormat::Html),
+            _ => Err(tr!("format struk \"{}\" tidak dikenal, seharusnya text atau html", "unknown receipt format \"{}\", expected text or html", s)),
+        }
+    }
+}
+
+// Everything printed on a receipt, built from an order and its ledger lines
+pub struct Receipt{
+    pub order: Order,
+    // Each line with its menu price before promotions
+    pub lines: Vec<(Sale, Money)>,
+    // Net amount, service charge and tax of all lines, the item lines themselves show menu prices
+    pub subtotal: Money,
+    pub service: Money,
+    pub tax: Money,
+    pub total: Money,
+    pub change: Money
+}
+
+impl Receipt{
+    // Refund lines are left out, a reprint shows the order as it was paid
+    pub fn new(order: &Order, sales: &[Sale]) -> Result<Self, MoneyError>{
+        let lines = sales.iter()
+            .filter(|sale| sale.order_id == order.id && !sale.is_refund())
+            .map(|sale| Ok((sale.clone(), sale.list_price()?)))
+            .collect::<Result<Vec<_>, MoneyError>>()?;
+        let subtotal = lines.iter().try_fold(Money::ZERO, |sum, (sale, _)| sum.checked_add(sale.net()?))?;
+        let service = Money::sum(lines.iter().map(|(sale, _)| sale.service))?;
+        let tax = Money::sum(lines.iter().map(|(sale, _)| sale.tax))?;
+        let total = Money::sum(lines.iter().map(|(sale, _)| sale.total))?;
+
+        Ok(Self{
+            order: order.clone(),
+            lines,
+            subtotal,
+            service,
+            tax,
+            total,
+            change: order.paid.checked_sub(total)?
+        })
+    }
+
+    pub fn for_order(data: &Restaurant, order_id: i64) -> Result<Option<Self>, MoneyError>{
+        data.orders.find(order_id).map(|order| Receipt::new(order, &data.ledger.list)).transpose()
+    }
+
+    fn totals(&self) -> [(String, Money); 6]{
+        [
+            (tr!("Subtotal", "Subtotal"), self.subtotal),
+            (tr!("Layanan", "Service"), self.service),
+            (tr!("PPN", "VAT"), self.tax),
+            (tr!("Total", "Total"), self.total),
+            (tr!("Bayar", "Paid"), self.order.paid),
+            (tr!("Kembali", "Change"), self.change),
+        ]
+    }
+
+    fn footer() -> String{
+        tr!("Terima kasih atas kunjungan Anda", "Thank you for your visit")
+    }
+
+    pub fn to_text(&self, locale: Locale) -> String{
+        let mut text = String::new();
+        let rule = |ch: char| ch.to_string().repeat(WIDTH);
+
+        text.push_str(&center(STORE_NAME));
+        text.push_str(&rule('='));
+        text.push('\n');
+        text.push_str(&spread(&tr!("Pesanan #{}", "Order #{}", self.order.number), &self.order.timestamp));
+        text.push_str(&rule('-'));
+        text.push('\n');
+        for (line, price) in &self.lines{
+            text.push_str(&format!("{}\n", line.item_name));
+            text.push_str(&spread(&format!("  {} x {}", locale.number(line.quantity), locale.money(line.unit_price)), &locale.money(*price)));
+            if !line.discount.is_zero(){
+                text.push_str(&spread(&format!("  {}", line.promotion), &format!("-{}", locale.money(line.discount))));
+            }
+        }
+        text.push_str(&rule('-'));
+        text.push('\n');
+        for (label, amount) in self.totals(){
+            text.push_str(&spread(&label, &locale.money(amount)));
+        }
+        text.push_str(&rule('='));
+        text.push('\n');
+        text.push_str(&center(&Self::footer()));
+        text
+    }
+
+    pub fn to_html(&self, locale: Locale) -> String{
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!("<title>{} - {}</title>\n", STORE_NAME, tr!("Pesanan #{}", "Order #{}", self.order.number)));
+        html.push_str("<style>body{font-family:monospace;width:24em}td.amount{text-align:right}</style>\n");
+        html.push_str("</head>\n<body>\n");
+        html.push_str(&format!("<h1>{}</h1>\n", escape_html(STORE_NAME)));
+        html.push_str(&format!("<p>{}<br>{}</p>\n", tr!("Pesanan #{}", "Order #{}", self.order.number), escape_html(&self.order.timestamp)));
+        html.push_str("<table>\n");
+        for (line, price) in &self.lines{
+            html.push_str(&format!(
+                "<tr><td>{}</td><td class=\"amount\">{} x {}</td><td class=\"amount\">{}</td></tr>\n",
+                escape_html(&line.item_name), locale.number(line.quantity), locale.money(line.unit_price), locale.money(*price)
+            ));
+            if !line.discount.is_zero(){
+                html.push_str(&format!(
+                    "<tr><td colspan=\"2\">{}</td><td class=\"amount\">-{}</td></tr>\n",
+                    escape_html(&line.promotion), locale.money(line.discount)
+                ));
+            }
+        }
+        for (label, amount) in self.totals(){
+            html.push_str(&format!("<tr><th colspan=\"2\">{}</th><td class=\"amount\">{}</td></tr>\n", escape_html(&label), locale.money(amount)));
+        }
+        html.push_str("</table>\n");
+        html.push_str(&format!("<p>{}</p>\n", escape_html(&Self::footer())));
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    pub fn render(&self, format: ReceiptFormat, locale: Locale) -> String{
+        match format{
+            ReceiptFormat::Text => self.to_text(locale),
+            ReceiptFormat::Html => self.to_html(locale),
+        }
+    }
+
+    // Writes the receipt to a file, or to stdout when the path is "-"
+    pub fn write(&self, format: ReceiptFormat, locale: Locale, output: &Path) -> std::io::Result<()>{
+        let receipt = self.render(format, locale);
+        if output == Path::new("-"){
+            std::io::stdout().write_all(receipt.as_bytes())
+        } else {
+            fs::write(output, receipt)
+        }
+    }
+}
+
+fn center(text: &str) -> String{
+    format!("{}\n", format!("{:^width$}", text, width = WIDTH).trim_end())
+}
+
+// Left text and right-aligned value on one line
+fn spread(left: &str, right: &str) -> String{
+    let padding = WIDTH.saturating_sub(left.chars().count() + right.chars().count()).max(1);
+    format!("{}{}{}\n", left, " ".repeat(padding), right)
+}
+
+fn escape_html(text: &str) -> String{
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Restaurant;
+    use crate::locale::Locale;
+    use crate::money::rp;
+    use crate::order::parse_order;
+    use super::Receipt;
+
+    #[test]
+    fn receipt_test(){
+        let mut data = Restaurant::new();
+        data.items.add("Risoles", 5, rp(25000), 0, false);
+        data.items.add("Es <Teh>", 10, rp(5000), 0, false);
+
+        let lines = parse_order("risoles=2\nes <teh>=1").unwrap();
+        let (order, sales) = data.order(&lines, Some(rp(100000)), "2022-11-22 10:00:00").unwrap();
+        data.void(sales[1].id, "", "2022-11-22 10:01:00").unwrap();
+
+        // The reprint still shows the order as it was paid
+        let receipt = Receipt::for_order(&data, order.id).unwrap().unwrap();
+        assert_eq!(receipt.lines.len(), 2);
+        assert_eq!(receipt.total, rp(55000));
+        assert_eq!(receipt.change, rp(45000));
+
+        let text = receipt.to_text(Locale::Indonesian);
+        assert!(text.contains("Pesanan #1"));
+        assert!(text.lines().all(|line| line.chars().count() <= 40));
+        assert!(text.contains("  2 x Rp 25.000                Rp 50.000"));
+        assert!(text.contains("Kembali                        Rp 45.000"));
+        assert!(receipt.to_text(Locale::English).contains("Kembali                       IDR 45,000"));
+        assert!(receipt.to_html(Locale::Indonesian).contains("es &lt;teh&gt;"));
+    }
+}
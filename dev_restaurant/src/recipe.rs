@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use thiserror::Error;
+
+use crate::{Item, Restaurant};
+use crate::i18n::tr;
+use crate::locale::Locale;
+use crate::margin::average_cost;
+use crate::money::{Money, MoneyError};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum RecipeError{
+    #[error("{}", tr!("bahan {} tidak ditemukan", "ingredient {} not found", .0))]
+    IngredientNotFound(String),
+
+    #[error("{}", tr!("bahan {} tidak mencukupi, tersedia {} dari {}", "not enough {}, {} available of {}", .0, .1, .2))]
+    NotEnoughIngredient(String, i64, i64),
+
+    #[error("{}", tr!("jumlah bahan harus lebih dari 0, bukan {}", "ingredient quantity must be more than 0, not {}", .0))]
+    InvalidQuantity(i64),
+
+    #[error("{}", tr!("stok bahan {} akan terlalu besar", "stock of {} would be too large", .0))]
+    StockTooLarge(String),
+
+    #[error("{}", tr!("harga bahan tidak boleh negatif {}", "ingredient cost must not be negative {}", .0))]
+    NegativeCost(Money),
+
+    #[error("{0}")]
+    Money(#[from] MoneyError),
+}
+
+impl RecipeError{
+    pub fn code(&self) -> &'static str{
+        match self{
+            RecipeError::IngredientNotFound(_) => "ingredient_not_found",
+            RecipeError::NotEnoughIngredient(..) => "not_enough_ingredient",
+            RecipeError::InvalidQuantity(_) => "invalid_quantity",
+            RecipeError::StockTooLarge(_) => "invalid_value",
+            RecipeError::NegativeCost(_) => "negative_amount",
+            RecipeError::Money(e) => e.code(),
+        }
+    }
+}
+
+// Raw stock the kitchen cooks with, counted in its own unit (gram, ml, butir, ...)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ingredient{
+    pub id: i64,
+    pub name: String,
+    pub unit: String,
+    pub quantity: i64,
+    // Price of one unit, 0 when unknown
+    pub cost: Money
+}
+
+pub struct Ingredients{
+    pub list: Vec<Ingredient>
+}
+
+impl Ingredients{
+    pub fn new() -> Self{
+        Self{
+            list: Vec::new()
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Result<&Ingredient, RecipeError>{
+        let lowercase = name.trim().to_lowercase();
+        self.list.iter()
+            .find(|ingredient| ingredient.name == lowercase)
+            .ok_or_else(|| RecipeError::IngredientNotFound(name.trim().to_string()))
+    }
+
+    fn get(&self, id: i64) -> Option<&Ingredient>{
+        self.list.iter().find(|ingredient| ingredient.id == id)
+    }
+
+    // Adds a new ingredient or changes the given values of an existing one
+    pub fn set(&mut self, name: &str, quantity: Option<i64>, unit: Option<&str>, cost: Option<Money>) -> Result<Ingredient, RecipeError>{
+        if let Some(quantity) = quantity.filter(|quantity| *quantity < 0){
+            return Err(RecipeError::InvalidQuantity(quantity));
+        }
+        if let Some(cost) = cost.filter(|cost| cost.is_negative()){
+            return Err(RecipeError::NegativeCost(cost));
+        }
+        let lowercase = name.trim().to_lowercase();
+        let index = match self.list.iter().position(|ingredient| ingredient.name == lowercase){
+            Some(index) => index,
+            None => {
+                self.list.push(Ingredient{
+                    id: self.list.iter().map(|ingredient| ingredient.id + 1).max().unwrap_or(0),
+                    name: lowercase,
+                    unit: String::new(),
+                    quantity: 0,
+                    cost: Money::ZERO
+                });
+                self.list.len() - 1
+            },
+        };
+
+        let ingredient = &mut self.list[index];
+        if let Some(quantity) = quantity{
+            ingredient.quantity = quantity;
+        }
+        if let Some(unit) = unit{
+            ingredient.unit = unit.trim().to_string();
+        }
+        if let Some(cost) = cost{
+            ingredient.cost = cost;
+        }
+        Ok(ingredient.clone())
+    }
+
+    // Adds delivered units, a unit cost other than 0 is averaged into the cost of the ingredient
+    pub fn restock(&mut self, name: &str, quantity: i64, unit_cost: Money) -> Result<Ingredient, RecipeError>{
+        if quantity <= 0{
+            return Err(RecipeError::InvalidQuantity(quantity));
+        }
+        if unit_cost.is_negative(){
+            return Err(RecipeError::NegativeCost(unit_cost));
+        }
+        let id = self.find(name)?.id;
+        let ingredient = self.list.iter_mut().find(|ingredient| ingredient.id == id).ok_or_else(|| RecipeError::IngredientNotFound(name.to_string()))?;
+        // Worked out before the cost, so a delivery that does not fit changes nothing
+        let stock = ingredient.quantity.checked_add(quantity).ok_or_else(|| RecipeError::StockTooLarge(ingredient.name.clone()))?;
+        ingredient.cost = average_cost(ingredient.quantity, ingredient.cost, quantity, unit_cost)?;
+        ingredient.quantity = stock;
+        Ok(ingredient.clone())
+    }
+
+    // Fails on the first ingredient that is short, so nothing is taken out unless everything is there
+    pub fn check(&self, needed: &[(i64, i64)]) -> Result<(), RecipeError>{
+        for (id, quantity) in needed{
+            match self.get(*id){
+                Some(ingredient) if ingredient.quantity < *quantity => {
+                    return Err(RecipeError::NotEnoughIngredient(ingredient.name.clone(), ingredient.quantity, *quantity));
+                },
+                Some(_) => {},
+                None => return Err(RecipeError::IngredientNotFound(id.to_string())),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn consume(&mut self, needed: &[(i64, i64)]){
+        for (id, quantity) in needed{
+            if let Some(ingredient) = self.list.iter_mut().find(|ingredient| ingredient.id == *id){
+                ingredient.quantity -= quantity;
+            }
+        }
+    }
+
+    pub fn print_ingredients(&self, locale: Locale){
+        if self.list.is_empty(){
+            println!("{}", tr!("Belum ada bahan", "No ingredients yet"));
+            return;
+        }
+        println!("{}", tr!(
+            "ID |      Nama      |    Stok     |   Satuan   | Harga Pokok",
+            "ID |      Name      |    Stock    |    Unit    | Cost"
+        ));
+        for ingredient in &self.list{
+            println!(
+                "{:<3}| {:<15}| {:<12}| {:<11}| {}",
+                ingredient.id, ingredient.name, locale.number(ingredient.quantity), ingredient.unit, locale.money(ingredient.cost)
+            );
+        }
+    }
+}
+
+// How much of an ingredient one portion of a menu item uses
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecipeLine{
+    pub item_id: i64,
+    pub ingredient_id: i64,
+    pub quantity: i64
+}
+
+pub struct Recipes{
+    pub list: Vec<RecipeLine>
+}
+
+impl Recipes{
+    pub fn new() -> Self{
+        Self{
+            list: Vec::new()
+        }
+    }
+
+    // Items with a recipe are cooked to order: their ingredients are the stock, not the item quantity
+    pub fn has_recipe(&self, item_id: i64) -> bool{
+        self.list.iter().any(|line| line.item_id == item_id)
+    }
+
+    pub fn recipe(&self, item_id: i64) -> Vec<&RecipeLine>{
+        self.list.iter().filter(|line| line.item_id == item_id).collect()
+    }
+
+    // Replaces the recipe of an item, no lines removes it
+    pub fn set(&mut self, item_id: i64, lines: &[(i64, i64)]) -> Result<(), RecipeError>{
+        if let Some((_, quantity)) = lines.iter().find(|(_, quantity)| *quantity <= 0){
+            return Err(RecipeError::InvalidQuantity(*quantity));
+        }
+        self.list.retain(|line| line.item_id != item_id);
+        for (ingredient_id, quantity) in lines{
+            match self.list.iter_mut().find(|line| line.item_id == item_id && line.ingredient_id == *ingredient_id){
+                Some(line) => line.quantity += quantity,
+                None => self.list.push(RecipeLine{
+                    item_id,
+                    ingredient_id: *ingredient_id,
+                    quantity: *quantity
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    // Total of every ingredient used by `quantity` portions of each item, as (ingredient id, quantity)
+    pub fn requirements(&self, sold: &[(Item, i64)]) -> Vec<(i64, i64)>{
+        let mut needed: Vec<(i64, i64)> = Vec::new();
+        for (item, quantity) in sold{
+            for line in self.recipe(item.id){
+                match needed.iter_mut().find(|(id, _)| *id == line.ingredient_id){
+                    Some((_, total)) => *total += line.quantity * quantity,
+                    None => needed.push((line.ingredient_id, line.quantity * quantity)),
+                }
+            }
+        }
+        needed
+    }
+
+    // What the ingredients of one portion cost
+    pub fn cost(&self, item_id: i64, ingredients: &Ingredients) -> Result<Money, MoneyError>{
+        self.recipe(item_id).iter().try_fold(Money::ZERO, |cost, line| {
+            let unit_cost = ingredients.get(line.ingredient_id).map_or(Money::ZERO, |ingredient| ingredient.cost);
+            cost.checked_add(unit_cost.checked_mul(line.quantity)?)
+        })
+    }
+
+    // Portions of each item with a recipe that the ingredients in stock still make
+    pub fn portions(&self, ingredients: &Ingredients) -> HashMap<i64, i64>{
+        let mut portions: HashMap<i64, i64> = HashMap::new();
+        for line in &self.list{
+            let available = ingredients.get(line.ingredient_id).map_or(0, |ingredient| ingredient.quantity.max(0) / line.quantity);
+            portions.entry(line.item_id)
+                .and_modify(|portions| *portions = (*portions).min(available))
+                .or_insert(available);
+        }
+        portions
+    }
+}
+
+impl Restaurant{
+    // Sets the recipe of a menu item from (ingredient name, quantity per portion) pairs
+    pub fn set_recipe(&mut self, item: &Item, lines: &[(String, i64)]) -> Result<(), RecipeError>{
+        let lines = lines.iter()
+            .map(|(name, quantity)| Ok((self.ingredients.find(name)?.id, *quantity)))
+            .collect::<Result<Vec<_>, RecipeError>>()?;
+        self.recipes.set(item.id, &lines)
+    }
+
+    pub fn print_recipe(&self, item: &Item, locale: Locale){
+        let lines = self.recipes.recipe(item.id);
+        if lines.is_empty(){
+            println!("{}", tr!("{} belum punya resep", "{} has no recipe yet", item.name));
+            return;
+        }
+        println!("{}", tr!("Resep {} per porsi:", "Recipe of {} per portion:", item.name));
+        for line in lines{
+            match self.ingredients.get(line.ingredient_id){
+                Some(ingredient) => println!("  {:<20} {} {}", ingredient.name, locale.number(line.quantity), ingredient.unit),
+                None => println!("  {:<20} {}", line.ingredient_id, locale.number(line.quantity)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Restaurant;
+    use crate::money::rp;
+    use crate::order::{parse_order, OrderError};
+    use super::RecipeError;
+
+    fn restaurant() -> Restaurant{
+        let mut data = Restaurant::new();
+        data.items.add("Nasi Goreng", 0, rp(25000), 0, false);
+        data.items.add("Es Teh", 10, rp(5000), 0, false);
+        data.ingredients.set("Nasi", Some(1000), Some("gram"), None).unwrap();
+        data.ingredients.set("Telur", Some(3), Some("butir"), None).unwrap();
+        data.ingredients.set("Minyak", Some(100), Some("ml"), None).unwrap();
+
+        let nasi_goreng = data.items.find_item("nasi goreng").unwrap();
+        let recipe = [("nasi".to_string(), 200), ("telur".to_string(), 1), ("minyak".to_string(), 15)];
+        data.set_recipe(&nasi_goreng, &recipe).unwrap();
+        data
+    }
+
+    #[test]
+    fn recipe_test(){
+        let mut data = restaurant();
+        let nasi_goreng = data.items.find_item("nasi goreng").unwrap();
+
+        // Eggs run out first
+        assert_eq!(data.recipes.portions(&data.ingredients)[&nasi_goreng.id], 3);
+        assert!(!data.recipes.has_recipe(data.items.find_item("es teh").unwrap().id));
+
+        assert_eq!(data.set_recipe(&nasi_goreng, &[("garam".to_string(), 1)]), Err(RecipeError::IngredientNotFound("garam".to_string())));
+        assert_eq!(data.set_recipe(&nasi_goreng, &[("nasi".to_string(), 0)]), Err(RecipeError::InvalidQuantity(0)));
+        assert_eq!(data.recipes.recipe(nasi_goreng.id).len(), 3);
+
+        let nasi = data.ingredients.find("nasi").unwrap().clone();
+        assert_eq!(data.ingredients.restock("nasi", i64::MAX, rp(1)), Err(RecipeError::StockTooLarge(nasi.name.clone())));
+        assert_eq!(data.ingredients.find("nasi").unwrap(), &nasi);
+    }
+
+    #[test]
+    fn order_uses_ingredients_test(){
+        let mut data = restaurant();
+
+        // Cooked to order: the item stock of 0 does not matter, the ingredients do
+        let (_, sales) = data.order(&parse_order("nasi goreng=2\nes teh=1").unwrap(), None, "2022-11-22 10:00:00").unwrap();
+        assert_eq!(sales[0].quantity, 2);
+        assert_eq!(data.ingredients.find("nasi").unwrap().quantity, 600);
+        assert_eq!(data.ingredients.find("telur").unwrap().quantity, 1);
+        assert_eq!(data.items.find_item("nasi goreng").unwrap().quantity, 0);
+
+        // Two more would need 2 eggs, nothing is sold
+        assert_eq!(
+            data.order(&parse_order("es teh=1\nnasi goreng=2").unwrap(), None, "2022-11-22 10:05:00"),
+            Err(OrderError::Ingredient(RecipeError::NotEnoughIngredient("telur".to_string(), 1, 2)))
+        );
+        assert_eq!(data.items.find_item("es teh").unwrap().quantity, 9);
+
+        // A refund does not put cooked food back into the ingredients
+        data.void(sales[0].id, "", "2022-11-22 10:10:00").unwrap();
+        assert_eq!(data.ingredients.find("nasi").unwrap().quantity, 600);
+        assert_eq!(data.items.find_item("nasi goreng").unwrap().quantity, 0);
+    }
+}
@@ -0,0 +1,356 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    thread
+};
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::{ItemChanges, ItemError, Restaurant};
+use crate::events::{self, Event};
+use crate::i18n::tr;
+use crate::money::{Money, MoneyError};
+use crate::order::{OrderError, OrderLine};
+use crate::output::{Failure, ItemRow, ReportRow, SaleRow};
+use crate::period::{parse_date, Period};
+use crate::storage::{Storage, StorageError};
+
+// A failed request, answered with the same error object as --format json
+#[derive(Debug, PartialEq)]
+struct ApiError{
+    status: u16,
+    failure: Failure
+}
+
+impl ApiError{
+    // Missing things are 404, everything else the client sent wrong is 400
+    fn new(error: &'static str, message: String) -> Self{
+        let status = if error.ends_with("not_found") { 404 } else { 400 };
+        Self{
+            status,
+            failure: Failure{ error, message }
+        }
+    }
+}
+
+impl From<ItemError> for ApiError{
+    fn from(e: ItemError) -> Self{
+        ApiError::new(e.code(), e.to_string())
+    }
+}
+
+impl From<OrderError> for ApiError{
+    fn from(e: OrderError) -> Self{
+        ApiError::new(e.code(), e.to_string())
+    }
+}
+
+impl From<MoneyError> for ApiError{
+    fn from(e: MoneyError) -> Self{
+        ApiError::new(e.code(), e.to_string())
+    }
+}
+
+impl From<StorageError> for ApiError{
+    fn from(e: StorageError) -> Self{
+        Self{
+            status: 500,
+            failure: Failure{ error: e.code(), message: e.to_string() }
+        }
+    }
+}
+
+impl From<serde_json::Error> for ApiError{
+    fn from(e: serde_json::Error) -> Self{
+        ApiError::new("invalid_json", e.to_string())
+    }
+}
+
+// The answer to a request, `changed` is set when the data has to be saved.
+// Events go to the kitchen feed once the change is saved
+#[derive(Debug)]
+struct Reply{
+    status: u16,
+    body: String,
+    changed: bool,
+    events: Vec<Event>
+}
+
+impl Reply{
+    fn json<T: Serialize>(status: u16, value: &T, changed: bool) -> Result<Self, ApiError>{
+        Ok(Self{
+            status,
+            body: serde_json::to_string(value)?,
+            changed,
+            events: Vec::new()
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct NewItem{
+    name: String,
+    #[serde(flatten)]
+    changes: ItemChanges
+}
+
+#[derive(Deserialize)]
+struct PurchaseLine{
+    name: String,
+    quantity: i64
+}
+
+#[derive(Deserialize)]
+struct NewPurchase{
+    lines: Vec<PurchaseLine>,
+    #[serde(default)]
+    paid: Option<Money>
+}
+
+// An order with the lines sold in it, refunds of those lines included
+#[derive(Serialize)]
+struct PurchaseRow{
+    id: i64,
+    number: i64,
+    timestamp: String,
+    paid: Money,
+    total: Money,
+    sales: Vec<SaleRow>
+}
+
+impl Restaurant{
+    fn purchase_row(&self, order_id: i64) -> Result<Option<PurchaseRow>, MoneyError>{
+        let Some(order) = self.orders.find(order_id) else { return Ok(None) };
+        let sales: Vec<_> = self.ledger.list.iter().filter(|sale| sale.order_id == order.id).collect();
+        Ok(Some(PurchaseRow{
+            id: order.id,
+            number: order.number,
+            timestamp: order.timestamp.clone(),
+            paid: order.paid,
+            total: Money::sum(sales.iter().map(|sale| sale.total))?,
+            sales: sales.iter().map(|sale| {
+                let low = self.items.list.get(&sale.item_id).is_some_and(|item| item.is_low());
+                SaleRow::new(sale, order.number, low)
+            }).collect()
+        }))
+    }
+
+    fn item_row(&self, id: &str) -> Result<ItemRow, ApiError>{
+        let item = id.parse::<i64>().ok()
+            .and_then(|id| self.items.list.get(&id))
+            .ok_or_else(|| ItemError::ItemNotFound(id.to_string()))?;
+        let portions = self.recipes.portions(&self.ingredients);
+        Ok(ItemRow::new(item, portions.get(&item.id).copied()))
+    }
+}
+
+// Value of `name` in a query string like from=2022-11-01&to=2022-11-30
+fn query_value<'a>(query: &'a str, name: &str) -> Option<&'a str>{
+    query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn query_date(query: &str, name: &str) -> Result<Option<chrono::NaiveDate>, ApiError>{
+    match query_value(query, name){
+        None | Some("") => Ok(None),
+        Some(date) => parse_date(date).map(Some).ok_or_else(|| ApiError::new(
+            "invalid_value", tr!("Nilai tidak valid pada parameter {}: {}", "Invalid value found on {} parameter: {}", name, date)
+        )),
+    }
+}
+
+// Works out a request against the data in memory, nothing is saved here
+fn route(data: &mut Restaurant, method: &str, url: &str, body: &str, timestamp: &str) -> Result<Reply, ApiError>{
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let segments: Vec<_> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+
+    match (method, segments.as_slice()){
+        ("GET", ["items"]) => {
+            let portions = data.recipes.portions(&data.ingredients);
+            let rows: Vec<_> = data.items.get_item_list().iter().map(|item| ItemRow::new(item, portions.get(&item.id).copied())).collect();
+            Reply::json(200, &rows, false)
+        },
+        ("GET", ["items", id]) => Reply::json(200, &data.item_row(id)?, false),
+        ("POST", ["items"]) => {
+            let new: NewItem = serde_json::from_str(body)?;
+            if data.items.find_item(&new.name).is_ok(){
+                let message = tr!("Makanan {} sudah ada", "Item {} already exists", new.name);
+                return Err(ApiError{ status: 409, failure: Failure{ error: "item_exists", message } });
+            }
+            let item = match data.change_item(&new.name, &new.changes, timestamp){
+                Err(ItemError::ItemNotFound(_)) => return Err(ApiError::new(
+                    "incomplete_item", tr!("Makanan baru {} harus diberi kuantitas dan harga", "New item {} needs a quantity and a price", new.name)
+                )),
+                item => item?,
+            };
+            Reply::json(201, &data.item_row(&item.id.to_string())?, true)
+        },
+        ("PUT" | "PATCH", ["items", id]) => {
+            let name = data.item_row(id)?.name;
+            let changes: ItemChanges = serde_json::from_str(body)?;
+            data.change_item(&name, &changes, timestamp)?;
+            Reply::json(200, &data.item_row(id)?, true)
+        },
+        ("DELETE", ["items", id]) => {
+            let row = data.item_row(id)?;
+            data.items.delete(&row.name)?;
+            Reply::json(200, &row, true)
+        },
+        ("GET", ["purchases"]) => {
+            let date = query_date(query, "date")?.map_or_else(|| timestamp.split(' ').next().unwrap_or(timestamp).to_string(), |date| date.to_string());
+            let ids: Vec<_> = data.orders.list.iter().filter(|order| order.date() == date).map(|order| order.id).collect();
+            let rows = ids.into_iter().filter_map(|id| data.purchase_row(id).transpose()).collect::<Result<Vec<_>, _>>()?;
+            Reply::json(200, &rows, false)
+        },
+        ("GET", ["purchases", id]) => {
+            let row = id.parse::<i64>().ok().map(|id| data.purchase_row(id)).transpose()?.flatten();
+            match row{
+                Some(row) => Reply::json(200, &row, false),
+                None => Err(ApiError::new("order_not_found", tr!("Pesanan {} tidak ditemukan", "Order {} not found", id))),
+            }
+        },
+        ("POST", ["purchases"]) => {
+            let new: NewPurchase = serde_json::from_str(body)?;
+            let lines: Vec<_> = new.lines.into_iter().map(|line| OrderLine{ name: line.name, quantity: line.quantity }).collect();
+            let (order, sales) = data.order(&lines, new.paid, timestamp)?;
+            let mut reply = Reply::json(201, &data.purchase_row(order.id)?, true)?;
+            reply.events = data.order_events(&order, &sales);
+            Ok(reply)
+        },
+        ("GET", ["reports"]) => {
+            let period = match query_value(query, "group_by"){
+                None | Some("") => Period::Day,
+                Some(period) => period.parse::<Period>().map_err(|e| ApiError::new("invalid_value", e))?,
+            };
+            let (from, to) = (query_date(query, "from")?, query_date(query, "to")?);
+            let rows = data.reports.summary(from, to, period)?.iter().map(ReportRow::new).collect::<Result<Vec<_>, _>>()?;
+            Reply::json(200, &rows, false)
+        },
+        _ => Err(ApiError::new("not_found", tr!("Tidak ada {} {}", "No such endpoint {} {}", method, path))),
+    }
+}
+
+// Works out a request and saves what it changed, holding the lock only for that. The data stays loaded
+// between requests and is only read again when the command line or other tills saved meanwhile.
+// When a request or its save fails the data is read back, so memory never runs ahead of storage
+fn handle(storage: &mut dyn Storage, data: &mut Restaurant, method: &str, url: &str, body: &str, timestamp: &str) -> Result<Reply, ApiError>{
+    let _lock = storage.begin(data)?;
+    let result = route(data, method, url, body, timestamp).and_then(|reply| {
+        if reply.changed{
+            storage.save(data)?;
+        }
+        Ok(reply)
+    });
+    if result.is_err(){
+        storage.reload(data, false)?;
+    }
+    result
+}
+
+// Answers requests one at a time until the process is stopped. A request that fails, or a client that
+// goes away before its answer, is logged and the server carries on with the next one
+pub fn serve(address: &str, mut storage: Box<dyn Storage>, mut data: Restaurant, feed: PathBuf, verbose: bool) -> Result<(), StorageError>{
+    let server = Server::http(address).map_err(io::Error::other)?;
+    println!("{}", tr!("Server berjalan di http://{}", "Server listening on http://{}", address));
+
+    for mut request in server.incoming_requests(){
+        let mut body = String::new();
+        let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let method = request.method().as_str().to_string();
+        let url = request.url().to_string();
+        if method == "GET" && url.split('?').next() == Some("/events"){
+            if verbose{
+                println!("{} {} {}", timestamp, method, url);
+            }
+            if let Err(e) = open_feed(request, &feed){
+                println!("{}", tr!("Gagal menjawab {} {}: {}", "Could not answer {} {}: {}", method, url, e));
+            }
+            continue;
+        }
+        // The body is read before taking the lock, a slow client must not hold up the other tills
+        let reply = match request.as_reader().read_to_string(&mut body){
+            Ok(_) => handle(storage.as_mut(), &mut data, &method, &url, &body, &timestamp),
+            Err(e) => Err(ApiError::new("invalid_body", e.to_string())),
+        };
+
+        let (status, body) = match reply{
+            Ok(reply) => {
+                // The change is saved already, a feed that cannot be written must not turn it into a failure
+                if let Err(message) = events::publish_saved(&feed, &reply.events){
+                    println!("{}", message);
+                }
+                (reply.status, reply.body)
+            },
+            Err(e) => (e.status, serde_json::to_string(&e.failure).unwrap_or_default()),
+        };
+        if verbose{
+            println!("{} {} {} {}", timestamp, method, url, status);
+        }
+
+        let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).expect("valid header");
+        if let Err(e) = request.respond(Response::from_string(body).with_status_code(status).with_header(content_type)){
+            println!("{}", tr!("Gagal menjawab {} {}: {}", "Could not answer {} {}: {}", method, url, e));
+        }
+    }
+    Ok(())
+}
+
+// Hands a WebSocket request over to its own thread that follows the kitchen feed, anything else on /events is refused
+fn open_feed(request: Request, feed: &Path) -> io::Result<()>{
+    let header = |name: &'static str| request.headers().iter().find(|header| header.field.equiv(name)).map(|header| header.value.to_string());
+    let key = match (header("Upgrade"), header("Sec-WebSocket-Key")){
+        (Some(upgrade), Some(key)) if upgrade.eq_ignore_ascii_case("websocket") => key,
+        _ => {
+            let failure = Failure{ error: "websocket_required", message: tr!("Feed dapur hanya tersedia lewat WebSocket", "The kitchen feed is only served over WebSocket") };
+            return request.respond(Response::from_string(serde_json::to_string(&failure)?).with_status_code(426));
+        },
+    };
+
+    let accept = Header::from_bytes(&b"Sec-WebSocket-Accept"[..], tungstenite::handshake::derive_accept_key(key.as_bytes()).as_bytes()).expect("valid header");
+    let stream = request.upgrade("websocket", Response::empty(101).with_header(accept));
+    let feed = feed.to_path_buf();
+    thread::spawn(move || events::feed(stream, feed));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Restaurant;
+    use crate::money::Money;
+    use super::route;
+
+    const NOW: &str = "2022-11-22 12:00:00";
+
+    #[test]
+    fn route_test(){
+        let mut data = Restaurant::new();
+
+        let reply = route(&mut data, "POST", "/items", r#"{"name": "Risoles", "quantity": 10, "price": 5000}"#, NOW).unwrap();
+        assert_eq!((reply.status, reply.changed), (201, true));
+        assert!(reply.body.contains(r#""name":"risoles""#));
+        assert_eq!(route(&mut data, "POST", "/items", r#"{"name": "risoles", "quantity": 1, "price": 1}"#, NOW).unwrap_err().status, 409);
+        assert_eq!(route(&mut data, "POST", "/items", r#"{"name": "Lemper", "quantity": 1}"#, NOW).unwrap_err().failure.error, "incomplete_item");
+
+        // Updates only change what is sent, a negative price changes nothing
+        route(&mut data, "PATCH", "/items/0", r#"{"price": "5500.50"}"#, NOW).unwrap();
+        assert_eq!(route(&mut data, "PUT", "/items/0", r#"{"price": -1, "quantity": 3}"#, NOW).unwrap_err().failure.error, "negative_amount");
+        let risoles = data.items.find_item("risoles").unwrap();
+        assert_eq!((risoles.quantity, risoles.price), (10, Money::from_sen(550050)));
+
+        let reply = route(&mut data, "POST", "/purchases", r#"{"lines": [{"name": "risoles", "quantity": 2}]}"#, NOW).unwrap();
+        assert_eq!(reply.status, 201);
+        assert!(reply.body.contains(r#""total":11001"#));
+        assert_eq!(route(&mut data, "POST", "/purchases", r#"{"lines": [{"name": "risoles", "quantity": 9}]}"#, NOW).unwrap_err().failure.error, "not_enough_stock");
+        assert_eq!(route(&mut data, "GET", "/purchases?date=2022-11-22", "", NOW).unwrap().body.matches(r#""number""#).count(), 1);
+
+        let reply = route(&mut data, "GET", "/reports?from=2022-11-01&group_by=month", "", NOW).unwrap();
+        assert!(reply.body.contains(r#""period":"2022-11""#));
+        assert_eq!(route(&mut data, "GET", "/reports?from=kemarin", "", NOW).unwrap_err().failure.error, "invalid_value");
+
+        assert_eq!(route(&mut data, "DELETE", "/items/0", "", NOW).unwrap().status, 200);
+        assert_eq!(route(&mut data, "GET", "/items/0", "", NOW).unwrap_err().status, 404);
+        assert_eq!(route(&mut data, "GET", "/menu", "", NOW).unwrap_err().status, 404);
+    }
+}
@@ -0,0 +1,182 @@
+use std::path::Path;
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::Validator,
+    Context, Editor, Helper
+};
+use structopt::StructOpt;
+
+use crate::{execute, Command, Restaurant};
+use crate::i18n::tr;
+use crate::locale::Locale;
+use crate::storage::{Storage, StorageError};
+
+// Subcommands completed as the first word of a line
+const COMMANDS: [&str; 15] = [
+    "add", "buy", "order", "receipt", "restock", "stock", "ingredient", "recipe",
+    "supplier", "purchase", "delete", "void", "refund", "report", "list"
+];
+
+// Completes command names at the start of a line and item names after, quoted when they hold a space
+struct ShellHelper{
+    items: Vec<String>
+}
+
+impl ShellHelper{
+    fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<Pair>){
+        let before = &line[..pos];
+        let quoted = before.matches('"').count() % 2 == 1;
+        let start = if quoted { before.rfind('"').unwrap_or(0) } else { before.rfind(' ').map_or(0, |space| space + 1) };
+        let word = before[start..].trim_start_matches('"').to_lowercase();
+
+        let names: Vec<&str> = if before[..start].trim().is_empty(){
+            COMMANDS.to_vec()
+        } else {
+            self.items.iter().map(String::as_str).collect()
+        };
+        let pairs = names.into_iter()
+            .filter(|name| name.starts_with(&word))
+            .map(|name| Pair{
+                display: name.to_string(),
+                replacement: if quoted || name.contains(' ') { format!("\"{}\"", name) } else { name.to_string() }
+            })
+            .collect();
+        (start, pairs)
+    }
+}
+
+impl Completer for ShellHelper{
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)>{
+        Ok(self.candidates(line, pos))
+    }
+}
+
+impl Hinter for ShellHelper{
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper{}
+
+impl Validator for ShellHelper{}
+
+impl Helper for ShellHelper{}
+
+// Splits a line into arguments like a shell would, so "ikan asin" stays one argument
+fn split_line(line: &str) -> Result<Vec<String>, String>{
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars(){
+        match (quote, c){
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => arg.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            },
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (None, c) => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some(){
+        return Err(tr!("Tanda kutip tidak ditutup", "Unclosed quote"));
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+// Reads commands until exit or Ctrl+D, without starting a new process for each. Every command holds
+// the lock and saves as it does from the command line. The data is only read again when another process
+// saved since the last command, or to roll back a command that failed
+pub fn run(storage: &mut dyn Storage, data: &mut Restaurant, feed: &Path, locale: Locale) -> Result<(), StorageError>{
+    let mut editor: Editor<ShellHelper, DefaultHistory> = Editor::new().map_err(readline_error)?;
+    editor.set_helper(Some(ShellHelper{ items: Vec::new() }));
+    println!("{}", tr!(
+        "Ketik perintah seperti buy \"ikan asin\" 2, help untuk daftar perintah, exit untuk keluar",
+        "Type commands like buy \"ikan asin\" 2, help for the list of commands, exit to leave"
+    ));
+
+    loop{
+        if let Some(helper) = editor.helper_mut(){
+            helper.items = data.items.get_item_list().into_iter().map(|item| item.name).collect();
+        }
+        let line = match editor.readline("dev_restaurant> "){
+            Ok(line) => line,
+            // Ctrl+C only drops the line being typed
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(readline_error(e)),
+        };
+        if line.trim().is_empty(){
+            continue;
+        }
+        let _ = editor.add_history_entry(line.as_str());
+
+        let args = match split_line(&line){
+            Ok(args) => args,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            },
+        };
+        if matches!(args[0].as_str(), "exit" | "quit"){
+            break;
+        }
+        let cmd = match Command::from_iter_safe(std::iter::once("dev_restaurant".to_string()).chain(args)){
+            Ok(cmd) => cmd,
+            // Help is returned as an error as well
+            Err(e) => {
+                println!("{}", e.message);
+                continue;
+            },
+        };
+
+        // Whatever other tills sold since the last command is read again first
+        let _lock = storage.begin(data)?;
+        if let Err(e) = execute(cmd, storage, data, feed, locale){
+            println!("{}", tr!("terjadi kesalahan: {}", "an error occurred: {}", e));
+            storage.reload(data, false)?;
+        }
+    }
+    // Nothing left to save, every change was saved as it was made
+    Ok(())
+}
+
+fn readline_error(e: ReadlineError) -> StorageError{
+    match e{
+        ReadlineError::Io(e) => StorageError::Io(e),
+        e => StorageError::Io(std::io::Error::other(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use structopt::{clap::ErrorKind, StructOpt};
+    use crate::Command;
+    use super::{split_line, ShellHelper, COMMANDS};
+
+    #[test]
+    fn shell_line_test(){
+        assert_eq!(split_line("buy \"ikan asin\" 2").unwrap(), vec!["buy", "ikan asin", "2"]);
+        assert_eq!(split_line("  add 'es teh' 10  '' ").unwrap(), vec!["add", "es teh", "10", ""]);
+        assert!(split_line("buy \"ikan asin 2").is_err());
+
+        // Every completed command is one the shell accepts
+        for name in COMMANDS{
+            let e = Command::from_iter_safe(["dev_restaurant", name, "--help"]).unwrap_err();
+            assert_eq!(e.kind, ErrorKind::HelpDisplayed, "{}", name);
+        }
+
+        let helper = ShellHelper{ items: vec!["ikan asin".to_string(), "risoles".to_string()] };
+        let (start, pairs) = helper.candidates("buy ik", 6);
+        assert_eq!((start, pairs[0].replacement.as_str()), (4, "\"ikan asin\""));
+        let (start, pairs) = helper.candidates("bu", 2);
+        assert_eq!((start, pairs[0].replacement.as_str()), (0, "buy"));
+    }
+}
@@ -0,0 +1,264 @@
+use std::str::FromStr;
+
+use crate::{Item, ItemError, Items, Restaurant};
+use crate::i18n::tr;
+use crate::locale::Locale;
+use crate::margin::average_cost;
+use crate::money::Money;
+
+// Why the stock of an item changed, sales are kept in the ledger instead
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MovementKind{
+    // Units delivered and added to the stock
+    Restock,
+    // Stock set by hand with add, the quantity is the difference
+    Adjustment
+}
+
+impl MovementKind{
+    pub fn as_str(&self) -> &'static str{
+        match self{
+            MovementKind::Restock => "restock",
+            MovementKind::Adjustment => "adjustment",
+        }
+    }
+}
+
+impl FromStr for MovementKind{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        match s.trim().to_lowercase().as_str(){
+            "restock" => Ok(MovementKind::Restock),
+            "adjustment" => Ok(MovementKind::Adjustment),
+            _ => Err(tr!("jenis pergerakan stok \"{}\" tidak dikenal", "unknown stock movement \"{}\"", s)),
+        }
+    }
+}
+
+// One change of stock outside of sales and refunds, appended and never modified afterwards
+#[derive(Clone, Debug, PartialEq)]
+pub struct Movement{
+    pub id: i64,
+    // Local time in YYYY-MM-DD HH:MM:SS
+    pub timestamp: String,
+    pub item_id: i64,
+    pub item_name: String,
+    pub kind: MovementKind,
+    // Units added, negative when an adjustment lowered the stock
+    pub quantity: i64,
+    // Purchase price per unit, 0 when unknown
+    pub unit_cost: Money,
+    pub supplier: String,
+    pub note: String
+}
+
+impl Movement{
+    pub fn date(&self) -> &str{
+        self.timestamp.split(' ').next().unwrap_or(&self.timestamp)
+    }
+}
+
+// Append-only stock history, ordered by id
+pub struct Movements{
+    pub list: Vec<Movement>
+}
+
+impl Movements{
+    pub fn new() -> Self{
+        Self{
+            list: Vec::new()
+        }
+    }
+
+    // Used when reading the history back from storage
+    pub fn push(&mut self, movement: Movement){
+        self.list.push(movement);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(&mut self, item: &Item, kind: MovementKind, quantity: i64, unit_cost: Money, supplier: &str, note: &str, timestamp: &str) -> Movement{
+        let movement = Movement{
+            id: self.list.last().map_or(0, |movement| movement.id + 1),
+            timestamp: timestamp.to_string(),
+            item_id: item.id,
+            item_name: item.name.clone(),
+            kind,
+            quantity,
+            unit_cost,
+            supplier: supplier.trim().to_string(),
+            note: note.trim().to_string()
+        };
+        self.list.push(movement.clone());
+        movement
+    }
+
+    pub fn print_movements(&self, name: Option<&str>, locale: Locale){
+        let name = name.map(str::to_lowercase);
+        let movements: Vec<_> = self.list.iter()
+            .filter(|movement| name.as_ref().is_none_or(|name| movement.item_name.to_lowercase() == *name))
+            .collect();
+        if movements.is_empty(){
+            println!("{}", tr!("Belum ada riwayat stok", "No stock history yet"));
+            return;
+        }
+        println!("{}", tr!(
+            "ID |  Tanggal   |      Nama      |   Jenis    |  Jumlah  | Harga Pokok |    Pemasok     | Catatan",
+            "ID |    Date    |      Name      |    Kind    | Quantity |  Unit Cost  |    Supplier    | Note"
+        ));
+        for movement in movements{
+            println!(
+                "{:<3}| {:<11}| {:<15}| {:<11}| {:<9}| {:<12}| {:<15}| {}",
+                movement.id, movement.date(), movement.item_name, movement.kind.as_str(), locale.number(movement.quantity),
+                locale.money(movement.unit_cost), movement.supplier, movement.note
+            );
+        }
+    }
+}
+
+impl Restaurant{
+    // Adds `quantity` delivered units to the stock of an item and records the delivery.
+    // The unit cost is averaged into the cost of the item, the selling price is not touched
+    pub fn restock(&mut self, name: &str, quantity: i64, unit_cost: Money, supplier: &str, note: &str, timestamp: &str) -> Result<Item, ItemError>{
+        if quantity <= 0{
+            return Err(ItemError::InvalidQuantity(quantity));
+        }
+        if unit_cost.is_negative(){
+            return Err(ItemError::NegativeAmount(unit_cost));
+        }
+        let item = self.items.find_item(name)?;
+        // Checked before the cost is averaged, so a delivery that does not fit changes nothing
+        self.items.stock_after(item.id, quantity)?;
+        self.items.set_cost(&item.name, average_cost(item.quantity, item.cost, quantity, unit_cost)?)?;
+        let item = self.items.return_stock(item.id, quantity)?;
+        self.movements.record(&item, MovementKind::Restock, quantity, unit_cost, supplier, note, timestamp);
+        Ok(item)
+    }
+}
+
+impl Item{
+    // An item is low once its stock is at or below its reorder level, a level of 0 turns alerts off
+    pub fn is_low(&self) -> bool{
+        self.reorder_level > 0 && self.quantity <= self.reorder_level
+    }
+
+    // Units to order to get back to the target stock. Without a target the stock is brought to twice the reorder level
+    pub fn reorder_quantity(&self) -> i64{
+        let target = if self.reorder_target > self.reorder_level{
+            self.reorder_target
+        } else {
+            self.reorder_level * 2
+        };
+        (target - self.quantity).max(0)
+    }
+}
+
+impl Items{
+    // Changes only the values given, both must be zero or more
+    pub fn set_reorder(&mut self, name: &str, level: Option<i64>, target: Option<i64>) -> Result<Item, ItemError>{
+        if let Some(value) = [level, target].into_iter().flatten().find(|value| *value < 0){
+            return Err(ItemError::NegativeValue(value));
+        }
+        let id = self.find_item(name)?.id;
+        let item = self.list.get_mut(&id).ok_or_else(|| ItemError::ItemNotFound(name.to_string()))?;
+        if let Some(level) = level{
+            item.reorder_level = level;
+        }
+        if let Some(target) = target{
+            item.reorder_target = target;
+        }
+        Ok(item.clone())
+    }
+
+    // Items at or below their reorder level, emptiest first
+    pub fn low_stock(&self) -> Vec<Item>{
+        let mut items: Vec<_> = self.get_item_list().into_iter().filter(Item::is_low).collect();
+        items.sort_by_key(|item| (item.quantity - item.reorder_level, item.id));
+        items
+    }
+
+    pub fn print_alerts(&self, locale: Locale){
+        let items = self.low_stock();
+        if items.is_empty(){
+            println!("{}", tr!("Semua stok aman", "All stock is fine"));
+            return;
+        }
+        println!("{}", tr!(
+            "ID |      Nama      |  Stok   | Batas Pesan   | Target  | Pesan Ulang",
+            "ID |      Name      |  Stock  | Reorder Level | Target  | Reorder"
+        ));
+        for item in &items{
+            let target = if item.reorder_target > 0 { locale.number(item.reorder_target) } else { "-".to_string() };
+            println!(
+                "{:<3}| {:<15}| {:<8}| {:<14}| {:<8}| {}",
+                item.id, item.name, locale.number(item.quantity), locale.number(item.reorder_level), target, locale.number(item.reorder_quantity())
+            );
+        }
+    }
+}
+
+// Printed after a sale leaves an item at or below its reorder level
+pub fn print_low_stock_warning(item: &Item, locale: Locale){
+    if item.is_low(){
+        println!("{}", tr!(
+            "Peringatan: stok {} tinggal {} (batas {}), sarankan pesan ulang {}",
+            "Warning: only {1} of {0} left (level {2}), consider reordering {3}",
+            item.name, locale.number(item.quantity), locale.number(item.reorder_level), locale.number(item.reorder_quantity())
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ItemError, Items, Restaurant};
+    use crate::money::{rp, Money};
+    use super::MovementKind;
+
+    #[test]
+    fn low_stock_test(){
+        let mut items = Items::new();
+        items.add("Risoles", 5, rp(5000), 0, false);
+        items.add("Es Teh", 20, rp(3000), 0, false);
+        items.add("Nasi Goreng", 1, rp(25000), 0, false);
+
+        items.set_reorder("risoles", Some(5), Some(30)).unwrap();
+        items.set_reorder("es teh", Some(10), None).unwrap();
+        items.set_reorder("nasi goreng", Some(2), None).unwrap();
+        assert_eq!(items.set_reorder("es teh", Some(-1), None), Err(ItemError::NegativeValue(-1)));
+
+        let low: Vec<_> = items.low_stock().iter().map(|item| (item.name.clone(), item.reorder_quantity())).collect();
+        assert_eq!(low, vec![("nasi goreng".to_string(), 3), ("risoles".to_string(), 25)]);
+
+        // Selling es teh down to its level raises the alert, updating the level keeps the target
+        let es_teh = items.buy("es teh", 10).unwrap();
+        assert!(es_teh.is_low());
+        assert_eq!(es_teh.reorder_quantity(), 10);
+        let risoles = items.set_reorder("risoles", Some(3), None).unwrap();
+        assert_eq!((risoles.reorder_target, risoles.is_low()), (30, false));
+    }
+
+    #[test]
+    fn restock_test(){
+        let mut data = Restaurant::new();
+        data.items.add("Risoles", 15, rp(25000), 0, false);
+
+        // A delivery adds to the stock and keeps the selling price
+        let item = data.restock("risoles", 20, rp(18000), "Pak Budi", "pagi", "2022-11-22 08:00:00").unwrap();
+        assert_eq!((item.quantity, item.price, item.cost), (35, rp(25000), rp(18000)));
+        assert_eq!(data.restock("risoles", 0, Money::ZERO, "", "", "2022-11-22 08:00:00"), Err(ItemError::InvalidQuantity(0)));
+        assert_eq!(data.restock("risoles", 1, rp(-1), "", "", "2022-11-22 08:00:00"), Err(ItemError::NegativeAmount(rp(-1))));
+        assert!(data.restock("lemper", 1, Money::ZERO, "", "", "2022-11-22 08:00:00").is_err());
+        assert_eq!(data.restock("risoles", i64::MAX, rp(1), "", "", "2022-11-22 08:00:00"), Err(ItemError::StockTooLarge("risoles".to_string())));
+        assert_eq!(data.items.find_item("risoles").unwrap().cost, rp(18000));
+
+        // Add only changes what is given, a stock change is kept as an adjustment
+        let item = data.edit_item("risoles", None, Some(rp(26000)), "2022-11-22 09:00:00").unwrap();
+        assert_eq!((item.quantity, item.price), (35, rp(26000)));
+        data.edit_item("risoles", Some(30), None, "2022-11-22 09:30:00").unwrap();
+        assert!(data.edit_item("lemper", Some(3), None, "2022-11-22 09:30:00").is_err());
+
+        let history: Vec<_> = data.movements.list.iter().map(|movement| (movement.kind, movement.quantity, movement.unit_cost)).collect();
+        assert_eq!(history, vec![(MovementKind::Restock, 20, rp(18000)), (MovementKind::Adjustment, -5, Money::ZERO)]);
+        assert_eq!(data.movements.list[0].supplier, "Pak Budi");
+    }
+}
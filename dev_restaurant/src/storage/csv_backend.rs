@@ -0,0 +1,1075 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime
+};
+use csv::{ReaderBuilder, StringRecord, Writer, WriterBuilder};
+use thiserror::Error;
+
+use crate::{Items, Ledger, Opt, Order, Orders, Reports, Restaurant, Sale};
+use crate::i18n::tr;
+use crate::money::{Money, MoneyError};
+use crate::recipe::{Ingredient, Ingredients, RecipeLine, Recipes};
+use crate::purchase::{PurchaseLine, PurchaseOrder, PurchaseOrders, Supplier, SupplierItem, Suppliers};
+use crate::stock::{Movement, Movements};
+use super::{sibling, Lock, Recovery, Storage, StorageError};
+use super::journal::{Entry, FileWrite, Journal};
+
+const ITEM_HEADER: [&str; 8] = ["id", "name", "stock", "price", "category", "reorder_level", "reorder_target", "cost"];
+const REPORT_HEADER: [&str; 8] = ["id", "date", "quantity", "income", "service", "tax", "discount", "cost"];
+const ORDER_HEADER: [&str; 4] = ["id", "number", "timestamp", "paid"];
+const SALE_HEADER: [&str; 15] = [
+    "id", "timestamp", "item_id", "item_name", "quantity", "unit_price", "total", "refund_of", "reason", "order_id", "service", "tax",
+    "discount", "promotion", "cost"
+];
+const MOVEMENT_HEADER: [&str; 9] = ["id", "timestamp", "item_id", "item_name", "kind", "quantity", "unit_cost", "supplier", "note"];
+const SUPPLIER_HEADER: [&str; 4] = ["id", "name", "phone", "note"];
+const SUPPLIER_ITEM_HEADER: [&str; 4] = ["supplier_id", "item_id", "item_name", "unit_cost"];
+// One row per purchase order line, the order fields are repeated on each
+const PURCHASE_HEADER: [&str; 10] = [
+    "id", "supplier_id", "status", "created", "expected", "item_id", "item_name", "quantity", "received", "unit_cost"
+];
+const INGREDIENT_HEADER: [&str; 5] = ["id", "name", "unit", "stock", "cost"];
+const RECIPE_HEADER: [&str; 3] = ["item_id", "ingredient_id", "quantity"];
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+#[derive(Error, Debug)]
+enum ParseError{
+    #[error("{}", tr!("nilai harus berupa angka {}", "value must be a number {}", .0))]
+    InvalidValue(#[from] std::num::ParseIntError),
+
+    #[error("{0}")]
+    InvalidAmount(#[from] MoneyError),
+
+    #[error("{}", tr!("csv rusak {}", "malformed csv {}", .0))]
+    Csv(#[from] csv::Error),
+
+    #[error("{}", tr!("baris kosong", "empty item"))]
+    EmptyItem,
+
+    #[error("{}", tr!("kolom {} tidak ada", "missing field {}", .0))]
+    MissingField(String),
+
+    #[error("{0}")]
+    InvalidKind(String)
+}
+
+// Reader shared by items and reports: quoted fields, CRLF and rows of any length are accepted,
+// the header row is detected by hand so files written without one still load
+fn csv_reader(buffer: &[u8]) -> csv::Reader<&[u8]>{
+    let buffer = buffer.strip_prefix(UTF8_BOM).unwrap_or(buffer);
+    ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(buffer)
+}
+
+// Files written before a column was added carry a shorter header, so a prefix of it is accepted too
+fn is_header(record: &StringRecord, header: &[&str]) -> bool{
+    record.len() > 1 && record.len() <= header.len() && record.iter().zip(header).all(|(field, name)| field.trim().eq_ignore_ascii_case(name))
+}
+
+// Item
+fn parse_record(record: &StringRecord, n2: &str, n3: &str, n4: &str) -> Result<(i64, String, i64, Money), ParseError>{
+    // ID
+    let f1 = match record.get(0){
+        Some(id) => id.trim().parse::<i64>()?,
+        None => return Err(ParseError::EmptyItem),
+    };
+    
+    // Name(Item) / Date(Report)
+    let f2 = match record.get(1){
+        Some(name) => name.to_string(),
+        None => return Err(ParseError::MissingField(n2.to_string())),
+    };
+
+    // Quantity
+    let f3 = match record.get(2){
+        Some(quantity) => quantity.trim().parse::<i64>()?,
+        None => return Err(ParseError::MissingField(n3.to_string())),
+    };
+    
+    // Price(Item) / Income(Report)
+    let f4 = match record.get(3){
+        Some(price) => price.parse::<Money>()?,
+        None => return Err(ParseError::MissingField(n4.to_string())),
+    };
+
+    Ok((f1, f2, f3, f4))
+}
+
+fn field<'a>(record: &'a StringRecord, index: usize, name: &str) -> Result<&'a str, ParseError>{
+    record.get(index).ok_or_else(|| ParseError::MissingField(name.to_string()))
+}
+
+// Number or amount of money in a column added later, empty or absent means zero
+fn optional_amount<T>(record: &StringRecord, index: usize) -> Result<T, ParseError>
+where
+    T: FromStr + Default,
+    ParseError: From<T::Err>
+{
+    match record.get(index).map(str::trim){
+        Some(amount) if !amount.is_empty() => Ok(amount.parse::<T>()?),
+        _ => Ok(T::default()),
+    }
+}
+
+fn parse_sale_record(record: &StringRecord) -> Result<Sale, ParseError>{
+    let id = field(record, 0, "id")?.trim().parse::<i64>()?;
+    Ok(Sale{
+        id,
+        // Sales recorded before orders existed were each their own order
+        order_id: match record.get(9).map(str::trim){
+            Some(order_id) if !order_id.is_empty() => order_id.parse::<i64>()?,
+            _ => id,
+        },
+        timestamp: field(record, 1, "timestamp")?.trim().to_string(),
+        item_id: field(record, 2, "item_id")?.trim().parse::<i64>()?,
+        item_name: field(record, 3, "item_name")?.to_string(),
+        quantity: field(record, 4, "quantity")?.trim().parse::<i64>()?,
+        unit_price: field(record, 5, "unit_price")?.parse::<Money>()?,
+        total: field(record, 6, "total")?.parse::<Money>()?,
+        // Absent in ledgers written before refunds existed
+        refund_of: match record.get(7).map(str::trim){
+            Some(id) if !id.is_empty() => Some(id.parse::<i64>()?),
+            _ => None,
+        },
+        reason: record.get(8).unwrap_or("").to_string(),
+        service: optional_amount(record, 10)?,
+        tax: optional_amount(record, 11)?,
+        discount: optional_amount(record, 12)?,
+        promotion: record.get(13).unwrap_or("").to_string(),
+        cost: optional_amount(record, 14)?,
+    })
+}
+
+fn parse_order_record(record: &StringRecord) -> Result<Order, ParseError>{
+    Ok(Order{
+        id: field(record, 0, "id")?.trim().parse::<i64>()?,
+        number: field(record, 1, "number")?.trim().parse::<i64>()?,
+        timestamp: field(record, 2, "timestamp")?.trim().to_string(),
+        paid: field(record, 3, "paid")?.parse::<Money>()?,
+    })
+}
+
+fn parse_movement_record(record: &StringRecord) -> Result<Movement, ParseError>{
+    Ok(Movement{
+        id: field(record, 0, "id")?.trim().parse::<i64>()?,
+        timestamp: field(record, 1, "timestamp")?.trim().to_string(),
+        item_id: field(record, 2, "item_id")?.trim().parse::<i64>()?,
+        item_name: field(record, 3, "item_name")?.to_string(),
+        kind: field(record, 4, "kind")?.parse().map_err(ParseError::InvalidKind)?,
+        quantity: field(record, 5, "quantity")?.trim().parse::<i64>()?,
+        unit_cost: optional_amount(record, 6)?,
+        supplier: record.get(7).unwrap_or("").to_string(),
+        note: record.get(8).unwrap_or("").to_string(),
+    })
+}
+
+fn parse_supplier_record(record: &StringRecord) -> Result<Supplier, ParseError>{
+    Ok(Supplier{
+        id: field(record, 0, "id")?.trim().parse::<i64>()?,
+        name: field(record, 1, "name")?.trim().to_string(),
+        phone: record.get(2).unwrap_or("").to_string(),
+        note: record.get(3).unwrap_or("").to_string(),
+    })
+}
+
+fn parse_supplier_item_record(record: &StringRecord) -> Result<SupplierItem, ParseError>{
+    Ok(SupplierItem{
+        supplier_id: field(record, 0, "supplier_id")?.trim().parse::<i64>()?,
+        item_id: field(record, 1, "item_id")?.trim().parse::<i64>()?,
+        item_name: field(record, 2, "item_name")?.to_string(),
+        unit_cost: optional_amount(record, 3)?,
+    })
+}
+
+// The order a purchase line belongs to, with the line itself
+fn parse_purchase_record(record: &StringRecord) -> Result<(PurchaseOrder, PurchaseLine), ParseError>{
+    let order = PurchaseOrder{
+        id: field(record, 0, "id")?.trim().parse::<i64>()?,
+        supplier_id: field(record, 1, "supplier_id")?.trim().parse::<i64>()?,
+        status: field(record, 2, "status")?.parse().map_err(ParseError::InvalidKind)?,
+        created: field(record, 3, "created")?.trim().to_string(),
+        expected: record.get(4).unwrap_or("").trim().to_string(),
+        lines: Vec::new(),
+    };
+    let line = PurchaseLine{
+        item_id: field(record, 5, "item_id")?.trim().parse::<i64>()?,
+        item_name: field(record, 6, "item_name")?.to_string(),
+        quantity: field(record, 7, "quantity")?.trim().parse::<i64>()?,
+        received: optional_amount(record, 8)?,
+        unit_cost: optional_amount(record, 9)?,
+    };
+    Ok((order, line))
+}
+
+fn parse_ingredient_record(record: &StringRecord) -> Result<Ingredient, ParseError>{
+    Ok(Ingredient{
+        id: field(record, 0, "id")?.trim().parse::<i64>()?,
+        name: field(record, 1, "name")?.trim().to_lowercase(),
+        unit: record.get(2).unwrap_or("").trim().to_string(),
+        quantity: optional_amount(record, 3)?,
+        cost: optional_amount(record, 4)?,
+    })
+}
+
+fn parse_recipe_record(record: &StringRecord) -> Result<RecipeLine, ParseError>{
+    Ok(RecipeLine{
+        item_id: field(record, 0, "item_id")?.trim().parse::<i64>()?,
+        ingredient_id: field(record, 1, "ingredient_id")?.trim().parse::<i64>()?,
+        quantity: field(record, 2, "quantity")?.trim().parse::<i64>()?,
+    })
+}
+
+// Returns every data row with its line number, skipping blank lines and the header row
+fn parse_records(buffer: &[u8], header: &[&str]) -> Vec<(u64, Result<StringRecord, ParseError>)>{
+    let mut rows = Vec::new();
+    for (index, record) in csv_reader(buffer).records().enumerate(){
+        match record{
+            Ok(record) => {
+                let line_number = record.position().map(|pos| pos.line()).unwrap_or(index as u64 + 1);
+                if record.iter().all(|field| field.trim().is_empty()) || (index == 0 && is_header(&record, header)){
+                    continue;
+                }
+                rows.push((line_number, Ok(record)));
+            },
+            Err(e) => {
+                let line_number = e.position().map(|pos| pos.line()).unwrap_or(index as u64 + 1);
+                rows.push((line_number, Err(ParseError::from(e))));
+            },
+        }
+    }
+    rows
+}
+
+// name, quantity, price
+fn parse_items(buffer: &[u8], verbose: bool) -> Items{
+    let mut items = Items::new();
+    
+    // Read each record
+    for (line_number, record) in parse_records(buffer, &ITEM_HEADER){
+        // Parse each record
+        let parsed = record.and_then(|record| {
+            let extra = (optional_amount(&record, 5)?, optional_amount(&record, 6)?, optional_amount(&record, 7)?);
+            Ok((parse_record(&record, "name", "quantity", "price")?, record.get(4).unwrap_or("").trim().to_string(), extra))
+        });
+        match parsed{
+            // Add to items data
+            Ok(((item_id, item_name, item_quantity, item_price), category, (reorder_level, reorder_target, cost))) => {
+                if verbose {
+                    println!("Adding {} | {} | {} | {} to item list", item_id, item_name, item_quantity, item_price);
+                };
+                items.add(
+                    &item_name,
+                    item_quantity,
+                    item_price,
+                    item_id,
+                    true
+                );
+                if let Some(item) = items.list.get_mut(&item_id){
+                    item.category = category;
+                    item.reorder_level = reorder_level;
+                    item.reorder_target = reorder_target;
+                    item.cost = cost;
+                }
+                },
+            // Ignore line if error
+            Err(e) => 
+                if verbose{
+                    println!("Error parsing item line {}: {:?}", line_number, e)
+                }
+        }
+    }
+    items
+}
+
+// A file that was never written is an empty table, any other failure to read it is returned
+fn read_table(path: &Path) -> io::Result<Vec<u8>>{
+    match fs::read(path){
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        result => result,
+    }
+}
+
+fn load_items_csv(csv_file: &Path, verbose: bool) -> io::Result<Items>{
+    let buffer = read_table(csv_file)?;
+    Ok(parse_items(&buffer, verbose))
+}
+
+// Report 
+fn parse_reports(buffer: &[u8], verbose: bool) -> Reports{
+    let mut reports = Reports::new();
+    
+    for (line_number, record) in parse_records(buffer, &REPORT_HEADER){
+        let parsed = record.and_then(|record| {
+            let amounts = [optional_amount(&record, 4)?, optional_amount(&record, 5)?, optional_amount(&record, 6)?, optional_amount(&record, 7)?];
+            Ok((parse_record(&record, "date", "quantity", "income")?, amounts))
+        });
+        match parsed{
+            Ok(((id, date, quantity, income), [service, tax, discount, cost])) => {
+                if verbose {
+                    println!("Adding {} | {} | {} | {} to reports list", id, date, quantity, income);
+                };
+                if let Err(e) = reports.add_or_update(&date, quantity, income, id, true){
+                    if verbose {
+                        println!("Error parsing report line {}: {:?}", line_number, e)
+                    }
+                    continue;
+                }
+                if let Some(report) = reports.list.get_mut(&id){
+                    report.service = service;
+                    report.tax = tax;
+                    report.discount = discount;
+                    report.cost = cost;
+                }
+                },
+            Err(e) => 
+                if verbose {
+                    println!("Error parsing report line {}: {:?}", line_number, e)
+                }
+        }
+    }
+    reports
+}
+
+fn load_reports_csv(csv_file: &Path, verbose: bool) -> io::Result<Reports>{
+    let buffer = read_table(csv_file)?;
+    Ok(parse_reports(&buffer, verbose))
+}
+
+// Sales
+fn parse_sales(buffer: &[u8], verbose: bool) -> Ledger{
+    let mut ledger = Ledger::new();
+
+    for (line_number, record) in parse_records(buffer, &SALE_HEADER){
+        match record.and_then(|record| parse_sale_record(&record)){
+            Ok(sale) => {
+                if verbose {
+                    println!("Adding sale {} | {} | {} x {} to ledger", sale.id, sale.timestamp, sale.item_name, sale.quantity);
+                };
+                ledger.push(sale);
+            },
+            Err(e) =>
+                if verbose {
+                    println!("Error parsing sale line {}: {:?}", line_number, e)
+                }
+        }
+    }
+    ledger
+}
+
+// Orders
+fn parse_orders(buffer: &[u8], verbose: bool) -> Orders{
+    let mut orders = Orders::new();
+
+    for (line_number, record) in parse_records(buffer, &ORDER_HEADER){
+        match record.and_then(|record| parse_order_record(&record)){
+            Ok(order) => {
+                if verbose {
+                    println!("Adding order {} | #{} | {} to orders", order.id, order.number, order.timestamp);
+                };
+                orders.push(order);
+            },
+            Err(e) =>
+                if verbose {
+                    println!("Error parsing order line {}: {:?}", line_number, e)
+                }
+        }
+    }
+    orders
+}
+
+// Stock movements
+fn parse_movements(buffer: &[u8], verbose: bool) -> Movements{
+    let mut movements = Movements::new();
+
+    for (line_number, record) in parse_records(buffer, &MOVEMENT_HEADER){
+        match record.and_then(|record| parse_movement_record(&record)){
+            Ok(movement) => {
+                if verbose {
+                    println!("Adding movement {} | {} | {} | {} to stock history", movement.id, movement.timestamp, movement.item_name, movement.quantity);
+                };
+                movements.push(movement);
+            },
+            Err(e) =>
+                if verbose {
+                    println!("Error parsing movement line {}: {:?}", line_number, e)
+                }
+        }
+    }
+    movements
+}
+
+// Suppliers, the items they deliver and purchase orders
+fn parse_suppliers(suppliers_buffer: &[u8], items_buffer: &[u8], verbose: bool) -> Suppliers{
+    let mut suppliers = Suppliers::new();
+
+    for (line_number, record) in parse_records(suppliers_buffer, &SUPPLIER_HEADER){
+        match record.and_then(|record| parse_supplier_record(&record)){
+            Ok(supplier) => {
+                if verbose {
+                    println!("Adding supplier {} | {} to suppliers", supplier.id, supplier.name);
+                };
+                suppliers.list.push(supplier);
+            },
+            Err(e) =>
+                if verbose {
+                    println!("Error parsing supplier line {}: {:?}", line_number, e)
+                }
+        }
+    }
+    for (line_number, record) in parse_records(items_buffer, &SUPPLIER_ITEM_HEADER){
+        match record.and_then(|record| parse_supplier_item_record(&record)){
+            Ok(entry) => suppliers.catalog.push(entry),
+            Err(e) =>
+                if verbose {
+                    println!("Error parsing supplier item line {}: {:?}", line_number, e)
+                }
+        }
+    }
+    suppliers
+}
+
+fn parse_purchases(buffer: &[u8], verbose: bool) -> PurchaseOrders{
+    let mut purchases = PurchaseOrders::new();
+
+    for (line_number, record) in parse_records(buffer, &PURCHASE_HEADER){
+        match record.and_then(|record| parse_purchase_record(&record)){
+            Ok((order, line)) => {
+                if verbose {
+                    println!("Adding purchase order {} | {} | {} to purchase orders", order.id, line.item_name, line.quantity);
+                };
+                match purchases.list.iter_mut().find(|existing| existing.id == order.id){
+                    Some(existing) => existing.lines.push(line),
+                    None => purchases.list.push(PurchaseOrder{ lines: vec![line], ..order }),
+                }
+            },
+            Err(e) =>
+                if verbose {
+                    println!("Error parsing purchase order line {}: {:?}", line_number, e)
+                }
+        }
+    }
+    purchases
+}
+
+// Ingredients and recipes
+fn parse_ingredients(buffer: &[u8], verbose: bool) -> Ingredients{
+    let mut ingredients = Ingredients::new();
+
+    for (line_number, record) in parse_records(buffer, &INGREDIENT_HEADER){
+        match record.and_then(|record| parse_ingredient_record(&record)){
+            Ok(ingredient) => {
+                if verbose {
+                    println!("Adding ingredient {} | {} | {} to ingredients", ingredient.id, ingredient.name, ingredient.quantity);
+                };
+                ingredients.list.push(ingredient);
+            },
+            Err(e) =>
+                if verbose {
+                    println!("Error parsing ingredient line {}: {:?}", line_number, e)
+                }
+        }
+    }
+    ingredients
+}
+
+fn parse_recipes(buffer: &[u8], verbose: bool) -> Recipes{
+    let mut recipes = Recipes::new();
+
+    for (line_number, record) in parse_records(buffer, &RECIPE_HEADER){
+        match record.and_then(|record| parse_recipe_record(&record)){
+            Ok(line) => recipes.list.push(line),
+            Err(e) =>
+                if verbose {
+                    println!("Error parsing recipe line {}: {:?}", line_number, e)
+                }
+        }
+    }
+    recipes
+}
+
+fn write_items<W: Write>(writer: W, items: &Items) -> csv::Result<()>{
+    let mut writer = Writer::from_writer(writer);
+    writer.write_record(ITEM_HEADER)?;
+
+    for item in items.get_item_list().into_iter(){
+        writer.write_record(&[
+            item.id.to_string(),
+            item.name,
+            item.quantity.to_string(),
+            item.price.to_string(),
+            item.category,
+            item.reorder_level.to_string(),
+            item.reorder_target.to_string(),
+            item.cost.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_reports<W: Write>(writer: W, reports: &Reports) -> csv::Result<()>{
+    let mut writer = Writer::from_writer(writer);
+    writer.write_record(REPORT_HEADER)?;
+
+    for report in reports.get_report_list().into_iter(){
+        writer.write_record(&[
+            report.id.to_string(),
+            report.date,
+            report.quantity.to_string(),
+            report.income.to_string(),
+            report.service.to_string(),
+            report.tax.to_string(),
+            report.discount.to_string(),
+            report.cost.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Writes the given sales without a header, callers add it when starting a new file
+fn write_sales<W: Write>(writer: W, sales: &[Sale], header: bool) -> csv::Result<()>{
+    let mut writer = WriterBuilder::new().has_headers(false).from_writer(writer);
+    if header{
+        writer.write_record(SALE_HEADER)?;
+    }
+
+    for sale in sales{
+        writer.write_record(&[
+            sale.id.to_string(),
+            sale.timestamp.clone(),
+            sale.item_id.to_string(),
+            sale.item_name.clone(),
+            sale.quantity.to_string(),
+            sale.unit_price.to_string(),
+            sale.total.to_string(),
+            sale.refund_of.map(|id| id.to_string()).unwrap_or_default(),
+            sale.reason.clone(),
+            sale.order_id.to_string(),
+            sale.service.to_string(),
+            sale.tax.to_string(),
+            sale.discount.to_string(),
+            sale.promotion.clone(),
+            sale.cost.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_orders<W: Write>(writer: W, orders: &[Order], header: bool) -> csv::Result<()>{
+    let mut writer = WriterBuilder::new().has_headers(false).from_writer(writer);
+    if header{
+        writer.write_record(ORDER_HEADER)?;
+    }
+
+    for order in orders{
+        writer.write_record(&[order.id.to_string(), order.number.to_string(), order.timestamp.clone(), order.paid.to_string()])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_movements<W: Write>(writer: W, movements: &[Movement], header: bool) -> csv::Result<()>{
+    let mut writer = WriterBuilder::new().has_headers(false).from_writer(writer);
+    if header{
+        writer.write_record(MOVEMENT_HEADER)?;
+    }
+
+    for movement in movements{
+        writer.write_record(&[
+            movement.id.to_string(),
+            movement.timestamp.clone(),
+            movement.item_id.to_string(),
+            movement.item_name.clone(),
+            movement.kind.as_str().to_string(),
+            movement.quantity.to_string(),
+            movement.unit_cost.to_string(),
+            movement.supplier.clone(),
+            movement.note.clone(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_suppliers<W: Write>(writer: W, suppliers: &Suppliers) -> csv::Result<()>{
+    let mut writer = Writer::from_writer(writer);
+    writer.write_record(SUPPLIER_HEADER)?;
+
+    for supplier in &suppliers.list{
+        writer.write_record(&[supplier.id.to_string(), supplier.name.clone(), supplier.phone.clone(), supplier.note.clone()])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_supplier_items<W: Write>(writer: W, suppliers: &Suppliers) -> csv::Result<()>{
+    let mut writer = Writer::from_writer(writer);
+    writer.write_record(SUPPLIER_ITEM_HEADER)?;
+
+    for entry in &suppliers.catalog{
+        writer.write_record(&[entry.supplier_id.to_string(), entry.item_id.to_string(), entry.item_name.clone(), entry.unit_cost.to_string()])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_purchases<W: Write>(writer: W, purchases: &PurchaseOrders) -> csv::Result<()>{
+    let mut writer = Writer::from_writer(writer);
+    writer.write_record(PURCHASE_HEADER)?;
+
+    for order in &purchases.list{
+        for line in &order.lines{
+            writer.write_record(&[
+                order.id.to_string(),
+                order.supplier_id.to_string(),
+                order.status.as_str().to_string(),
+                order.created.clone(),
+                order.expected.clone(),
+                line.item_id.to_string(),
+                line.item_name.clone(),
+                line.quantity.to_string(),
+                line.received.to_string(),
+                line.unit_cost.to_string(),
+            ])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_ingredients<W: Write>(writer: W, ingredients: &Ingredients) -> csv::Result<()>{
+    let mut writer = Writer::from_writer(writer);
+    writer.write_record(INGREDIENT_HEADER)?;
+
+    for ingredient in &ingredients.list{
+        writer.write_record(&[
+            ingredient.id.to_string(), ingredient.name.clone(), ingredient.unit.clone(), ingredient.quantity.to_string(), ingredient.cost.to_string()
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_recipes<W: Write>(writer: W, recipes: &Recipes) -> csv::Result<()>{
+    let mut writer = Writer::from_writer(writer);
+    writer.write_record(RECIPE_HEADER)?;
+
+    for line in &recipes.list{
+        writer.write_record(&[line.item_id.to_string(), line.ingredient_id.to_string(), line.quantity.to_string()])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Rewrites a whole file through a temporary one that is synced and then renamed over it,
+// so a crash leaves either the old or the new file and never a half written one
+pub(super) fn save_file<F>(file_name: &Path, write: F) -> std::io::Result<()>
+where F: FnOnce(&mut fs::File) -> csv::Result<()>{
+    let temp_name = sibling(file_name, ".tmp");
+
+    let written = fs::File::create(&temp_name).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+    if let Err(e) = written{
+        let _ = fs::remove_file(&temp_name);
+        return Err(e);
+    }
+    fs::rename(&temp_name, file_name)?;
+    sync_dir(file_name)
+}
+
+// A rename only survives a power cut once the directory holding it is synced
+#[cfg(unix)]
+pub(super) fn sync_dir(file_name: &Path) -> std::io::Result<()>{
+    let dir = file_name.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+pub(super) fn sync_dir(_file_name: &Path) -> std::io::Result<()>{
+    Ok(())
+}
+
+// New lines for a file that is only appended to, with a header when the file is still empty or missing
+fn appended<F>(file_name: &Path, write: F) -> std::io::Result<FileWrite>
+where F: FnOnce(&mut Vec<u8>, bool) -> csv::Result<()>{
+    let append_at = fs::metadata(file_name).map_or(0, |metadata| metadata.len());
+    let mut content = Vec::new();
+    write(&mut content, append_at == 0)?;
+    Ok(FileWrite{ path: file_name.to_path_buf(), append_at: Some(append_at), content: String::from_utf8(content).map_err(std::io::Error::other)? })
+}
+
+// The whole new content of a file that is rewritten on every save
+fn rewritten<F>(file_name: &Path, write: F) -> std::io::Result<FileWrite>
+where F: FnOnce(&mut Vec<u8>) -> csv::Result<()>{
+    let mut content = Vec::new();
+    write(&mut content)?;
+    Ok(FileWrite{ path: file_name.to_path_buf(), append_at: None, content: String::from_utf8(content).map_err(std::io::Error::other)? })
+}
+
+// The original storage: one CSV file each for items, reports, the sales ledger, orders and stock movements
+pub struct CsvStorage{
+    items_csv: PathBuf,
+    reports_csv: PathBuf,
+    sales_csv: PathBuf,
+    orders_csv: PathBuf,
+    movements_csv: PathBuf,
+    suppliers_csv: PathBuf,
+    supplier_items_csv: PathBuf,
+    purchases_csv: PathBuf,
+    ingredients_csv: PathBuf,
+    recipes_csv: PathBuf,
+    // Ledger sales, orders and movements already in their files
+    saved_sales: usize,
+    saved_orders: usize,
+    saved_movements: usize,
+    // Modification time and size of every file when they were last checked or saved, None before the first check
+    seen: Option<Vec<Option<(SystemTime, u64)>>>
+}
+
+impl CsvStorage{
+    pub fn new(opt: &Opt) -> Self{
+        Self{
+            items_csv: opt.items_csv.clone(),
+            reports_csv: opt.reports_csv.clone(),
+            sales_csv: opt.sales_csv.clone(),
+            orders_csv: opt.orders_csv.clone(),
+            movements_csv: opt.movements_csv.clone(),
+            suppliers_csv: opt.suppliers_csv.clone(),
+            supplier_items_csv: opt.supplier_items_csv.clone(),
+            purchases_csv: opt.purchases_csv.clone(),
+            ingredients_csv: opt.ingredients_csv.clone(),
+            recipes_csv: opt.recipes_csv.clone(),
+            saved_sales: 0,
+            saved_orders: 0,
+            saved_movements: 0,
+            seen: None
+        }
+    }
+
+    // A missing file is None, so creating it counts as a change too
+    fn stamp(&self) -> io::Result<Vec<Option<(SystemTime, u64)>>>{
+        let files = [
+            &self.items_csv, &self.reports_csv, &self.sales_csv, &self.orders_csv, &self.movements_csv, &self.suppliers_csv,
+            &self.supplier_items_csv, &self.purchases_csv, &self.ingredients_csv, &self.recipes_csv
+        ];
+        files.into_iter()
+            .map(|file| match fs::metadata(file){
+                Ok(metadata) => Ok(Some((metadata.modified()?, metadata.len()))),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            })
+            .collect()
+    }
+}
+
+impl Storage for CsvStorage{
+    // One lock for all the files, next to the items file
+    fn lock(&self) -> Result<Lock, StorageError>{
+        Lock::acquire(&sibling(&self.items_csv, ".lock"))
+    }
+
+    // A missing file is treated as an empty list, one that cannot be read fails the load
+    // rather than being overwritten with nothing on the next save
+    fn load_items(&mut self, verbose: bool) -> Result<Items, StorageError>{
+        Ok(load_items_csv(&self.items_csv, verbose)?)
+    }
+
+    fn load_reports(&mut self, verbose: bool) -> Result<Reports, StorageError>{
+        Ok(load_reports_csv(&self.reports_csv, verbose)?)
+    }
+
+    fn load_ledger(&mut self, verbose: bool) -> Result<Ledger, StorageError>{
+        let ledger = parse_sales(&read_table(&self.sales_csv)?, verbose);
+        self.saved_sales = ledger.list.len();
+        Ok(ledger)
+    }
+
+    fn load_orders(&mut self, verbose: bool) -> Result<Orders, StorageError>{
+        let orders = parse_orders(&read_table(&self.orders_csv)?, verbose);
+        self.saved_orders = orders.list.len();
+        Ok(orders)
+    }
+
+    fn load_movements(&mut self, verbose: bool) -> Result<Movements, StorageError>{
+        let movements = parse_movements(&read_table(&self.movements_csv)?, verbose);
+        self.saved_movements = movements.list.len();
+        Ok(movements)
+    }
+
+    fn load_suppliers(&mut self, verbose: bool) -> Result<Suppliers, StorageError>{
+        let suppliers = read_table(&self.suppliers_csv)?;
+        let supplier_items = read_table(&self.supplier_items_csv)?;
+        Ok(parse_suppliers(&suppliers, &supplier_items, verbose))
+    }
+
+    fn load_purchases(&mut self, verbose: bool) -> Result<PurchaseOrders, StorageError>{
+        Ok(parse_purchases(&read_table(&self.purchases_csv)?, verbose))
+    }
+
+    fn load_ingredients(&mut self, verbose: bool) -> Result<Ingredients, StorageError>{
+        Ok(parse_ingredients(&read_table(&self.ingredients_csv)?, verbose))
+    }
+
+    fn load_recipes(&mut self, verbose: bool) -> Result<Recipes, StorageError>{
+        Ok(parse_recipes(&read_table(&self.recipes_csv)?, verbose))
+    }
+
+    // Another process writing a file changes its modification time, and almost always its size
+    fn changed(&mut self) -> Result<bool, StorageError>{
+        let stamp = Some(self.stamp()?);
+        let changed = self.seen != stamp;
+        self.seen = stamp;
+        Ok(changed)
+    }
+
+    // Finishes the save a complete journal describes, a journal cut off while it was written is dropped
+    fn recover(&mut self) -> Result<Recovery, StorageError>{
+        let journal = Journal::new(&self.items_csv);
+        let recovery = match journal.read()?{
+            None => return Ok(Recovery::Clean),
+            Some(Entry::Incomplete) => Recovery::RolledBack,
+            Some(Entry::Complete(writes)) => {
+                for write in &writes{
+                    write.apply()?;
+                }
+                Recovery::Replayed(writes.into_iter().map(|write| write.path).collect())
+            },
+        };
+        journal.clear()?;
+        Ok(recovery)
+    }
+
+    // Every change is written to the journal before any file is touched, so a crash between files,
+    // like stock taken off with no income recorded, is finished by recover. The ledger goes first since reports can be rebuilt from it
+    fn save(&mut self, data: &Restaurant) -> Result<(), StorageError>{
+        let (sales, orders, movements) = (
+            &data.ledger.list[self.saved_sales..], &data.orders.list[self.saved_orders..], &data.movements.list[self.saved_movements..]
+        );
+        let mut writes = Vec::new();
+        if !sales.is_empty(){
+            writes.push(appended(&self.sales_csv, |buffer, header| write_sales(buffer, sales, header))?);
+        }
+        if !orders.is_empty(){
+            writes.push(appended(&self.orders_csv, |buffer, header| write_orders(buffer, orders, header))?);
+        }
+        if !movements.is_empty(){
+            writes.push(appended(&self.movements_csv, |buffer, header| write_movements(buffer, movements, header))?);
+        }
+
+        writes.push(rewritten(&self.items_csv, |buffer| write_items(buffer, &data.items))?);
+        writes.push(rewritten(&self.reports_csv, |buffer| write_reports(buffer, &data.reports))?);
+
+        // Only written once used, so the files do not show up for restaurants without suppliers or ingredients
+        if !data.suppliers.list.is_empty() || self.suppliers_csv.exists(){
+            writes.push(rewritten(&self.suppliers_csv, |buffer| write_suppliers(buffer, &data.suppliers))?);
+            writes.push(rewritten(&self.supplier_items_csv, |buffer| write_supplier_items(buffer, &data.suppliers))?);
+        }
+        if !data.purchases.list.is_empty() || self.purchases_csv.exists(){
+            writes.push(rewritten(&self.purchases_csv, |buffer| write_purchases(buffer, &data.purchases))?);
+        }
+        if !data.ingredients.list.is_empty() || self.ingredients_csv.exists(){
+            writes.push(rewritten(&self.ingredients_csv, |buffer| write_ingredients(buffer, &data.ingredients))?);
+            writes.push(rewritten(&self.recipes_csv, |buffer| write_recipes(buffer, &data.recipes))?);
+        }
+
+        let journal = Journal::new(&self.items_csv);
+        journal.write(&writes)?;
+        for write in &writes{
+            write.apply()?;
+        }
+        journal.clear()?;
+
+        self.saved_sales = data.ledger.list.len();
+        self.saved_orders = data.orders.list.len();
+        self.saved_movements = data.movements.list.len();
+        self.seen = Some(self.stamp()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Ledger, Order};
+    use crate::money::{rp, Money};
+    use crate::promo::LineDiscount;
+    use crate::tax::{Charges, TaxRules};
+    use crate::{OrderLine, Restaurant};
+    use crate::stock::{Movement, MovementKind};
+    use super::{
+        parse_ingredients, parse_items, parse_movements, parse_orders, parse_purchases, parse_recipes, parse_reports, parse_sales,
+        parse_suppliers, read_table, save_file, write_ingredients, write_items, write_movements, write_orders, write_purchases, write_recipes,
+        write_sales, write_supplier_items, write_suppliers
+    };
+    use crate::storage::{sibling, Lock};
+
+    #[test]
+    fn quoted_fields_round_trip_test(){
+        let mut items = crate::Items::new();
+        items.add("Nasi Goreng, Pedas", 10, rp(25000), 0, false);
+        items.add("Es \"Teler\"", 5, Money::from_sen(1500050), 0, false);
+        items.add("Kue\nLapis", 3, rp(2000), 0, false);
+
+        let mut buffer = Vec::new();
+        write_items(&mut buffer, &items).unwrap();
+        let loaded = parse_items(&buffer, false);
+
+        assert_eq!(loaded.list.len(), 3);
+        assert_eq!(loaded.find_item("nasi goreng, pedas").unwrap().quantity, 10);
+        assert_eq!(loaded.find_item("es \"teler\"").unwrap().price, Money::from_sen(1500050));
+        assert_eq!(loaded.find_item("kue\nlapis").unwrap().quantity, 3);
+    }
+
+    #[test]
+    fn bom_crlf_and_header_test(){
+        let buffer = b"\xEF\xBB\xBFid,name,stock,price\r\n0,risoles,164,25000\r\n\r\n1,\"ikan, asin\",512,2518.5\r\n2,telur,10,2.500\r\n";
+        let items = parse_items(buffer, false);
+        assert_eq!(items.list.len(), 2);
+        assert_eq!(items.list.get(&1).unwrap().name, "ikan, asin");
+        assert_eq!(items.list.get(&1).unwrap().price, Money::from_sen(251850));
+
+        let buffer = b"id,date,quantity,income\n0,2022-11-22,2,25000\n";
+        let reports = parse_reports(buffer, false);
+        assert_eq!(reports.list.len(), 1);
+        assert_eq!(reports.list.get(&0).unwrap().income, rp(25000));
+    }
+
+    #[test]
+    fn sales_round_trip_test(){
+        let mut items = crate::Items::new();
+        items.add("Nasi Goreng, Pedas", 10, rp(25000), 0, false);
+        let item = items.find_item("nasi goreng, pedas").unwrap();
+
+        let mut ledger = Ledger::new();
+        ledger.record(&item, 2, &LineDiscount::default(), Charges::untaxed(rp(50000)), rp(30000), 0, "2022-11-22 10:00:00");
+        let rules = TaxRules::parse(b"category,service,tax\n*,5,11\n").unwrap();
+        let discount = LineDiscount{ amount: rp(5000), promotion: "Diskon, 20%".to_string() };
+        let sale = ledger.record(&item, 1, &discount, rules.charges("", rp(20000)).unwrap(), Money::from_sen(1500050), 1, "2022-11-22 10:05:00");
+        ledger.refund(&sale, 1, "salah input, pelanggan batal", "2022-11-22 10:06:00").unwrap();
+
+        let mut buffer = Vec::new();
+        write_sales(&mut buffer, &ledger.list[..1], true).unwrap();
+        write_sales(&mut buffer, &ledger.list[1..], false).unwrap();
+
+        assert_eq!(parse_sales(&buffer, false).list, ledger.list);
+    }
+
+    #[test]
+    fn legacy_sales_test(){
+        let buffer = b"id,timestamp,item_id,item_name,quantity,unit_price,total\n0,2022-11-22 10:00:00,0,risoles,2,25000,50000\n";
+        let ledger = parse_sales(buffer, false);
+        assert_eq!(ledger.list.len(), 1);
+        assert_eq!(ledger.list[0].refund_of, None);
+        assert_eq!(ledger.list[0].reason, "");
+        assert_eq!(ledger.list[0].order_id, 0);
+    }
+
+    #[test]
+    fn orders_round_trip_test(){
+        let orders = vec![
+            Order{ id: 0, number: 1, timestamp: "2022-11-22 10:00:00".to_string(), paid: rp(50000) },
+            Order{ id: 1, number: 2, timestamp: "2022-11-22 10:05:00".to_string(), paid: Money::from_sen(10000050) },
+        ];
+
+        let mut buffer = Vec::new();
+        write_orders(&mut buffer, &orders, true).unwrap();
+        assert_eq!(parse_orders(&buffer, false).list, orders);
+    }
+
+    #[test]
+    fn movements_round_trip_test(){
+        let movements = vec![
+            Movement{
+                id: 0, timestamp: "2022-11-22 08:00:00".to_string(), item_id: 3, item_name: "risoles".to_string(),
+                kind: MovementKind::Restock, quantity: 20, unit_cost: rp(18000), supplier: "Pak Budi, Pasar".to_string(), note: String::new()
+            },
+            Movement{
+                id: 1, timestamp: "2022-11-22 09:00:00".to_string(), item_id: 3, item_name: "risoles".to_string(),
+                kind: MovementKind::Adjustment, quantity: -2, unit_cost: Money::ZERO, supplier: String::new(), note: "jatuh".to_string()
+            },
+        ];
+
+        let mut buffer = Vec::new();
+        write_movements(&mut buffer, &movements, true).unwrap();
+        assert_eq!(parse_movements(&buffer, false).list, movements);
+    }
+
+    #[test]
+    fn purchases_round_trip_test(){
+        let mut data = Restaurant::new();
+        data.items.add("Risoles", 5, rp(25000), 0, false);
+        data.items.add("Es Teh", 10, rp(5000), 0, false);
+        data.suppliers.add("Toko \"Maju\", Pasar", "0812", "").unwrap();
+        data.suppliers.set_cost(0, &data.items.find_item("risoles").unwrap(), rp(15000)).unwrap();
+        let lines = vec![OrderLine{ name: "risoles".to_string(), quantity: 20 }, OrderLine{ name: "es teh".to_string(), quantity: 5 }];
+        data.create_purchase("toko \"maju\", pasar", &lines, "2022-11-25", "2022-11-22 08:00:00").unwrap();
+        data.send_purchase(0).unwrap();
+        data.receive_purchase(0, &lines[..1], "2022-11-23 08:00:00").unwrap();
+
+        let (mut suppliers, mut supplier_items, mut purchases) = (Vec::new(), Vec::new(), Vec::new());
+        write_suppliers(&mut suppliers, &data.suppliers).unwrap();
+        write_supplier_items(&mut supplier_items, &data.suppliers).unwrap();
+        write_purchases(&mut purchases, &data.purchases).unwrap();
+
+        let loaded = parse_suppliers(&suppliers, &supplier_items, false);
+        assert_eq!(loaded.list, data.suppliers.list);
+        assert_eq!(loaded.catalog, data.suppliers.catalog);
+        assert_eq!(parse_purchases(&purchases, false).list, data.purchases.list);
+    }
+
+    #[test]
+    fn recipes_round_trip_test(){
+        let mut data = Restaurant::new();
+        data.items.add("Nasi Goreng", 0, rp(25000), 0, false);
+        data.ingredients.set("Nasi", Some(1000), Some("gram"), Some(Money::from_sen(1550))).unwrap();
+        data.ingredients.set("Telur, ayam", Some(12), Some("butir"), None).unwrap();
+        let item = data.items.find_item("nasi goreng").unwrap();
+        data.set_recipe(&item, &[("nasi".to_string(), 200), ("telur, ayam".to_string(), 1)]).unwrap();
+
+        let (mut ingredients, mut recipes) = (Vec::new(), Vec::new());
+        write_ingredients(&mut ingredients, &data.ingredients).unwrap();
+        write_recipes(&mut recipes, &data.recipes).unwrap();
+        assert_eq!(parse_ingredients(&ingredients, false).list, data.ingredients.list);
+        assert_eq!(parse_recipes(&recipes, false).list, data.recipes.list);
+    }
+
+    #[test]
+    fn save_and_lock_test(){
+        let path = std::env::temp_dir().join(format!("dev_restaurant_save_{}.csv", std::process::id()));
+        std::fs::write(&path, "id,name\n0,risoles\n").unwrap();
+
+        // A write that fails halfway leaves the old file as it was
+        let failed = save_file(&path, |file| {
+            std::io::Write::write_all(file, b"id,na")?;
+            Err(csv::Error::from(std::io::Error::other("disk full")))
+        });
+        assert!(failed.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "id,name\n0,risoles\n");
+        assert!(!path.with_extension("csv.tmp").exists());
+
+        save_file(&path, |file| Ok(std::io::Write::write_all(file, b"id,name\n")?)).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "id,name\n");
+
+        // Another process, or another open of the lock file, has to wait while the lock is held
+        let lock = Lock::acquire(&sibling(&path, ".lock")).unwrap();
+        let other = std::fs::File::open(sibling(&path, ".lock")).unwrap();
+        assert!(other.try_lock().is_err());
+        drop(lock);
+        assert!(other.try_lock().is_ok());
+
+        // Only a file that is not there yet reads as an empty table
+        assert_eq!(read_table(&path.with_extension("missing")).unwrap(), b"");
+        assert!(read_table(&std::env::temp_dir()).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(sibling(&path, ".lock")).unwrap();
+    }
+}
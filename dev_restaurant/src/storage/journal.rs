@@ -0,0 +1,134 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf}
+};
+use serde::{Deserialize, Serialize};
+
+use super::csv_backend::{save_file, sync_dir};
+use super::sibling;
+
+// Marks a journal as written in full, the line is only added once everything before it is on disk
+const COMMIT: &str = "commit";
+
+// One change a save makes to a file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileWrite{
+    pub path: PathBuf,
+    // Length of the file before new lines are appended to it, None replaces the whole file
+    pub append_at: Option<u64>,
+    pub content: String
+}
+
+impl FileWrite{
+    // Safe to run again: a replaced file is written whole, an appended one is cut back first
+    pub fn apply(&self) -> io::Result<()>{
+        match self.append_at{
+            None => save_file(&self.path, |file| Ok(file.write_all(self.content.as_bytes())?)),
+            Some(append_at) => {
+                let mut file = OpenOptions::new().create(true).truncate(false).write(true).open(&self.path)?;
+                // Drops whatever an interrupted save got to append, never grows a file that was cut shorter
+                let length = file.metadata()?.len();
+                file.set_len(append_at.min(length))?;
+                file.seek(SeekFrom::End(0))?;
+                file.write_all(self.content.as_bytes())?;
+                file.sync_all()
+            },
+        }
+    }
+}
+
+// What the journal held when it was read
+#[derive(Debug, PartialEq)]
+pub enum Entry{
+    // Written in full, its changes may be applied partly
+    Complete(Vec<FileWrite>),
+    // Cut off while the journal itself was written, no data file was touched yet
+    Incomplete
+}
+
+// Every change of a save is written here before any data file is touched,
+// and the journal is removed once all of them are applied
+pub struct Journal{
+    path: PathBuf
+}
+
+impl Journal{
+    // Kept next to the items file, food.csv.journal
+    pub fn new(data: &Path) -> Self{
+        Self{ path: sibling(data, ".journal") }
+    }
+
+    pub fn write(&self, writes: &[FileWrite]) -> io::Result<()>{
+        let mut file = File::create(&self.path)?;
+        serde_json::to_writer(&mut file, writes)?;
+        file.write_all(b"\n")?;
+        file.sync_all()?;
+        file.write_all(format!("{}\n", COMMIT).as_bytes())?;
+        file.sync_all()?;
+        sync_dir(&self.path)
+    }
+
+    // None when the last save finished
+    pub fn read(&self) -> io::Result<Option<Entry>>{
+        let text = match fs::read_to_string(&self.path){
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            // Not valid text means it was cut off halfway
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => return Ok(Some(Entry::Incomplete)),
+            Err(e) => return Err(e),
+        };
+        let mut lines = text.lines();
+        let entry = match (lines.next(), lines.next()){
+            (Some(writes), Some(COMMIT)) => serde_json::from_str(writes).map_or(Entry::Incomplete, Entry::Complete),
+            _ => Entry::Incomplete,
+        };
+        Ok(Some(entry))
+    }
+
+    pub fn clear(&self) -> io::Result<()>{
+        fs::remove_file(&self.path)?;
+        sync_dir(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::{Entry, FileWrite, Journal};
+
+    #[test]
+    fn journal_test(){
+        let dir = std::env::temp_dir().join(format!("dev_restaurant_journal_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (items, sales) = (dir.join("food.csv"), dir.join("sales.csv"));
+        fs::write(&items, "id,name,stock\n0,risoles,5\n").unwrap();
+        fs::write(&sales, "id,item\n0,risoles\n").unwrap();
+
+        let writes = vec![
+            FileWrite{ path: items.clone(), append_at: None, content: "id,name,stock\n0,risoles,4\n".to_string() },
+            FileWrite{ path: sales.clone(), append_at: Some(18), content: "1,risoles\n".to_string() },
+        ];
+        let journal = Journal::new(&items);
+        assert_eq!(journal.read().unwrap(), None);
+        journal.write(&writes).unwrap();
+
+        // A crash halfway through appending, then the journal is replayed twice
+        fs::write(&sales, "id,item\n0,risoles\n1,ris").unwrap();
+        let Some(Entry::Complete(replay)) = journal.read().unwrap() else { panic!("journal not complete") };
+        for _ in 0..2{
+            replay.iter().for_each(|write| write.apply().unwrap());
+        }
+        assert_eq!(fs::read_to_string(&items).unwrap(), "id,name,stock\n0,risoles,4\n");
+        assert_eq!(fs::read_to_string(&sales).unwrap(), "id,item\n0,risoles\n1,risoles\n");
+
+        // A journal without its commit line was never acted on
+        let text = fs::read_to_string(dir.join("food.csv.journal")).unwrap();
+        fs::write(dir.join("food.csv.journal"), &text[..text.len() / 2]).unwrap();
+        assert_eq!(journal.read().unwrap(), Some(Entry::Incomplete));
+        journal.clear().unwrap();
+        assert_eq!(journal.read().unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+    str::FromStr
+};
+use thiserror::Error;
+
+use crate::{Ingredients, Items, Ledger, Movements, Opt, Orders, Promotions, PurchaseOrders, Recipes, Reports, Restaurant, Suppliers, TaxRules};
+use crate::i18n::tr;
+use crate::money::MoneyError;
+
+mod csv_backend;
+mod journal;
+mod sqlite_backend;
+
+pub use self::csv_backend::CsvStorage;
+pub use self::sqlite_backend::SqliteStorage;
+
+#[derive(Error, Debug)]
+pub enum StorageError{
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("{}", tr!("kesalahan database: {}", "database error: {}", .0))]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("{0}")]
+    Money(#[from] MoneyError),
+}
+
+impl StorageError{
+    pub fn code(&self) -> &'static str{
+        match self{
+            StorageError::Io(_) => "io",
+            StorageError::Sqlite(_) => "database",
+            StorageError::Money(e) => e.code(),
+        }
+    }
+}
+
+// Held by a process while it reads, changes and saves data, so two cashiers selling at the same
+// moment never overwrite each other. Advisory: every dev_restaurant process takes it before touching the data
+pub struct Lock{
+    _file: File
+}
+
+impl Lock{
+    // Waits for any other process holding it, the lock is let go when the file is closed
+    pub fn acquire(path: &Path) -> Result<Self, StorageError>{
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(path)?;
+        file.lock()?;
+        Ok(Self{ _file: file })
+    }
+}
+
+// A file kept next to the data it belongs to, like food.csv.lock or restaurant.db.lock
+pub fn sibling(data: &Path, suffix: &str) -> PathBuf{
+    let mut path = data.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+// What was found of a save that a crash or power cut interrupted
+#[derive(Debug, PartialEq)]
+pub enum Recovery{
+    // The last save finished
+    Clean,
+    // The save was written down in full, so it was finished; these files were written again
+    Replayed(Vec<PathBuf>),
+    // The save was cut off before any data was touched, so it was dropped
+    RolledBack
+}
+
+impl fmt::Display for Recovery{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        let message = match self{
+            Recovery::Clean => tr!("Tidak ada penyimpanan yang terputus", "No interrupted save was found"),
+            Recovery::Replayed(files) => {
+                let files: Vec<_> = files.iter().map(|file| file.display().to_string()).collect();
+                tr!(
+                    "Penyimpanan yang terputus diselesaikan, ditulis ulang: {}", "An interrupted save was finished, written again: {}",
+                    files.join(", ")
+                )
+            },
+            Recovery::RolledBack => tr!(
+                "Penyimpanan yang terputus sebelum menulis data dibatalkan", "An interrupted save was rolled back, no data had been written yet"
+            ),
+        };
+        write!(f, "{}", message)
+    }
+}
+
+// Everything `run` needs to read and persist data, regardless of where it lives
+pub trait Storage{
+    fn lock(&self) -> Result<Lock, StorageError>;
+
+    // Finishes or drops a save that was interrupted, called with the lock held before loading
+    fn recover(&mut self) -> Result<Recovery, StorageError>{
+        Ok(Recovery::Clean)
+    }
+
+    // Whether another process saved since this one last checked or saved, called with the lock held.
+    // The first call always says yes, as does a backend that cannot tell
+    fn changed(&mut self) -> Result<bool, StorageError>{
+        Ok(true)
+    }
+
+    fn load_items(&mut self, verbose: bool) -> Result<Items, StorageError>;
+
+    fn load_reports(&mut self, verbose: bool) -> Result<Reports, StorageError>;
+
+    fn load_ledger(&mut self, verbose: bool) -> Result<Ledger, StorageError>;
+
+    fn load_orders(&mut self, verbose: bool) -> Result<Orders, StorageError>;
+
+    fn load_movements(&mut self, verbose: bool) -> Result<Movements, StorageError>;
+
+    // Suppliers together with the items each of them delivers
+    fn load_suppliers(&mut self, verbose: bool) -> Result<Suppliers, StorageError>;
+
+    fn load_purchases(&mut self, verbose: bool) -> Result<PurchaseOrders, StorageError>;
+
+    fn load_ingredients(&mut self, verbose: bool) -> Result<Ingredients, StorageError>;
+
+    fn load_recipes(&mut self, verbose: bool) -> Result<Recipes, StorageError>;
+
+    fn load(&mut self, verbose: bool) -> Result<Restaurant, StorageError>{
+        let items = self.load_items(verbose)?;
+        let ledger = self.load_ledger(verbose)?;
+        let mut reports = self.load_reports(verbose)?;
+
+        // Reports are a view over the ledger, rebuild them if only the ledger is left
+        if reports.list.is_empty() && !ledger.list.is_empty(){
+            reports = ledger.daily_reports()?;
+        }
+
+        Ok(Restaurant{
+            items,
+            reports,
+            ledger,
+            orders: self.load_orders(verbose)?,
+            movements: self.load_movements(verbose)?,
+            suppliers: self.load_suppliers(verbose)?,
+            purchases: self.load_purchases(verbose)?,
+            ingredients: self.load_ingredients(verbose)?,
+            recipes: self.load_recipes(verbose)?,
+            taxes: TaxRules::new(),
+            promotions: Promotions::new()
+        })
+    }
+
+    // Reads everything again for processes that stay open, the tax and promotion config already read is kept
+    fn reload(&mut self, data: &mut Restaurant, verbose: bool) -> Result<(), StorageError>{
+        let mut fresh = self.load(verbose)?;
+        fresh.taxes = std::mem::replace(&mut data.taxes, TaxRules::new());
+        fresh.promotions = std::mem::replace(&mut data.promotions, Promotions::new());
+        *data = fresh;
+        Ok(())
+    }
+
+    // Starts a change in a process that stays open: waits for the lock, then reads what others saved meanwhile.
+    // Data is only read again when the store changed, so a change that fails without saving has to reload itself
+    fn begin(&mut self, data: &mut Restaurant) -> Result<Lock, StorageError>{
+        let lock = self.lock()?;
+        let recovery = self.recover()?;
+        if recovery != Recovery::Clean || self.changed()?{
+            self.reload(data, false)?;
+        }
+        Ok(lock)
+    }
+
+    // Persists everything together, so a sale never updates one list without the others.
+    // Ledger sales, orders and stock movements are only ever appended
+    fn save(&mut self, data: &Restaurant) -> Result<(), StorageError>;
+}
+
+// Storage backend selected with --backend
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend{
+    Csv,
+    Sqlite
+}
+
+impl FromStr for Backend{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        match s.to_lowercase().as_str(){
+            "csv" => Ok(Backend::Csv),
+            "sqlite" => Ok(Backend::Sqlite),
+            _ => Err(tr!("backend \"{}\" tidak dikenal, seharusnya csv atau sqlite", "unknown backend \"{}\", expected csv or sqlite", s)),
+        }
+    }
+}
+
+pub fn open(opt: &Opt) -> Result<Box<dyn Storage>, StorageError>{
+    match opt.backend{
+        Backend::Csv => Ok(Box::new(CsvStorage::new(opt))),
+        Backend::Sqlite => Ok(Box::new(SqliteStorage::open(opt.database.clone())?)),
+    }
+}
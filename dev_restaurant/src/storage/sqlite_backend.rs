@@ -0,0 +1,744 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf
+};
+use rusqlite::{
+    params,
+    types::{FromSql, FromSqlResult, ToSqlOutput, Type, ValueRef},
+    Connection, ToSql
+};
+
+use crate::{Item, Items, Ledger, Order, Orders, Report, Reports, Restaurant, Sale};
+use crate::money::Money;
+use crate::recipe::{Ingredient, Ingredients, RecipeLine, Recipes};
+use crate::purchase::{PurchaseLine, PurchaseOrder, PurchaseOrders, Supplier, SupplierItem, Suppliers};
+use crate::stock::{Movement, Movements};
+use super::{sibling, Lock, Storage, StorageError};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS items (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        stock INTEGER NOT NULL,
+        price INTEGER NOT NULL,
+        category TEXT NOT NULL DEFAULT '',
+        reorder_level INTEGER NOT NULL DEFAULT 0,
+        reorder_target INTEGER NOT NULL DEFAULT 0,
+        cost INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS reports (
+        id INTEGER PRIMARY KEY,
+        date TEXT NOT NULL,
+        quantity INTEGER NOT NULL,
+        income INTEGER NOT NULL,
+        service INTEGER NOT NULL DEFAULT 0,
+        tax INTEGER NOT NULL DEFAULT 0,
+        discount INTEGER NOT NULL DEFAULT 0,
+        cost INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS sales (
+        id INTEGER PRIMARY KEY,
+        timestamp TEXT NOT NULL,
+        item_id INTEGER NOT NULL,
+        item_name TEXT NOT NULL,
+        quantity INTEGER NOT NULL,
+        unit_price INTEGER NOT NULL,
+        total INTEGER NOT NULL,
+        refund_of INTEGER,
+        reason TEXT NOT NULL DEFAULT '',
+        order_id INTEGER,
+        service INTEGER NOT NULL DEFAULT 0,
+        tax INTEGER NOT NULL DEFAULT 0,
+        discount INTEGER NOT NULL DEFAULT 0,
+        promotion TEXT NOT NULL DEFAULT '',
+        cost INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS orders (
+        id INTEGER PRIMARY KEY,
+        number INTEGER NOT NULL,
+        timestamp TEXT NOT NULL,
+        paid INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS movements (
+        id INTEGER PRIMARY KEY,
+        timestamp TEXT NOT NULL,
+        item_id INTEGER NOT NULL,
+        item_name TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        quantity INTEGER NOT NULL,
+        unit_cost INTEGER NOT NULL DEFAULT 0,
+        supplier TEXT NOT NULL DEFAULT '',
+        note TEXT NOT NULL DEFAULT ''
+    );
+    CREATE TABLE IF NOT EXISTS suppliers (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        phone TEXT NOT NULL DEFAULT '',
+        note TEXT NOT NULL DEFAULT ''
+    );
+    CREATE TABLE IF NOT EXISTS supplier_items (
+        supplier_id INTEGER NOT NULL,
+        item_id INTEGER NOT NULL,
+        item_name TEXT NOT NULL,
+        unit_cost INTEGER NOT NULL,
+        PRIMARY KEY (supplier_id, item_id)
+    );
+    CREATE TABLE IF NOT EXISTS purchase_orders (
+        id INTEGER PRIMARY KEY,
+        supplier_id INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        created TEXT NOT NULL,
+        expected TEXT NOT NULL DEFAULT ''
+    );
+    CREATE TABLE IF NOT EXISTS purchase_lines (
+        order_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        item_id INTEGER NOT NULL,
+        item_name TEXT NOT NULL,
+        quantity INTEGER NOT NULL,
+        received INTEGER NOT NULL DEFAULT 0,
+        unit_cost INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (order_id, position)
+    );
+    CREATE TABLE IF NOT EXISTS ingredients (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        unit TEXT NOT NULL DEFAULT '',
+        stock INTEGER NOT NULL,
+        cost INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS recipes (
+        item_id INTEGER NOT NULL,
+        ingredient_id INTEGER NOT NULL,
+        quantity INTEGER NOT NULL,
+        PRIMARY KEY (item_id, ingredient_id)
+    );
+";
+
+// Columns added after a table was first released, as (table, column, definition)
+const MIGRATIONS: [(&str, &str, &str); 17] = [
+    ("sales", "refund_of", "INTEGER"),
+    ("sales", "reason", "TEXT NOT NULL DEFAULT ''"),
+    ("sales", "order_id", "INTEGER"),
+    ("items", "category", "TEXT NOT NULL DEFAULT ''"),
+    ("reports", "service", "INTEGER NOT NULL DEFAULT 0"),
+    ("reports", "tax", "INTEGER NOT NULL DEFAULT 0"),
+    ("sales", "service", "INTEGER NOT NULL DEFAULT 0"),
+    ("sales", "tax", "INTEGER NOT NULL DEFAULT 0"),
+    ("reports", "discount", "INTEGER NOT NULL DEFAULT 0"),
+    ("sales", "discount", "INTEGER NOT NULL DEFAULT 0"),
+    ("sales", "promotion", "TEXT NOT NULL DEFAULT ''"),
+    ("items", "reorder_level", "INTEGER NOT NULL DEFAULT 0"),
+    ("items", "reorder_target", "INTEGER NOT NULL DEFAULT 0"),
+    ("items", "cost", "INTEGER NOT NULL DEFAULT 0"),
+    ("reports", "cost", "INTEGER NOT NULL DEFAULT 0"),
+    ("sales", "cost", "INTEGER NOT NULL DEFAULT 0"),
+    ("ingredients", "cost", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+// Money columns held whole rupiah before user_version 1, they hold sen since
+const MONEY_COLUMNS: [(&str, &[&str]); 8] = [
+    ("items", &["price", "cost"]),
+    ("reports", &["income", "service", "tax", "discount", "cost"]),
+    ("sales", &["unit_price", "total", "service", "tax", "discount", "cost"]),
+    ("orders", &["paid"]),
+    ("movements", &["unit_cost"]),
+    ("supplier_items", &["unit_cost"]),
+    ("purchase_lines", &["unit_cost"]),
+    ("ingredients", &["cost"]),
+];
+
+impl ToSql for Money{
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>>{
+        Ok(ToSqlOutput::from(self.sen()))
+    }
+}
+
+impl FromSql for Money{
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self>{
+        i64::column_result(value).map(Money::from_sen)
+    }
+}
+
+// Embedded SQLite database kept in a single local file
+pub struct SqliteStorage{
+    conn: Connection,
+    database: PathBuf,
+    // Rows as they were last loaded or saved, so only changed rows get written
+    items: HashMap<i64, Item>,
+    reports: HashMap<i64, Report>,
+    suppliers: Vec<Supplier>,
+    catalog: Vec<SupplierItem>,
+    purchases: Vec<PurchaseOrder>,
+    ingredients: Vec<Ingredient>,
+    recipes: Vec<RecipeLine>,
+    // Ledger sales, orders and movements already in their tables
+    saved_sales: usize,
+    saved_orders: usize,
+    saved_movements: usize,
+    // PRAGMA data_version when last checked, it only moves when another connection commits
+    data_version: Option<i64>
+}
+
+impl SqliteStorage{
+    pub fn open(database: PathBuf) -> Result<Self, StorageError>{
+        Self::from_connection(Connection::open(&database)?, database)
+    }
+
+    fn from_connection(conn: Connection, database: PathBuf) -> Result<Self, StorageError>{
+        conn.execute_batch(SCHEMA)?;
+        migrate(&conn)?;
+        Ok(Self{
+            conn,
+            database,
+            items: HashMap::new(),
+            reports: HashMap::new(),
+            suppliers: Vec::new(),
+            catalog: Vec::new(),
+            purchases: Vec::new(),
+            ingredients: Vec::new(),
+            recipes: Vec::new(),
+            saved_sales: 0,
+            saved_orders: 0,
+            saved_movements: 0,
+            data_version: None
+        })
+    }
+}
+
+// Brings databases created by older versions up to the current schema
+fn migrate(conn: &Connection) -> Result<(), StorageError>{
+    for (table, column, definition) in MIGRATIONS{
+        let exists: bool = conn.query_row(
+            &format!("SELECT COUNT(*) > 0 FROM pragma_table_info('{}') WHERE name = ?1", table),
+            params![column],
+            |row| row.get(0),
+        )?;
+        if !exists{
+            conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))?;
+        }
+    }
+
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version < 1{
+        let mut batch = String::from("BEGIN;\n");
+        for (table, columns) in MONEY_COLUMNS{
+            let scaled: Vec<String> = columns.iter().map(|column| format!("{0} = {0} * 100", column)).collect();
+            batch.push_str(&format!("UPDATE {} SET {};\n", table, scaled.join(", ")));
+        }
+        batch.push_str("PRAGMA user_version = 1;\nCOMMIT;");
+        conn.execute_batch(&batch)?;
+    }
+    Ok(())
+}
+
+impl Storage for SqliteStorage{
+    // SQLite locks during a write only, the lock file also covers the read before it.
+    // A save cut off halfway is rolled back by SQLite itself, so there is never anything to recover
+    fn lock(&self) -> Result<Lock, StorageError>{
+        Lock::acquire(&sibling(&self.database, ".lock"))
+    }
+
+    fn changed(&mut self) -> Result<bool, StorageError>{
+        let version = Some(self.conn.query_row("PRAGMA data_version", [], |row| row.get(0))?);
+        let changed = self.data_version != version;
+        self.data_version = version;
+        Ok(changed)
+    }
+
+    fn load_items(&mut self, verbose: bool) -> Result<Items, StorageError>{
+        let mut items = Items::new();
+        let mut stmt = self.conn.prepare("SELECT id, name, stock, price, category, reorder_level, reorder_target, cost FROM items ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                (row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, Money>(3)?),
+                (row.get::<_, String>(4)?, row.get::<_, i64>(5)?, row.get::<_, i64>(6)?, row.get::<_, Money>(7)?)
+            ))
+        })?;
+
+        for row in rows{
+            let ((id, name, quantity, price), (category, reorder_level, reorder_target, cost)) = row?;
+            if verbose {
+                println!("Adding {} | {} | {} | {} to item list", id, name, quantity, price);
+            };
+            items.add(&name, quantity, price, id, true);
+            if let Some(item) = items.list.get_mut(&id){
+                item.category = category;
+                item.reorder_level = reorder_level;
+                item.reorder_target = reorder_target;
+                item.cost = cost;
+            }
+        }
+
+        self.items = items.list.clone();
+        Ok(items)
+    }
+
+    fn load_reports(&mut self, verbose: bool) -> Result<Reports, StorageError>{
+        let mut reports = Reports::new();
+        let mut stmt = self.conn.prepare("SELECT id, date, quantity, income, service, tax, discount, cost FROM reports ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Report{
+                id: row.get(0)?,
+                date: row.get(1)?,
+                quantity: row.get(2)?,
+                income: row.get(3)?,
+                service: row.get(4)?,
+                tax: row.get(5)?,
+                discount: row.get(6)?,
+                cost: row.get(7)?,
+            })
+        })?;
+
+        for row in rows{
+            let report = row?;
+            if verbose {
+                println!("Adding {} | {} | {} | {} to reports list", report.id, report.date, report.quantity, report.income);
+            };
+            reports.list.insert(report.id, report);
+        }
+
+        self.reports = reports.list.clone();
+        Ok(reports)
+    }
+
+    fn load_ledger(&mut self, verbose: bool) -> Result<Ledger, StorageError>{
+        let mut ledger = Ledger::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT id, timestamp, item_id, item_name, quantity, unit_price, total, refund_of, reason, COALESCE(order_id, id), service, tax,
+             discount, promotion, cost FROM sales ORDER BY id"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Sale{
+                id: row.get(0)?,
+                order_id: row.get(9)?,
+                timestamp: row.get(1)?,
+                item_id: row.get(2)?,
+                item_name: row.get(3)?,
+                quantity: row.get(4)?,
+                unit_price: row.get(5)?,
+                total: row.get(6)?,
+                refund_of: row.get(7)?,
+                reason: row.get(8)?,
+                service: row.get(10)?,
+                tax: row.get(11)?,
+                discount: row.get(12)?,
+                promotion: row.get(13)?,
+                cost: row.get(14)?,
+            })
+        })?;
+
+        for row in rows{
+            let sale = row?;
+            if verbose {
+                println!("Adding sale {} | {} | {} x {} to ledger", sale.id, sale.timestamp, sale.item_name, sale.quantity);
+            };
+            ledger.push(sale);
+        }
+
+        self.saved_sales = ledger.list.len();
+        Ok(ledger)
+    }
+
+    fn load_orders(&mut self, verbose: bool) -> Result<Orders, StorageError>{
+        let mut orders = Orders::new();
+        let mut stmt = self.conn.prepare("SELECT id, number, timestamp, paid FROM orders ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Order{
+                id: row.get(0)?,
+                number: row.get(1)?,
+                timestamp: row.get(2)?,
+                paid: row.get(3)?,
+            })
+        })?;
+
+        for row in rows{
+            let order = row?;
+            if verbose {
+                println!("Adding order {} | #{} | {} to orders", order.id, order.number, order.timestamp);
+            };
+            orders.push(order);
+        }
+
+        self.saved_orders = orders.list.len();
+        Ok(orders)
+    }
+
+    fn load_movements(&mut self, verbose: bool) -> Result<Movements, StorageError>{
+        let mut movements = Movements::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT id, timestamp, item_id, item_name, kind, quantity, unit_cost, supplier, note FROM movements ORDER BY id"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Movement{
+                id: row.get(0)?,
+                timestamp: row.get(1)?,
+                item_id: row.get(2)?,
+                item_name: row.get(3)?,
+                kind: row.get::<_, String>(4)?.parse()
+                    .map_err(|e: String| rusqlite::Error::FromSqlConversionFailure(4, Type::Text, e.into()))?,
+                quantity: row.get(5)?,
+                unit_cost: row.get(6)?,
+                supplier: row.get(7)?,
+                note: row.get(8)?,
+            })
+        })?;
+
+        for row in rows{
+            let movement = row?;
+            if verbose {
+                println!("Adding movement {} | {} | {} | {} to stock history", movement.id, movement.timestamp, movement.item_name, movement.quantity);
+            };
+            movements.push(movement);
+        }
+
+        self.saved_movements = movements.list.len();
+        Ok(movements)
+    }
+
+    fn load_suppliers(&mut self, verbose: bool) -> Result<Suppliers, StorageError>{
+        let mut suppliers = Suppliers::new();
+        let mut stmt = self.conn.prepare("SELECT id, name, phone, note FROM suppliers ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Supplier{
+                id: row.get(0)?,
+                name: row.get(1)?,
+                phone: row.get(2)?,
+                note: row.get(3)?,
+            })
+        })?;
+        for row in rows{
+            let supplier = row?;
+            if verbose {
+                println!("Adding supplier {} | {} to suppliers", supplier.id, supplier.name);
+            };
+            suppliers.list.push(supplier);
+        }
+
+        let mut stmt = self.conn.prepare("SELECT supplier_id, item_id, item_name, unit_cost FROM supplier_items ORDER BY rowid")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(SupplierItem{
+                supplier_id: row.get(0)?,
+                item_id: row.get(1)?,
+                item_name: row.get(2)?,
+                unit_cost: row.get(3)?,
+            })
+        })?;
+        for row in rows{
+            suppliers.catalog.push(row?);
+        }
+
+        self.suppliers = suppliers.list.clone();
+        self.catalog = suppliers.catalog.clone();
+        Ok(suppliers)
+    }
+
+    fn load_purchases(&mut self, verbose: bool) -> Result<PurchaseOrders, StorageError>{
+        let mut purchases = PurchaseOrders::new();
+        let mut stmt = self.conn.prepare("SELECT id, supplier_id, status, created, expected FROM purchase_orders ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(PurchaseOrder{
+                id: row.get(0)?,
+                supplier_id: row.get(1)?,
+                status: row.get::<_, String>(2)?.parse()
+                    .map_err(|e: String| rusqlite::Error::FromSqlConversionFailure(2, Type::Text, e.into()))?,
+                created: row.get(3)?,
+                expected: row.get(4)?,
+                lines: Vec::new(),
+            })
+        })?;
+        for row in rows{
+            purchases.list.push(row?);
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT order_id, item_id, item_name, quantity, received, unit_cost FROM purchase_lines ORDER BY order_id, position"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, PurchaseLine{
+                item_id: row.get(1)?,
+                item_name: row.get(2)?,
+                quantity: row.get(3)?,
+                received: row.get(4)?,
+                unit_cost: row.get(5)?,
+            }))
+        })?;
+        for row in rows{
+            let (order_id, line) = row?;
+            if verbose {
+                println!("Adding purchase order {} | {} | {} to purchase orders", order_id, line.item_name, line.quantity);
+            };
+            if let Some(order) = purchases.list.iter_mut().find(|order| order.id == order_id){
+                order.lines.push(line);
+            }
+        }
+
+        self.purchases = purchases.list.clone();
+        Ok(purchases)
+    }
+
+    fn load_ingredients(&mut self, verbose: bool) -> Result<Ingredients, StorageError>{
+        let mut ingredients = Ingredients::new();
+        let mut stmt = self.conn.prepare("SELECT id, name, unit, stock, cost FROM ingredients ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Ingredient{
+                id: row.get(0)?,
+                name: row.get(1)?,
+                unit: row.get(2)?,
+                quantity: row.get(3)?,
+                cost: row.get(4)?,
+            })
+        })?;
+        for row in rows{
+            let ingredient = row?;
+            if verbose {
+                println!("Adding ingredient {} | {} | {} to ingredients", ingredient.id, ingredient.name, ingredient.quantity);
+            };
+            ingredients.list.push(ingredient);
+        }
+
+        self.ingredients = ingredients.list.clone();
+        Ok(ingredients)
+    }
+
+    fn load_recipes(&mut self, _verbose: bool) -> Result<Recipes, StorageError>{
+        let mut recipes = Recipes::new();
+        let mut stmt = self.conn.prepare("SELECT item_id, ingredient_id, quantity FROM recipes ORDER BY rowid")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(RecipeLine{
+                item_id: row.get(0)?,
+                ingredient_id: row.get(1)?,
+                quantity: row.get(2)?,
+            })
+        })?;
+        for row in rows{
+            recipes.list.push(row?);
+        }
+
+        self.recipes = recipes.list.clone();
+        Ok(recipes)
+    }
+
+    // Appends new sales, orders and movements and writes only the rows that differ from what was loaded, inside a single transaction
+    fn save(&mut self, data: &Restaurant) -> Result<(), StorageError>{
+        let (items, reports) = (&data.items, &data.reports);
+        let tx = self.conn.transaction()?;
+
+        for sale in &data.ledger.list[self.saved_sales..]{
+            tx.execute(
+                "INSERT INTO sales (id, timestamp, item_id, item_name, quantity, unit_price, total, refund_of, reason, order_id, service, tax,
+                     discount, promotion, cost)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                params![
+                    sale.id, sale.timestamp, sale.item_id, sale.item_name, sale.quantity, sale.unit_price, sale.total,
+                    sale.refund_of, sale.reason, sale.order_id, sale.service, sale.tax, sale.discount, sale.promotion, sale.cost
+                ],
+            )?;
+        }
+        for order in &data.orders.list[self.saved_orders..]{
+            tx.execute(
+                "INSERT INTO orders (id, number, timestamp, paid) VALUES (?1, ?2, ?3, ?4)",
+                params![order.id, order.number, order.timestamp, order.paid],
+            )?;
+        }
+        for movement in &data.movements.list[self.saved_movements..]{
+            tx.execute(
+                "INSERT INTO movements (id, timestamp, item_id, item_name, kind, quantity, unit_cost, supplier, note)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    movement.id, movement.timestamp, movement.item_id, movement.item_name, movement.kind.as_str(),
+                    movement.quantity, movement.unit_cost, movement.supplier, movement.note
+                ],
+            )?;
+        }
+
+        for item in items.list.values(){
+            if self.items.get(&item.id) != Some(item){
+                tx.execute(
+                    "INSERT OR REPLACE INTO items (id, name, stock, price, category, reorder_level, reorder_target, cost)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![item.id, item.name, item.quantity, item.price, item.category, item.reorder_level, item.reorder_target, item.cost],
+                )?;
+            }
+        }
+        for id in self.items.keys(){
+            if !items.list.contains_key(id){
+                tx.execute("DELETE FROM items WHERE id = ?1", params![id])?;
+            }
+        }
+
+        for report in reports.list.values(){
+            if self.reports.get(&report.id) != Some(report){
+                tx.execute(
+                    "INSERT OR REPLACE INTO reports (id, date, quantity, income, service, tax, discount, cost)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![report.id, report.date, report.quantity, report.income, report.service, report.tax, report.discount, report.cost],
+                )?;
+            }
+        }
+        for id in self.reports.keys(){
+            if !reports.list.contains_key(id){
+                tx.execute("DELETE FROM reports WHERE id = ?1", params![id])?;
+            }
+        }
+
+        // Suppliers, purchase orders, ingredients and recipes are few, they are rewritten whole when anything changed
+        if self.suppliers != data.suppliers.list || self.catalog != data.suppliers.catalog{
+            tx.execute("DELETE FROM suppliers", [])?;
+            tx.execute("DELETE FROM supplier_items", [])?;
+            for supplier in &data.suppliers.list{
+                tx.execute(
+                    "INSERT INTO suppliers (id, name, phone, note) VALUES (?1, ?2, ?3, ?4)",
+                    params![supplier.id, supplier.name, supplier.phone, supplier.note],
+                )?;
+            }
+            for entry in &data.suppliers.catalog{
+                tx.execute(
+                    "INSERT INTO supplier_items (supplier_id, item_id, item_name, unit_cost) VALUES (?1, ?2, ?3, ?4)",
+                    params![entry.supplier_id, entry.item_id, entry.item_name, entry.unit_cost],
+                )?;
+            }
+        }
+        if self.purchases != data.purchases.list{
+            tx.execute("DELETE FROM purchase_orders", [])?;
+            tx.execute("DELETE FROM purchase_lines", [])?;
+            for order in &data.purchases.list{
+                tx.execute(
+                    "INSERT INTO purchase_orders (id, supplier_id, status, created, expected) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![order.id, order.supplier_id, order.status.as_str(), order.created, order.expected],
+                )?;
+                for (position, line) in order.lines.iter().enumerate(){
+                    tx.execute(
+                        "INSERT INTO purchase_lines (order_id, position, item_id, item_name, quantity, received, unit_cost)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![order.id, position as i64, line.item_id, line.item_name, line.quantity, line.received, line.unit_cost],
+                    )?;
+                }
+            }
+        }
+
+        if self.ingredients != data.ingredients.list{
+            tx.execute("DELETE FROM ingredients", [])?;
+            for ingredient in &data.ingredients.list{
+                tx.execute(
+                    "INSERT INTO ingredients (id, name, unit, stock, cost) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![ingredient.id, ingredient.name, ingredient.unit, ingredient.quantity, ingredient.cost],
+                )?;
+            }
+        }
+        if self.recipes != data.recipes.list{
+            tx.execute("DELETE FROM recipes", [])?;
+            for line in &data.recipes.list{
+                tx.execute(
+                    "INSERT INTO recipes (item_id, ingredient_id, quantity) VALUES (?1, ?2, ?3)",
+                    params![line.item_id, line.ingredient_id, line.quantity],
+                )?;
+            }
+        }
+
+        tx.commit()?;
+
+        self.items = items.list.clone();
+        self.reports = reports.list.clone();
+        self.suppliers = data.suppliers.list.clone();
+        self.catalog = data.suppliers.catalog.clone();
+        self.purchases = data.purchases.list.clone();
+        self.ingredients = data.ingredients.list.clone();
+        self.recipes = data.recipes.list.clone();
+        self.saved_sales = data.ledger.list.len();
+        self.saved_orders = data.orders.list.len();
+        self.saved_movements = data.movements.list.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use rusqlite::Connection;
+    use crate::{OrderLine, Restaurant};
+    use crate::money::rp;
+    use crate::storage::Storage;
+    use super::{SqliteStorage, MIGRATIONS, SCHEMA};
+
+    #[test]
+    fn sqlite_round_trip_test(){
+        let mut storage = SqliteStorage::from_connection(Connection::open_in_memory().unwrap(), PathBuf::new()).unwrap();
+
+        let mut data = Restaurant::new();
+        data.items.add("Risoles", 164, rp(25000), 0, false);
+        data.items.add("Ikan Asin", 512, rp(25182), 0, false);
+        storage.save(&data).unwrap();
+
+        data.items.delete("Risoles").unwrap();
+        let line = OrderLine{ name: "Ikan Asin".to_string(), quantity: 12 };
+        let (order, sales) = data.order(&[line], None, "2022-11-22 10:00:00").unwrap();
+        data.suppliers.add("Pasar", "", "").unwrap();
+        let line = OrderLine{ name: "Ikan Asin".to_string(), quantity: 10 };
+        data.create_purchase("pasar", &[line], "", "2022-11-22 11:00:00").unwrap();
+        data.send_purchase(0).unwrap();
+        data.receive_purchase(0, &[], "2022-11-22 11:00:00").unwrap();
+        storage.save(&data).unwrap();
+
+        let loaded = storage.load(false).unwrap();
+        assert_eq!(loaded.items.list.len(), 1);
+        assert_eq!(loaded.items.find_item("ikan asin").unwrap().quantity, 510);
+        assert_eq!(loaded.movements.list, data.movements.list);
+        assert_eq!(loaded.suppliers.list, data.suppliers.list);
+        assert_eq!(loaded.purchases.list, data.purchases.list);
+        assert_eq!(loaded.reports.find_report("2022-11-22").unwrap().income, rp(302184));
+        assert_eq!(loaded.ledger.list, sales);
+        assert_eq!(loaded.orders.list, vec![order]);
+    }
+
+    #[test]
+    fn changed_test(){
+        let database = std::env::temp_dir().join(format!("dev_restaurant_changed_{}.db", std::process::id()));
+        let mut till = SqliteStorage::open(database.clone()).unwrap();
+        let mut other = SqliteStorage::open(database.clone()).unwrap();
+        let mut data = Restaurant::new();
+
+        // Saves of its own do not count, only what another connection commits
+        assert!(till.changed().unwrap());
+        data.items.add("Risoles", 5, rp(5000), 0, false);
+        till.save(&data).unwrap();
+        assert!(!till.changed().unwrap());
+        let mut theirs = other.load(false).unwrap();
+        theirs.items.add("Es Teh", 10, rp(5000), 0, false);
+        other.save(&theirs).unwrap();
+        assert!(till.changed().unwrap());
+        assert!(!till.changed().unwrap());
+
+        std::fs::remove_file(&database).unwrap();
+    }
+
+    #[test]
+    fn migrate_old_sales_table_test(){
+        // A new database gets every column from the schema alone, migrations are only for older files
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        for (table, column, _) in MIGRATIONS{
+            let exists: bool = conn.query_row(
+                &format!("SELECT COUNT(*) > 0 FROM pragma_table_info('{}') WHERE name = ?1", table), [column], |row| row.get(0)
+            ).unwrap();
+            assert!(exists, "{}.{}", table, column);
+        }
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("
+            CREATE TABLE sales (id INTEGER PRIMARY KEY, timestamp TEXT NOT NULL, item_id INTEGER NOT NULL, item_name TEXT NOT NULL,
+                quantity INTEGER NOT NULL, unit_price INTEGER NOT NULL, total INTEGER NOT NULL);
+            INSERT INTO sales VALUES (0, '2022-11-22 10:00:00', 0, 'risoles', 2, 25000, 50000);
+        ").unwrap();
+
+        let mut storage = SqliteStorage::from_connection(conn, PathBuf::new()).unwrap();
+        let ledger = storage.load_ledger(false).unwrap();
+        assert_eq!(ledger.list[0].refund_of, None);
+        assert_eq!(ledger.list[0].reason, "");
+        assert_eq!(ledger.list[0].order_id, 0);
+        // Amounts were kept in rupiah, they are converted to sen once
+        assert_eq!((ledger.list[0].unit_price, ledger.list[0].total), (rp(25000), rp(50000)));
+        let mut storage = SqliteStorage::from_connection(storage.conn, PathBuf::new()).unwrap();
+        assert_eq!(storage.load_ledger(false).unwrap().list[0].total, rp(50000));
+    }
+}
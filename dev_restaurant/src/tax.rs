@@ -0,0 +1,207 @@
+use std::{
+    fs,
+    io,
+    path::Path
+};
+use csv::ReaderBuilder;
+use thiserror::Error;
+
+use crate::i18n::tr;
+use crate::money::{Money, MoneyError};
+
+// Rates are kept in hundredths of a percent, 1100 is 11%
+const FULL_RATE: i64 = 10000;
+
+// Rule used for items without a category of their own
+const DEFAULT_CATEGORY: &str = "*";
+
+#[derive(Error, Debug)]
+pub enum TaxError{
+    #[error("{}", tr!("konfigurasi pajak rusak {}", "malformed tax config {}", .0))]
+    Csv(#[from] csv::Error),
+
+    #[error("{}", tr!("tidak bisa dibaca: {}", "cannot be read: {}", .0))]
+    Io(#[from] io::Error),
+
+    #[error("{}", tr!("tarif \"{}\" tidak valid, seharusnya persentase seperti 11 atau 5.5", "invalid rate \"{}\", expected a percentage like 11 or 5.5", .0))]
+    InvalidRate(String),
+
+    #[error("{}", tr!("nilai inclusive \"{}\" tidak valid, seharusnya true atau false", "invalid inclusive value \"{}\", expected true or false", .0))]
+    InvalidInclusive(String),
+
+    #[error("{}", tr!("kolom {} tidak ada", "missing field {}", .0))]
+    MissingField(String),
+}
+
+// Service charge and PPN applied to one item category
+#[derive(Clone, Debug, PartialEq)]
+pub struct TaxRule{
+    pub category: String,
+    pub service_rate: i64,
+    pub tax_rate: i64,
+    // Whether the menu price already contains service and tax
+    pub inclusive: bool
+}
+
+// How the amount of a sale line splits up. Gross is what the customer pays
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Charges{
+    pub net: Money,
+    pub service: Money,
+    pub tax: Money,
+    pub gross: Money
+}
+
+impl Charges{
+    pub fn untaxed(amount: Money) -> Self{
+        Self{
+            net: amount,
+            service: Money::ZERO,
+            tax: Money::ZERO,
+            gross: amount
+        }
+    }
+}
+
+pub struct TaxRules{
+    pub list: Vec<TaxRule>
+}
+
+// amount * rate, rounded half up to the sen
+pub fn apply_rate(amount: Money, rate: i64) -> Result<Money, MoneyError>{
+    amount.scale(rate, FULL_RATE)
+}
+
+// Parses "11" or "5.5" into hundredths of a percent
+pub fn parse_rate(rate: &str) -> Result<i64, TaxError>{
+    let invalid = || TaxError::InvalidRate(rate.to_string());
+    let rate = rate.trim().trim_end_matches('%');
+    let (whole, fraction) = rate.split_once('.').unwrap_or((rate, ""));
+
+    if fraction.len() > 2 || !fraction.chars().all(|c| c.is_ascii_digit()){
+        return Err(invalid());
+    }
+    let whole = whole.parse::<i64>().map_err(|_| invalid())?;
+    let fraction = format!("{:0<2}", fraction).parse::<i64>().map_err(|_| invalid())?;
+    if whole < 0{
+        return Err(invalid());
+    }
+    Ok(whole * 100 + fraction)
+}
+
+fn parse_inclusive(value: &str) -> Result<bool, TaxError>{
+    match value.trim().to_lowercase().as_str(){
+        "true" | "yes" | "ya" | "1" => Ok(true),
+        "false" | "no" | "tidak" | "0" | "" => Ok(false),
+        _ => Err(TaxError::InvalidInclusive(value.to_string())),
+    }
+}
+
+impl TaxRules{
+    pub fn new() -> Self{
+        Self{
+            list: Vec::new()
+        }
+    }
+
+    // Tax config: category,service,tax,inclusive with rates in percent, "*" is the default rule.
+    // A missing file means nothing is taxed, one that cannot be read is an error rather than selling untaxed
+    pub fn load(file: &Path) -> Result<Self, TaxError>{
+        let buffer = match fs::read(file){
+            Ok(buffer) => buffer,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Self::parse(&buffer)
+    }
+
+    pub fn parse(buffer: &[u8]) -> Result<Self, TaxError>{
+        let mut rules = Self::new();
+        let mut reader = ReaderBuilder::new().flexible(true).trim(csv::Trim::All).from_reader(buffer);
+
+        for record in reader.records(){
+            let record = record?;
+            let field = |index: usize, name: &str| record.get(index).ok_or_else(|| TaxError::MissingField(name.to_string()));
+            rules.list.push(TaxRule{
+                category: field(0, "category")?.to_lowercase(),
+                service_rate: parse_rate(field(1, "service")?)?,
+                tax_rate: parse_rate(field(2, "tax")?)?,
+                inclusive: parse_inclusive(record.get(3).unwrap_or(""))?,
+            });
+        }
+        Ok(rules)
+    }
+
+    fn find_rule(&self, category: &str) -> Option<&TaxRule>{
+        let category = category.to_lowercase();
+        self.list.iter().find(|rule| rule.category == category)
+            .or_else(|| self.list.iter().find(|rule| rule.category == DEFAULT_CATEGORY))
+    }
+
+    // Splits `amount` (menu price times quantity) for an item of `category`.
+    // Service is charged on the net amount and PPN on net plus service, each rounded to whole rupiah
+    pub fn charges(&self, category: &str, amount: Money) -> Result<Charges, MoneyError>{
+        let rule = match self.find_rule(category){
+            Some(rule) => rule,
+            None => return Ok(Charges::untaxed(amount)),
+        };
+
+        if rule.inclusive{
+            let gross = amount;
+            let divisor = (FULL_RATE + rule.service_rate) * (FULL_RATE + rule.tax_rate);
+            let net = gross.scale(FULL_RATE * FULL_RATE, divisor)?.round_rupiah()?;
+            let service = apply_rate(net, rule.service_rate)?.round_rupiah()?;
+            Ok(Charges{
+                net,
+                service,
+                tax: gross.checked_sub(net)?.checked_sub(service)?,
+                gross
+            })
+        } else {
+            let net = amount;
+            let service = apply_rate(net, rule.service_rate)?.round_rupiah()?;
+            let tax = apply_rate(net.checked_add(service)?, rule.tax_rate)?.round_rupiah()?;
+            Ok(Charges{
+                net,
+                service,
+                tax,
+                gross: Money::sum([net, service, tax])?
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::money::{rp, MoneyError};
+    use super::{parse_rate, Charges, TaxError, TaxRules};
+
+    #[test]
+    fn parse_rate_test(){
+        assert_eq!(parse_rate("11").unwrap(), 1100);
+        assert_eq!(parse_rate("5.5").unwrap(), 550);
+        assert_eq!(parse_rate("12.25%").unwrap(), 1225);
+        assert!(parse_rate("1.125").is_err());
+        assert!(parse_rate("-1").is_err());
+        assert!(parse_rate("sebelas").is_err());
+
+        assert!(TaxRules::load(&std::env::temp_dir().join("dev_restaurant_no_tax.csv")).unwrap().list.is_empty());
+        assert!(matches!(TaxRules::load(&std::env::temp_dir()), Err(TaxError::Io(_))));
+    }
+
+    #[test]
+    fn charges_test(){
+        let rules = TaxRules::parse(b"category,service,tax,inclusive\n*,5,11,false\nminuman,0,11,true\nberas,0,0\n").unwrap();
+
+        // 100000 + 5% service = 105000, + 11% PPN = 116550
+        assert_eq!(rules.charges("", rp(100000)), Ok(Charges{ net: rp(100000), service: rp(5000), tax: rp(11550), gross: rp(116550) }));
+        assert_eq!(rules.charges("Minuman", rp(11100)), Ok(Charges{ net: rp(10000), service: rp(0), tax: rp(1100), gross: rp(11100) }));
+        assert_eq!(rules.charges("beras", rp(5000)), Ok(Charges::untaxed(rp(5000))));
+
+        let charges = rules.charges("minuman", rp(5000)).unwrap();
+        assert_eq!(charges.net.checked_add(charges.service).unwrap().checked_add(charges.tax), Ok(charges.gross));
+
+        assert_eq!(TaxRules::new().charges("minuman", rp(5000)), Ok(Charges::untaxed(rp(5000))));
+        assert_eq!(rules.charges("", rp(90_000_000_000_000_000)), Err(MoneyError::Overflow));
+    }
+}